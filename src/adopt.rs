@@ -0,0 +1,64 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{get_trees_dir, Config, FileEntry, LinkType, CONFIG_DIR, CONFIG_FILE};
+use crate::link::same_contents;
+use crate::metadata::Metadata;
+use crate::vcs;
+use crate::worktree::{list_untracked_paths, list_worktrees};
+
+/// Whether the config is gone while the trees dir still holds worktrees, e.g. after the
+/// config was deleted by hand.
+pub fn config_missing(project_root: &Path) -> bool {
+    let config_path = project_root.join(CONFIG_DIR).join(CONFIG_FILE);
+    let has_trees = fs::read_dir(get_trees_dir(project_root))
+        .is_ok_and(|mut entries| entries.next().is_some());
+    !config_path.exists() && has_trees
+}
+
+/// Rebuild a config from the existing worktrees: the root's branch becomes the main
+/// branch, and files the worktrees symlink to, or hold identical copies of, become file
+/// entries. Every worktree gets a metadata entry.
+pub fn adopt_existing(project_root: &Path) -> Result<Config> {
+    let mut config = Config::default();
+    let root_branch = vcs::open(project_root).current_branch()?;
+    if !root_branch.is_empty() {
+        config.main_branch = root_branch;
+    }
+
+    let worktrees = list_worktrees(project_root)?;
+    for worktree in &worktrees {
+        for path in list_untracked_paths(&worktree.path)? {
+            if config.files.iter().any(|entry| entry.path == path) {
+                continue;
+            }
+            let src = project_root.join(&path);
+            let dst = worktree.path.join(&path);
+            let link_type = if fs::read_link(&dst).is_ok_and(|target| target == src) {
+                LinkType::Symlink
+            } else if src.is_file() && dst.is_file() && same_contents(&src, &dst).unwrap_or(false) {
+                LinkType::Copy
+            } else {
+                continue;
+            };
+            config.files.push(FileEntry {
+                path,
+                link_type,
+                secret: None,
+            });
+        }
+    }
+    config.files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut metadata = Metadata::load(project_root)?;
+    for worktree in &worktrees {
+        if !worktree.branch.is_empty() {
+            metadata.entry(&worktree.name).branch = Some(worktree.branch.clone());
+        }
+        metadata.record_operation(&worktree.name, "adopt");
+    }
+    metadata.save(project_root)?;
+
+    Ok(config)
+}
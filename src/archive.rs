@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{get_archive_dir, get_state_dir, Config};
+use crate::git;
+use crate::trace;
+use crate::worktree::{unsaved_changes, Worktree};
+
+/// Name of the diff of tracked files against HEAD inside an archive.
+const PATCH_FILE: &str = "changes.patch";
+
+/// Save what removing a worktree would lose into `.epi/archive/<name>-<date>.tar.gz`: the
+/// changed and untracked files as they are, and `changes.patch`, the diff of tracked
+/// files against HEAD, which also records deletions. Returns None when there is nothing
+/// to save.
+pub fn archive_worktree(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+) -> Result<Option<PathBuf>> {
+    let changes = unsaved_changes(project_root, worktree, config)?;
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    let (name, worktree_path) = (&worktree.name, &worktree.path);
+
+    let diff = git::output(worktree_path, &["diff", "HEAD", "--binary"])?;
+    if !diff.status.success() {
+        anyhow::bail!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&diff.stderr).trim()
+        );
+    }
+    let staging = get_state_dir(project_root).join(format!("archive-{}", name));
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+    fs::write(staging.join(PATCH_FILE), &diff.stdout)
+        .with_context(|| format!("Failed to write {}", PATCH_FILE))?;
+
+    let archive_dir = get_archive_dir(project_root);
+    fs::create_dir_all(&archive_dir)
+        .with_context(|| format!("Failed to create {}", archive_dir.display()))?;
+    // Archives hold work in progress, possibly secrets, never meant to be committed
+    fs::write(archive_dir.join(".gitignore"), "*\n")
+        .with_context(|| format!("Failed to write {}/.gitignore", archive_dir.display()))?;
+    let archive = archive_dir.join(format!("{}-{}.tar.gz", name, timestamp()));
+
+    let mut command = Command::new("tar");
+    command
+        .arg("-czf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&staging)
+        .arg(PATCH_FILE)
+        .arg("-C")
+        .arg(worktree_path)
+        // Deleted files are only in the patch; `./` keeps names from passing for options
+        .args(
+            changes
+                .iter()
+                .filter(|path| worktree_path.join(path).symlink_metadata().is_ok())
+                .map(|path| format!("./{}", path)),
+        );
+    let status = trace::status(&mut command).context("Failed to run tar");
+    let _ = fs::remove_dir_all(&staging);
+    if !status?.success() {
+        let _ = fs::remove_file(&archive);
+        anyhow::bail!("tar failed to archive worktree '{}'", name);
+    }
+    Ok(Some(archive))
+}
+
+/// The current UTC time as `YYYYMMDD-HHMMSS`.
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format_timestamp(seconds)
+}
+
+/// Seconds since the epoch as a UTC `YYYYMMDD-HHMMSS`.
+fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since the epoch, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_timestamp_at_epoch() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+    }
+
+    #[test]
+    fn format_timestamp_around_leap_days() {
+        assert_eq!(format_timestamp(951_782_400), "20000229-000000");
+        assert_eq!(format_timestamp(1_709_251_199), "20240229-235959");
+        assert_eq!(format_timestamp(1_709_251_200), "20240301-000000");
+    }
+}
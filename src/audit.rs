@@ -0,0 +1,125 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::config::Config;
+use crate::git;
+use crate::worktree::list_worktrees;
+
+pub struct AuditFinding {
+    pub location: String,
+    pub issue: String,
+}
+
+/// Check the repository and its worktrees for risky setups: committed symlinks that
+/// escape their checkout, configured paths that resolve outside the project root and
+/// hook commands that look dangerous.
+pub fn audit(project_root: &Path, config: &Config) -> Result<Vec<AuditFinding>> {
+    let mut findings = Vec::new();
+
+    for (name, command) in config.hooks.commands() {
+        if let Some(reason) = dangerous_command_reason(command) {
+            findings.push(AuditFinding {
+                location: format!("hook: {}", name),
+                issue: format!("{}: {}", reason, command),
+            });
+        }
+    }
+
+    for entry in &config.files {
+        if !is_within(project_root, &project_root.join(&entry.path)) {
+            findings.push(AuditFinding {
+                location: format!("config: {}", entry.path),
+                issue: "file entry resolves outside the project root".to_string(),
+            });
+        }
+    }
+
+    let mut checkouts = vec![project_root.to_path_buf()];
+    checkouts.extend(list_worktrees(project_root)?.into_iter().map(|wt| wt.path));
+    for checkout in checkouts {
+        for link in tracked_symlinks(&checkout)? {
+            let link_path = checkout.join(&link);
+            let target = match fs::read_link(&link_path) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            let resolved = link_path
+                .parent()
+                .map(|parent| parent.join(&target))
+                .unwrap_or_else(|| target.clone());
+            if !is_within(&checkout, &resolved) {
+                findings.push(AuditFinding {
+                    location: link_path.display().to_string(),
+                    issue: format!(
+                        "committed symlink points outside its checkout: {}",
+                        target.display()
+                    ),
+                });
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+fn dangerous_command_reason(command: &str) -> Option<&'static str> {
+    let pipes_to_shell = command.split('|').skip(1).any(|stage| {
+        matches!(
+            stage.split_whitespace().next(),
+            Some("sh" | "bash" | "zsh" | "sudo")
+        )
+    });
+    let fetches = command.contains("curl") || command.contains("wget");
+
+    if fetches && pipes_to_shell {
+        Some("pipes a download into a shell")
+    } else if command.split_whitespace().any(|word| word == "sudo") {
+        Some("runs sudo")
+    } else if command.contains("rm -rf /") || command.contains("rm -rf ~") {
+        Some("deletes outside the worktree")
+    } else if command.contains("base64") && pipes_to_shell {
+        Some("executes decoded content")
+    } else if command.split_whitespace().any(|word| word == "eval") {
+        Some("uses eval")
+    } else {
+        None
+    }
+}
+
+fn tracked_symlinks(checkout: &Path) -> Result<Vec<String>> {
+    let output = git::output(checkout, &["ls-files", "-s"])?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git ls-files failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    // Lines look like "<mode> <object> <stage>\t<path>"; symlinks have mode 120000
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .filter(|line| line.starts_with("120000 "))
+        .filter_map(|line| line.split_once('\t').map(|(_, path)| path.to_string()))
+        .collect())
+}
+
+/// Whether `path` stays inside `root` once `.` and `..` components are resolved lexically.
+fn is_within(root: &Path, path: &Path) -> bool {
+    normalize(path).starts_with(normalize(root))
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
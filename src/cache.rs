@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{get_state_dir, is_read_only};
+
+const CACHE_DIR: &str = "cache";
+
+/// How long a cached answer is reused before asking again.
+const TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Answers of lookups that are slow or rate limited, like pull request queries to `gh`,
+/// kept under `.epi/state/cache` as one file per key and reused while younger than the
+/// TTL. Failing to read or write the cache only means asking again. Read-only projects
+/// are never written to.
+pub struct Cache {
+    dir: PathBuf,
+    refresh: bool,
+    writable: bool,
+}
+
+impl Cache {
+    /// With `refresh`, cached answers are ignored and replaced by fresh ones.
+    pub fn new(project_root: &Path, refresh: bool) -> Self {
+        Cache {
+            dir: get_state_dir(project_root).join(CACHE_DIR),
+            refresh,
+            writable: !is_read_only(project_root),
+        }
+    }
+
+    /// The cached answer for `key`, or else the answer of `lookup`, cached when it has one.
+    pub fn get_or(&self, key: &str, lookup: impl FnOnce() -> Option<String>) -> Option<String> {
+        // Keys may contain branch names, and their slashes aren't subdirectories
+        let path = self.dir.join(key.replace('%', "%25").replace('/', "%2F"));
+        if !self.refresh {
+            if let Some(value) = Self::fresh(&path) {
+                return Some(value);
+            }
+        }
+        let value = lookup()?;
+        if self.writable && fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(&path, &value);
+        }
+        Some(value)
+    }
+
+    fn fresh(path: &Path) -> Option<String> {
+        let age = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > TTL {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+}
@@ -1,22 +1,115 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::git;
+use crate::perms;
+use crate::plugin;
+
 pub const CONFIG_DIR: &str = ".epi";
 pub const CONFIG_FILE: &str = "config.toml";
 pub const TREES_DIR: &str = "trees";
+pub const STATE_DIR: &str = "state";
+pub const STORE_DIR: &str = "store";
+pub const TEMPLATES_DIR: &str = "templates";
+pub const ARCHIVE_DIR: &str = "archive";
+const CONFIG_LOCK_FILE: &str = "config.lock";
+const UPDATE_ATTEMPTS: usize = 5;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LinkType {
     Copy,
+    #[default]
     Symlink,
+    /// Rendered per worktree from `.epi/templates/<path>.tmpl`
+    Template,
+    /// Fetched from the secret manager URI in `secret`, never stored in the root
+    Secret,
+    /// Any other name: created by the `epi-<name>` plugin
+    #[serde(untagged)]
+    Plugin(String),
 }
 
-impl Default for LinkType {
-    fn default() -> Self {
-        LinkType::Symlink
+impl LinkType {
+    /// The name used in the config.
+    pub fn name(&self) -> &str {
+        match self {
+            LinkType::Copy => "copy",
+            LinkType::Symlink => "symlink",
+            LinkType::Template => "template",
+            LinkType::Secret => "secret",
+            LinkType::Plugin(name) => name,
+        }
+    }
+
+    pub fn plugin(&self) -> Option<&str> {
+        match self {
+            LinkType::Plugin(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether entries of this type are made from a file that must exist.
+    pub fn needs_source(&self) -> bool {
+        !matches!(self, LinkType::Secret | LinkType::Plugin(_))
+    }
+}
+
+/// Parses the names used in the config; any other name is a plugin.
+impl std::str::FromStr for LinkType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "copy" => LinkType::Copy,
+            "symlink" => LinkType::Symlink,
+            "template" => LinkType::Template,
+            "secret" => LinkType::Secret,
+            _ => LinkType::Plugin(name.to_string()),
+        })
+    }
+}
+
+/// Picks the link type of files added without `--copy` or `--symlink` from their path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkRule {
+    /// Glob matched against the path, or only its file name when it has no `/`
+    pub pattern: String,
+    #[serde(alias = "type")]
+    pub link_type: LinkType,
+}
+
+impl LinkRule {
+    pub fn matches(&self, path: &str) -> bool {
+        let subject = match self.pattern.contains('/') {
+            true => path,
+            false => path.rsplit('/').next().unwrap_or(path),
+        };
+        glob_match(self.pattern.as_bytes(), subject.as_bytes())
+    }
+}
+
+/// Match `path` against `pattern`, where `*` and `?` don't cross a `/` and `**` does.
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => match rest {
+            // `**/` also matches no directory at all
+            [b'/', rest @ ..] => (0..=path.len())
+                .filter(|&i| i == 0 || path[i - 1] == b'/')
+                .any(|i| glob_match(rest, &path[i..])),
+            _ => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        },
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_match(rest, &path[i..])),
+        [b'?', rest @ ..] => {
+            path.first().is_some_and(|&c| c != b'/') && glob_match(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
     }
 }
 
@@ -25,6 +118,290 @@ pub struct FileEntry {
     pub path: String,
     #[serde(default)]
     pub link_type: LinkType,
+    /// Secret manager URI for `secret` entries, e.g. `op://vault/item/field`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl FileEntry {
+    /// Where the entry's contents come from: the file in the project root, or its
+    /// template.
+    pub fn source(&self, project_root: &Path) -> PathBuf {
+        match self.link_type {
+            LinkType::Template => {
+                get_templates_dir(project_root).join(format!("{}.tmpl", self.path))
+            }
+            LinkType::Copy | LinkType::Symlink | LinkType::Secret | LinkType::Plugin(_) => {
+                project_root.join(&self.path)
+            }
+        }
+    }
+}
+
+/// How file contents are copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyStrategy {
+    /// Let the kernel copy (copy_file_range or sendfile on Linux)
+    #[default]
+    Kernel,
+    /// Read and write through a buffer of `buffer_size` bytes
+    Buffered,
+    /// Like buffered, but keep holes of sparse files
+    Sparse,
+}
+
+/// How copy entries are materialized in worktrees.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CopyConfig {
+    /// Keep one read-only copy of each file under .epi/store and hardlink it into worktrees
+    #[serde(default)]
+    pub dedup: bool,
+    #[serde(default)]
+    pub strategy: CopyStrategy,
+    /// Buffer size in bytes for the buffered and sparse strategies
+    #[serde(default = "default_copy_buffer_size")]
+    pub buffer_size: usize,
+    /// Ask before adding a worktree whose copy entries add up to more bytes than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_above: Option<u64>,
+    /// CPU and I/O priority of `relink --background`
+    #[serde(default)]
+    pub background_priority: BackgroundPriority,
+}
+
+/// How much background relinks, e.g. from the git hooks of `install-hooks`, yield to
+/// the user's foreground work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundPriority {
+    /// Leave the priority alone
+    Normal,
+    /// Nice 10, and the lowest best-effort I/O priority (throttled I/O on macOS)
+    #[default]
+    Low,
+    /// Nice 19, and disk time only when nobody else uses it (throttled I/O on macOS)
+    Idle,
+}
+
+fn default_copy_buffer_size() -> usize {
+    1024 * 1024
+}
+
+impl Default for CopyConfig {
+    fn default() -> Self {
+        Self {
+            dedup: false,
+            strategy: CopyStrategy::default(),
+            buffer_size: default_copy_buffer_size(),
+            confirm_above: None,
+            background_priority: BackgroundPriority::default(),
+        }
+    }
+}
+
+impl CopyConfig {
+    pub fn is_default(&self) -> bool {
+        *self == CopyConfig::default()
+    }
+}
+
+/// Modes for what epiphyte creates, for shared machines where the defaults are too open.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PermissionsConfig {
+    /// Octal mode for directories epiphyte creates, e.g. "0700"
+    #[serde(default, with = "octal_mode", skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<u32>,
+    /// Octal mode for files copied into worktrees, e.g. "0600"
+    #[serde(default, with = "octal_mode", skip_serializing_if = "Option::is_none")]
+    pub file_mode: Option<u32>,
+}
+
+impl PermissionsConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == PermissionsConfig::default()
+    }
+}
+
+/// Modes written as octal strings ("0700") so they read the same as in `chmod`.
+mod octal_mode {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match mode {
+            Some(mode) => serializer.serialize_str(&format!("{:04o}", mode)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let Some(value) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        u32::from_str_radix(value.trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid octal mode '{}'", value)))
+    }
+}
+
+/// A checkout of this repository on another machine, reached over SSH (experimental).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// SSH destination, e.g. `devbox` or `me@devbox`
+    pub host: String,
+    /// Path of the repository root on the remote host
+    pub path: String,
+    /// Command that runs epiphyte on the remote host, interpreted by the remote shell
+    #[serde(default = "default_remote_command")]
+    pub command: String,
+}
+
+fn default_remote_command() -> String {
+    "epiphyte".to_string()
+}
+
+/// Generate a dev container config in every new worktree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DevcontainerConfig {
+    /// devcontainer.json in the project root used as the template
+    #[serde(default = "default_devcontainer_template")]
+    pub template: String,
+    /// Container engine CLI, e.g. `docker` or `podman`
+    #[serde(default = "default_container_engine")]
+    pub engine: String,
+}
+
+fn default_devcontainer_template() -> String {
+    ".devcontainer/devcontainer.json".to_string()
+}
+
+fn default_container_engine() -> String {
+    "docker".to_string()
+}
+
+/// Where worktree lifecycle events are delivered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// URL the JSON event is POSTed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    /// Command run through `sh -c` with the JSON event on stdin and EPI_EVENT set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Plugins (`epi-<name>` on PATH) sent every event as JSON on stdin
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<String>,
+}
+
+/// A worktree declared in the config, created by `apply` when missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// A hook, written as a plain command or as a table when it needs options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    Command(String),
+    Options {
+        command: String,
+        /// Never run two instances of this hook at once, across epiphyte processes
+        #[serde(default)]
+        serialize: bool,
+        /// Run only for worktrees created with one of these profiles (`add --profile`)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        only_profiles: Vec<String>,
+        /// Run only for worktrees with one of these tags (`add --tag`)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        only_tags: Vec<String>,
+    },
+}
+
+impl Hook {
+    pub fn command(&self) -> &str {
+        match self {
+            Hook::Command(command) | Hook::Options { command, .. } => command,
+        }
+    }
+
+    pub fn serialize(&self) -> bool {
+        matches!(self, Hook::Options { serialize: true, .. })
+    }
+
+    /// Whether the hook runs for a worktree with this profile and these tags.
+    pub fn applies_to(&self, profile: Option<&str>, tags: &[String]) -> bool {
+        let Hook::Options {
+            only_profiles,
+            only_tags,
+            ..
+        } = self
+        else {
+            return true;
+        };
+        (only_profiles.is_empty()
+            || profile.is_some_and(|profile| only_profiles.iter().any(|p| p == profile)))
+            && (only_tags.is_empty() || tags.iter().any(|tag| only_tags.contains(tag)))
+    }
+}
+
+/// Shell commands run at points of the worktree lifecycle.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Runs in the project root before a worktree is created; a non-zero exit aborts creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_create: Option<Hook>,
+    /// Runs in the new worktree after files are linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_create: Option<Hook>,
+    /// Most hooks of this project running at once, across epiphyte processes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    /// Seconds after which a running hook is killed and counted as failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl HooksConfig {
+    pub fn is_empty(&self) -> bool {
+        self.pre_create.is_none()
+            && self.post_create.is_none()
+            && self.max_concurrent.is_none()
+            && self.timeout.is_none()
+    }
+
+    /// Drop every hook, e.g. for `--no-hooks`.
+    pub fn disable(&mut self) {
+        self.pre_create = None;
+        self.post_create = None;
+    }
+
+    /// All configured hooks as (name, command) pairs.
+    pub fn commands(&self) -> Vec<(&'static str, &str)> {
+        let mut commands = Vec::new();
+        if let Some(hook) = &self.pre_create {
+            commands.push(("pre_create", hook.command()));
+        }
+        if let Some(hook) = &self.post_create {
+            commands.push(("post_create", hook.command()));
+        }
+        commands
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,6 +410,69 @@ pub struct Config {
     pub main_branch: String,
     #[serde(default)]
     pub files: Vec<FileEntry>,
+    /// Link types for `files add` by path; the first matching rule wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub link_rules: Vec<LinkRule>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Print a summary of the worktree's state when entering it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub motd: bool,
+    /// Command `enter` runs through `sh -c` instead of $SHELL, e.g. `nix develop`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enter_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub worktrees: Vec<WorktreeSpec>,
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    pub hooks: HooksConfig,
+    #[serde(default, skip_serializing_if = "CopyConfig::is_default")]
+    pub copy: CopyConfig,
+    #[serde(default, skip_serializing_if = "PermissionsConfig::is_empty")]
+    pub permissions: PermissionsConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub devcontainer: Option<DevcontainerConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<NotifyConfig>,
+    /// Named shell commands runnable in every worktree with `epi each <name>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub each: BTreeMap<String, String>,
+    /// Exit non-zero when a command reported warnings, like `--strict`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    /// Directories in .epi/trees that epiphyte leaves alone in listings and bulk operations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unmanaged: Vec<String>,
+    /// Where new worktrees are created
+    #[serde(default, skip_serializing_if = "Layout::is_nested")]
+    pub layout: Layout,
+    /// Create an ignored .scratch directory in every new worktree
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub scratch: bool,
+    /// Have git record conflict resolutions in the repository's shared rr-cache and
+    /// replay them during `sync`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rerere: bool,
+}
+
+/// Where new worktrees are created. Worktrees are found again through their metadata,
+/// so changing the layout leaves existing worktrees where they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// In `.epi/trees/<name>`, inside the main checkout
+    #[default]
+    Nested,
+    /// Next to the main checkout, in `../<repo>--<name>`, for tools that index
+    /// everything below the repository
+    Sibling,
+}
+
+impl Layout {
+    fn is_nested(&self) -> bool {
+        *self == Layout::Nested
+    }
 }
 
 fn default_main_branch() -> String {
@@ -44,26 +484,153 @@ impl Default for Config {
         Self {
             main_branch: default_main_branch(),
             files: Vec::new(),
+            link_rules: Vec::new(),
+            env: BTreeMap::new(),
+            motd: false,
+            enter_command: None,
+            worktrees: Vec::new(),
+            hooks: HooksConfig::default(),
+            copy: CopyConfig::default(),
+            permissions: PermissionsConfig::default(),
+            remote: None,
+            devcontainer: None,
+            notify: None,
+            each: BTreeMap::new(),
+            strict: false,
+            unmanaged: Vec::new(),
+            layout: Layout::Nested,
+            scratch: false,
+            rerere: false,
         }
     }
 }
 
 impl Config {
+    /// Every command of the config epiphyte may run, keyed by where it is configured.
+    pub fn commands(&self) -> Vec<(String, String)> {
+        let mut commands: Vec<(String, String)> = self
+            .hooks
+            .commands()
+            .into_iter()
+            .map(|(name, command)| (format!("hooks.{}", name), command.to_string()))
+            .collect();
+        if let Some(command) = &self.enter_command {
+            commands.push(("enter_command".to_string(), command.clone()));
+        }
+        if let Some(notify) = &self.notify {
+            if let Some(command) = &notify.command {
+                commands.push(("notify.command".to_string(), command.clone()));
+            }
+            for name in &notify.plugins {
+                commands.push((format!("notify.plugins.{}", name), plugin::executable(name)));
+            }
+        }
+        for (name, script) in &self.each {
+            commands.push((format!("each.{}", name), script.clone()));
+        }
+        for entry in &self.files {
+            if let Some(name) = entry.link_type.plugin() {
+                commands.push((format!("files.{}", entry.path), plugin::executable(name)));
+            }
+        }
+        commands
+    }
+
+    /// Where a new worktree named `name` goes under the configured layout.
+    pub fn worktree_dir(&self, project_root: &Path, name: &str) -> PathBuf {
+        match self.layout {
+            Layout::Nested => get_trees_dir(project_root).join(name),
+            Layout::Sibling => get_sibling_dir(project_root, name),
+        }
+    }
+
+    /// The configured remote checkout, for commands run with `--remote`.
+    pub fn remote(&self) -> Result<&RemoteConfig> {
+        self.remote.as_ref().with_context(|| {
+            format!("No [remote] section in {}/{}", CONFIG_DIR, CONFIG_FILE)
+        })
+    }
+
     pub fn load(project_root: &Path) -> Result<Self> {
+        Self::parse(project_root, Self::read(project_root)?.as_deref())
+    }
+
+    fn read(project_root: &Path) -> Result<Option<String>> {
         let config_path = project_root.join(CONFIG_DIR).join(CONFIG_FILE);
         if !config_path.exists() {
-            return Ok(Config::default());
+            return Ok(None);
         }
         let content = fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
-        let config: Config = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
-        Ok(config)
+        Ok(Some(content))
     }
 
-    pub fn save(&self, project_root: &Path) -> Result<()> {
+    fn parse(project_root: &Path, content: Option<&str>) -> Result<Self> {
+        let Some(content) = content else {
+            return Ok(Config::default());
+        };
+        let config_path = project_root.join(CONFIG_DIR).join(CONFIG_FILE);
+        toml::from_str(content)
+            .with_context(|| format!("Failed to parse config file: {}", config_path.display()))
+    }
+
+    /// Load the config, apply `change` to it and save it, holding a lock shared by every
+    /// epiphyte process of the project. When something else, like an editor, wrote the
+    /// file in the meantime, `change` is applied again on top of what it wrote instead
+    /// of overwriting it.
+    pub fn update<T>(
+        project_root: &Path,
+        mut change: impl FnMut(&mut Config) -> Result<T>,
+    ) -> Result<(Config, T)> {
+        let state_dir = get_state_dir(project_root);
+        fs::create_dir_all(&state_dir)
+            .with_context(|| format!("Failed to create state dir: {}", state_dir.display()))?;
+        let lock_path = state_dir.join(CONFIG_LOCK_FILE);
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file: {}", lock_path.display()))?;
+        // Released when the file is closed
+        lock.lock().context("Failed to lock the config")?;
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            let before = Self::read(project_root)?;
+            let mut config = Self::parse(project_root, before.as_deref())?;
+            let value = change(&mut config)?;
+            if Self::read(project_root)? != before {
+                continue;
+            }
+            config.save(project_root)?;
+            return Ok((config, value));
+        }
+        anyhow::bail!(
+            "{}/{} kept changing while saving it; try again",
+            CONFIG_DIR,
+            CONFIG_FILE
+        )
+    }
+
+    /// The link type of the first rule matching `path`, symlink when none does.
+    pub fn link_type_for(&self, path: &str) -> Result<LinkType> {
+        let Some(rule) = self.link_rules.iter().find(|rule| rule.matches(path)) else {
+            return Ok(LinkType::Symlink);
+        };
+        if matches!(rule.link_type, LinkType::Template | LinkType::Secret) {
+            anyhow::bail!(
+                "Link rule '{}' picks {} entries, which need `files template init` or \
+                `files add --secret`",
+                rule.pattern,
+                rule.link_type.name()
+            );
+        }
+        Ok(rule.link_type.clone())
+    }
+
+    fn save(&self, project_root: &Path) -> Result<()> {
         let config_dir = project_root.join(CONFIG_DIR);
-        fs::create_dir_all(&config_dir)
+        perms::ensure_dir(&config_dir, self.permissions.dir_mode)
             .with_context(|| format!("Failed to create config dir: {}", config_dir.display()))?;
         let config_path = config_dir.join(CONFIG_FILE);
         let content = toml::to_string_pretty(self)
@@ -74,10 +641,151 @@ impl Config {
     }
 }
 
+/// Which operations ask for confirmation before proceeding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmPolicy {
+    /// Confirm destructive operations and overwrites of existing files
+    Always,
+    /// Confirm only operations that delete work
+    #[default]
+    Destructive,
+    /// Prompt only before `remove` discards local changes without --force
+    Never,
+}
+
+/// What to do about a worktree whose directory was deleted, when entering it or with
+/// `doctor --fix`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingWorktreePolicy {
+    /// Ask whether to recreate it, prune it or, when entering, pick another worktree
+    #[default]
+    Ask,
+    /// Check out its branch again at the same place
+    Recreate,
+    /// Forget the worktree
+    Prune,
+    /// Leave it and report the error
+    Error,
+}
+
+/// The kind of change an operation is about to make, used against `ConfirmPolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Overwrite,
+    Destructive,
+    /// Copying more than `copy.confirm_above` bytes
+    LargeCopy,
+    /// Removing a worktree along with its local changes without `--force`, which every
+    /// policy confirms
+    Discard,
+}
+
+impl ConfirmPolicy {
+    pub fn requires_confirmation(self, kind: ChangeKind) -> bool {
+        match self {
+            ConfirmPolicy::Always => true,
+            ConfirmPolicy::Destructive => kind != ChangeKind::Overwrite,
+            ConfirmPolicy::Never => kind == ChangeKind::Discard,
+        }
+    }
+}
+
+/// Per-user settings shared by every repository.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GlobalConfig {
+    /// Command aliases expanded before argument parsing, e.g. `cd = "enter"`
+    #[serde(default)]
+    pub alias: BTreeMap<String, String>,
+    #[serde(default)]
+    pub confirm: ConfirmPolicy,
+    /// Seconds after which a spawned git command is killed; unset waits indefinitely
+    #[serde(default)]
+    pub git_timeout: Option<u64>,
+    /// The git every command runs, instead of the first on PATH
+    #[serde(default)]
+    pub git_binary: Option<PathBuf>,
+    /// Arguments passed to git before every subcommand, e.g. `["-c", "protocol.version=2"]`
+    #[serde(default)]
+    pub git_args: Vec<String>,
+    /// Refuse to run commands other than `init` in repositories without a project config
+    #[serde(default)]
+    pub require_init: bool,
+    /// What to do about a worktree whose directory was deleted
+    #[serde(default)]
+    pub on_missing_worktree: MissingWorktreePolicy,
+}
+
+impl GlobalConfig {
+    pub fn path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("epiphyte").join(CONFIG_FILE))
+    }
+
+    pub fn load() -> Result<Self> {
+        let Some(config_path) = Self::path() else {
+            return Ok(GlobalConfig::default());
+        };
+        if !config_path.exists() {
+            return Ok(GlobalConfig::default());
+        }
+        let content = fs::read_to_string(&config_path).with_context(|| {
+            format!("Failed to read global config file: {}", config_path.display())
+        })?;
+        let config: GlobalConfig = toml::from_str(&content).with_context(|| {
+            format!("Failed to parse global config file: {}", config_path.display())
+        })?;
+        Ok(config)
+    }
+}
+
 pub fn get_trees_dir(project_root: &Path) -> PathBuf {
     project_root.join(CONFIG_DIR).join(TREES_DIR)
 }
 
+/// Where the worktree `name` goes with `layout = "sibling"`.
+pub fn get_sibling_dir(project_root: &Path, name: &str) -> PathBuf {
+    let repo = project_root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    project_root
+        .parent()
+        .unwrap_or(project_root)
+        .join(format!("{}--{}", repo, name))
+}
+
+pub fn get_state_dir(project_root: &Path) -> PathBuf {
+    project_root.join(CONFIG_DIR).join(STATE_DIR)
+}
+
+pub fn get_store_dir(project_root: &Path) -> PathBuf {
+    project_root.join(CONFIG_DIR).join(STORE_DIR)
+}
+
+pub fn get_archive_dir(project_root: &Path) -> PathBuf {
+    project_root.join(CONFIG_DIR).join(ARCHIVE_DIR)
+}
+
+pub fn get_templates_dir(project_root: &Path) -> PathBuf {
+    project_root.join(CONFIG_DIR).join(TEMPLATES_DIR)
+}
+
+/// Whether epiphyte can't write its state for this project, e.g. when inspecting a
+/// checkout owned by another user or on a read-only mount.
+pub fn is_read_only(project_root: &Path) -> bool {
+    let config_dir = project_root.join(CONFIG_DIR);
+    if config_dir.exists() {
+        !perms::is_writable(&config_dir)
+    } else {
+        !perms::is_writable(project_root)
+    }
+}
+
 pub fn find_project_root() -> Result<PathBuf> {
     let current_dir = std::env::current_dir().context("Failed to get current directory")?;
     let mut dir = current_dir.as_path();
@@ -87,22 +795,10 @@ pub fn find_project_root() -> Result<PathBuf> {
         if git_path.exists() {
             // Check if .git is a file (worktree) or directory (main repo)
             if git_path.is_file() {
-                // Worktree: .git file contains "gitdir: /path/to/.git/worktrees/name"
-                let content = fs::read_to_string(&git_path)
-                    .with_context(|| format!("Failed to read .git file: {}", git_path.display()))?;
-                if let Some(gitdir) = content.strip_prefix("gitdir: ") {
-                    let gitdir = gitdir.trim();
-                    // gitdir points to .git/worktrees/<name>, we need the main .git
-                    let gitdir_path = PathBuf::from(gitdir);
-                    // Go up from .git/worktrees/<name> to .git, then to repo root
-                    if let Some(git_main) = gitdir_path.parent().and_then(|p| p.parent()) {
-                        if let Some(repo_root) = git_main.parent() {
-                            return Ok(repo_root.to_path_buf());
-                        }
-                    }
-                }
-                // Fallback: return current dir if we can't parse the gitdir
-                return Ok(dir.to_path_buf());
+                // Linked worktree, or a checkout whose git dir lives elsewhere
+                // (--separate-git-dir, relocated common dir). Ask git for the main
+                // working tree instead of guessing from the gitdir path.
+                return main_worktree_root(dir);
             } else {
                 // Main repo: .git is a directory
                 return Ok(dir.to_path_buf());
@@ -114,3 +810,156 @@ pub fn find_project_root() -> Result<PathBuf> {
         }
     }
 }
+
+fn main_worktree_root(dir: &Path) -> Result<PathBuf> {
+    let output = git::output(
+        dir,
+        &[
+            "rev-parse",
+            "--path-format=absolute",
+            "--git-dir",
+            "--git-common-dir",
+            "--show-toplevel",
+        ],
+    )?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to resolve the git dir for {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(PathBuf::from);
+    let (Some(git_dir), Some(common_dir), Some(toplevel)) =
+        (lines.next(), lines.next(), lines.next())
+    else {
+        anyhow::bail!("Unexpected git rev-parse output for {}", dir.display());
+    };
+
+    // A main checkout whose git dir lives elsewhere (--separate-git-dir)
+    if git_dir == common_dir {
+        return Ok(toplevel);
+    }
+
+    // Linked worktree of a regular repository: the common dir is <root>/.git
+    if common_dir.file_name().is_some_and(|name| name == ".git") {
+        if let Some(root) = common_dir.parent() {
+            return Ok(root.to_path_buf());
+        }
+    }
+
+    if git_config(&common_dir, "core.bare")?.as_deref() == Some("true") {
+        anyhow::bail!(
+            "The git common dir of {} is a bare repository ({}), so there is no main \
+            checkout to hold {}/. Run epiphyte from a repository with a main working tree.",
+            dir.display(),
+            common_dir.display(),
+            CONFIG_DIR
+        );
+    }
+
+    if let Some(worktree) = git_config(&common_dir, "core.worktree")? {
+        return Ok(common_dir.join(worktree));
+    }
+
+    // Relocated common dir: the main checkout of a managed worktree is the ancestor
+    // holding the epiphyte config dir and sharing the same common dir.
+    for ancestor in dir.ancestors().skip(1) {
+        if ancestor.join(CONFIG_DIR).is_dir() && ancestor.join(".git").exists() {
+            return Ok(ancestor.to_path_buf());
+        }
+    }
+
+    anyhow::bail!(
+        "Cannot locate the main checkout of {}: its git common dir {} is outside any \
+        working tree (relocated or separate git dir). Set core.worktree in that git dir \
+        or run epiphyte from the main checkout.",
+        dir.display(),
+        common_dir.display()
+    )
+}
+
+fn git_config(git_dir: &Path, key: &str) -> Result<Option<String>> {
+    let git_dir_str = git_dir.to_string_lossy();
+    let output = git::output(git_dir, &["--git-dir", &git_dir_str, "config", "--get", key])?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str) -> LinkRule {
+        LinkRule {
+            pattern: pattern.to_string(),
+            link_type: LinkType::Copy,
+        }
+    }
+
+    #[test]
+    fn link_rule_without_slash_matches_file_name() {
+        assert!(rule("*.env").matches("config/prod.env"));
+        assert!(rule(".env?").matches(".envx"));
+        assert!(!rule("*.env").matches("config.env/file"));
+    }
+
+    #[test]
+    fn link_rule_with_slash_matches_path() {
+        assert!(rule("config/*.toml").matches("config/app.toml"));
+        assert!(!rule("config/*.toml").matches("config/nested/app.toml"));
+        assert!(!rule("config/*.toml").matches("other/config/app.toml"));
+    }
+
+    #[test]
+    fn glob_double_star_crosses_directories() {
+        assert!(glob_match(b"**/*.key", b"a/b/c.key"));
+        assert!(glob_match(b"**/*.key", b"c.key"));
+        assert!(glob_match(b"secrets/**", b"secrets/a/b"));
+        assert!(!glob_match(b"**/*.key", b"a/b/c.keys"));
+        assert!(!glob_match(b"a?c", b"a/c"));
+    }
+
+    #[test]
+    fn link_type_names_round_trip() {
+        for name in ["copy", "symlink", "template", "secret", "sops"] {
+            assert_eq!(name.parse::<LinkType>().unwrap().name(), name);
+        }
+        assert_eq!("sops".parse::<LinkType>().unwrap().plugin(), Some("sops"));
+    }
+
+    #[test]
+    fn confirm_policies() {
+        use ChangeKind::*;
+        for kind in [Overwrite, Destructive, LargeCopy, Discard] {
+            assert!(ConfirmPolicy::Always.requires_confirmation(kind));
+            assert_eq!(ConfirmPolicy::Destructive.requires_confirmation(kind), kind != Overwrite);
+            assert_eq!(ConfirmPolicy::Never.requires_confirmation(kind), kind == Discard);
+        }
+    }
+
+    #[test]
+    fn octal_modes_round_trip() {
+        let permissions: PermissionsConfig =
+            toml::from_str("dir_mode = \"0700\"\nfile_mode = \"0o640\"").unwrap();
+        assert_eq!(permissions.dir_mode, Some(0o700));
+        assert_eq!(permissions.file_mode, Some(0o640));
+        let written = toml::to_string(&permissions).unwrap();
+        assert_eq!(written, "dir_mode = \"0700\"\nfile_mode = \"0640\"\n");
+    }
+
+    #[test]
+    fn invalid_octal_modes() {
+        for mode in ["0800", "10000", "rwx", ""] {
+            let toml = format!("dir_mode = \"{}\"", mode);
+            assert!(toml::from_str::<PermissionsConfig>(&toml).is_err(), "{}", mode);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::config::{CopyConfig, CopyStrategy};
+
+/// Copy the contents and permissions of `src` to `dst` using the configured strategy.
+pub fn copy_file(src: &Path, dst: &Path, config: &CopyConfig) -> Result<()> {
+    match config.strategy {
+        // std uses copy_file_range, falling back to sendfile, on Linux
+        CopyStrategy::Kernel => {
+            fs::copy(src, dst)?;
+        }
+        CopyStrategy::Buffered => copy_buffered(src, dst, config.buffer_size, false)?,
+        CopyStrategy::Sparse => copy_buffered(src, dst, config.buffer_size, true)?,
+    }
+    Ok(())
+}
+
+/// Copy through a userspace buffer of `buffer_size` bytes. With `sparse`, blocks that
+/// are all zeroes are skipped with a seek so the destination keeps the source's holes.
+fn copy_buffered(src: &Path, dst: &Path, buffer_size: usize, sparse: bool) -> Result<()> {
+    let mut reader =
+        File::open(src).with_context(|| format!("Failed to open {}", src.display()))?;
+    let permissions = reader.metadata()?.permissions();
+    let mut writer =
+        File::create(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+
+    let mut buffer = vec![0u8; buffer_size.max(4096)];
+    let mut length = 0u64;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", src.display()))
+            }
+        };
+        let chunk = &buffer[..read];
+        if sparse && chunk.iter().all(|&byte| byte == 0) {
+            writer.seek(SeekFrom::Current(read as i64))?;
+        } else {
+            writer.write_all(chunk)?;
+        }
+        length += read as u64;
+    }
+
+    // A trailing hole is only materialized by setting the length
+    writer.set_len(length)?;
+    writer.flush()?;
+    fs::set_permissions(dst, permissions)?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::DevcontainerConfig;
+use crate::trace;
+use crate::worktree::Worktree;
+
+/// Where the generated config is written, relative to the worktree.
+pub const GENERATED_CONFIG: &str = ".devcontainer/epi/devcontainer.json";
+
+/// Name of the container for a worktree, unique per repository.
+pub fn container_name(project_root: &Path, worktree: &str) -> String {
+    let project = project_root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = format!("epi-{}-{}", project, worktree);
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "_.-".contains(c) { c } else { '-' })
+        .collect()
+}
+
+/// Write a devcontainer config for `worktree`, based on the template from the project
+/// root, with a per-worktree name, container name and a mount of the repository's git
+/// dir so git works inside the container.
+pub fn write_config(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &DevcontainerConfig,
+) -> Result<PathBuf> {
+    let template_path = project_root.join(&config.template);
+    let content = fs::read_to_string(&template_path).with_context(|| {
+        format!("Failed to read devcontainer template: {}", template_path.display())
+    })?;
+    let mut value: Value = serde_json::from_str(&strip_jsonc(&content)).with_context(|| {
+        format!("Failed to parse devcontainer template: {}", template_path.display())
+    })?;
+    let settings = value.as_object_mut().with_context(|| {
+        format!("Devcontainer template is not an object: {}", template_path.display())
+    })?;
+
+    let container = container_name(project_root, &worktree.name);
+    let base_name = settings
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("dev")
+        .to_string();
+    settings.insert("name".into(), json!(format!("{} ({})", base_name, worktree.name)));
+    settings
+        .entry("workspaceFolder")
+        .or_insert_with(|| json!(format!("/workspaces/{}", worktree.name)));
+    push_all(settings, "runArgs", [json!("--name"), json!(container)]);
+    // A worktree's .git file points at the main repository's git dir by absolute path
+    let git_dir = project_root.join(".git");
+    push_all(
+        settings,
+        "mounts",
+        [json!(format!(
+            "source={0},target={0},type=bind",
+            git_dir.display()
+        ))],
+    );
+
+    let path = worktree.path.join(GENERATED_CONFIG);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create dir: {}", parent.display()))?;
+        // Keep the generated config out of `git status` without touching shared ignores
+        fs::write(parent.join(".gitignore"), "*\n")
+            .with_context(|| format!("Failed to write {}/.gitignore", parent.display()))?;
+    }
+    let output = serde_json::to_string_pretty(&value)?;
+    fs::write(&path, output + "\n")
+        .with_context(|| format!("Failed to write devcontainer config: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Open a login shell in the worktree's running dev container.
+pub fn enter(project_root: &Path, worktree: &Worktree, config: &DevcontainerConfig) -> Result<()> {
+    let container = container_name(project_root, &worktree.name);
+    let generated = worktree.path.join(GENERATED_CONFIG);
+
+    let output = trace::output(
+        Command::new(&config.engine).args(["inspect", "-f", "{{.State.Running}}", &container]),
+    )
+    .with_context(|| format!("Failed to run {} inspect", config.engine))?;
+    if String::from_utf8_lossy(&output.stdout).trim() != "true" {
+        anyhow::bail!(
+            "Container '{}' is not running. Start it with: devcontainer up \
+            --workspace-folder {} --config {}",
+            container,
+            worktree.path.display(),
+            generated.display()
+        );
+    }
+
+    let workspace = fs::read_to_string(&generated)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|value| value.get("workspaceFolder")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("/workspaces/{}", worktree.name));
+
+    trace::status(
+        Command::new(&config.engine)
+            .args(["exec", "-it", "-w", &workspace, &container])
+            .args(["sh", "-c", "exec \"${SHELL:-sh}\" -l"]),
+    )
+    .with_context(|| format!("Failed to run {} exec", config.engine))?;
+    Ok(())
+}
+
+fn push_all<const N: usize>(settings: &mut Map<String, Value>, key: &str, values: [Value; N]) {
+    let entry = settings.entry(key).or_insert_with(|| json!([]));
+    if let Some(array) = entry.as_array_mut() {
+        array.extend(values);
+    }
+}
+
+/// Remove comments and trailing commas, which devcontainer.json allows but JSON does not.
+fn strip_jsonc(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => output.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        output.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            (']' | '}', _) => {
+                let trimmed = output.trim_end().len();
+                if output[..trimmed].ends_with(',') {
+                    output.truncate(trimmed - 1);
+                }
+                output.push(c);
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
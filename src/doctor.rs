@@ -0,0 +1,348 @@
+use anyhow::{Context, Result};
+use inquire::error::InquireError;
+use inquire::Select;
+use serde::Serialize;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::config::{
+    get_sibling_dir, get_trees_dir, Config, MissingWorktreePolicy, CONFIG_DIR,
+};
+use crate::metadata::Metadata;
+use crate::prompt;
+use crate::vcs;
+use crate::worktree::{
+    add_worktree, auto_worktree_name, list_managed_worktrees, list_worktrees, managed_path,
+    managed_path_in, AddOptions,
+};
+
+pub struct Diagnosis {
+    pub entry: String,
+    pub problem: String,
+}
+
+/// A worktree whose branch was renamed after epiphyte recorded it.
+pub struct BranchRename {
+    pub worktree: String,
+    pub recorded: String,
+    pub current: String,
+}
+
+/// Worktrees whose checked-out branch differs from the one in their metadata, e.g. after
+/// `git branch -m`. Detached worktrees and those with no recorded branch are skipped.
+pub fn renamed_branches(project_root: &Path, config: &Config) -> Result<Vec<BranchRename>> {
+    let metadata = Metadata::load(project_root)?;
+    let mut renames = Vec::new();
+    for worktree in list_managed_worktrees(project_root, config)? {
+        let Some(recorded) = metadata.get(&worktree.name).and_then(|meta| meta.branch.clone())
+        else {
+            continue;
+        };
+        if !worktree.branch.is_empty() && worktree.branch != recorded {
+            renames.push(BranchRename {
+                worktree: worktree.name,
+                recorded,
+                current: worktree.branch,
+            });
+        }
+    }
+    Ok(renames)
+}
+
+/// A managed worktree whose directory is gone, e.g. deleted with `rm -rf`.
+pub struct MissingWorktree {
+    pub name: String,
+    pub path: PathBuf,
+    /// Branch to check out when recreating it; empty when unknown
+    pub branch: String,
+}
+
+/// Worktrees git or the metadata still know about whose directory no longer exists.
+pub fn missing_worktrees(project_root: &Path, config: &Config) -> Result<Vec<MissingWorktree>> {
+    let metadata = Metadata::load(project_root)?;
+    let registered = list_managed_worktrees(project_root, config)?;
+    let mut missing: Vec<MissingWorktree> = registered
+        .iter()
+        .filter(|wt| !wt.path.exists())
+        .map(|wt| MissingWorktree {
+            name: wt.name.clone(),
+            path: wt.path.clone(),
+            branch: wt.branch.clone(),
+        })
+        .collect();
+    // Already pruned from git, e.g. by `git worktree prune`, but still in the metadata
+    for (name, meta) in &metadata.worktrees {
+        let path = managed_path_in(project_root, &metadata, name);
+        if !path.exists()
+            && !registered.iter().any(|wt| wt.name == *name)
+            && !config.unmanaged.contains(name)
+        {
+            missing.push(MissingWorktree {
+                name: name.clone(),
+                path,
+                branch: meta.branch.clone().unwrap_or_default(),
+            });
+        }
+    }
+    Ok(missing)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingAction {
+    Recreate,
+    Prune,
+    /// Enter another worktree instead
+    Pick,
+}
+
+/// What to do about `missing` under `policy`, asking when it says so. `can_pick` offers
+/// to pick another worktree. None leaves the worktree alone, as when the user cancels or
+/// there is no terminal to ask on.
+pub fn missing_action(
+    missing: &MissingWorktree,
+    policy: MissingWorktreePolicy,
+    can_pick: bool,
+) -> Result<Option<MissingAction>> {
+    match policy {
+        MissingWorktreePolicy::Recreate => return Ok(Some(MissingAction::Recreate)),
+        MissingWorktreePolicy::Prune => return Ok(Some(MissingAction::Prune)),
+        MissingWorktreePolicy::Error => return Ok(None),
+        MissingWorktreePolicy::Ask => {}
+    }
+
+    let mut actions = vec![
+        ("recreate", "Recreate it from its branch", MissingAction::Recreate),
+        ("prune", "Prune it", MissingAction::Prune),
+    ];
+    if can_pick {
+        actions.push(("pick", "Pick another worktree", MissingAction::Pick));
+    }
+    let names: Vec<String> = actions.iter().map(|(name, ..)| name.to_string()).collect();
+    if let Some(choice) = prompt::env_choice(prompt::MISSING_ACTION, &names)? {
+        return Ok(actions
+            .iter()
+            .find(|(name, ..)| *name == choice)
+            .map(|(.., action)| *action));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    let labels: Vec<&str> = actions.iter().map(|(_, label, _)| *label).collect();
+    let message = format!(
+        "The directory of worktree '{}' is gone: {}",
+        missing.name,
+        missing.path.display()
+    );
+    match Select::new(&message, labels).prompt() {
+        Ok(choice) => Ok(actions
+            .iter()
+            .find(|(_, label, _)| *label == choice)
+            .map(|(.., action)| *action)),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(None),
+        Err(err) => Err(err).context("Failed to prompt for the missing worktree"),
+    }
+}
+
+/// Check out the worktree's branch again at its place, keeping its metadata.
+pub fn recreate_missing(
+    project_root: &Path,
+    missing: &MissingWorktree,
+    config: &Config,
+) -> Result<()> {
+    let vcs = vcs::open(project_root);
+    vcs.prune_worktrees()?;
+    let branch = Some(missing.branch.as_str())
+        .filter(|branch| !branch.is_empty())
+        .map(|branch| vcs.branch_exists(branch).map(|exists| exists.then_some(branch)))
+        .transpose()?
+        .flatten();
+    add_worktree(
+        project_root,
+        &missing.name,
+        branch,
+        config,
+        &AddOptions::default(),
+    )?;
+    Ok(())
+}
+
+/// Drop the worktree's registration and metadata.
+pub fn prune_missing(project_root: &Path, missing: &MissingWorktree) -> Result<()> {
+    vcs::open(project_root).prune_worktrees()?;
+    let mut metadata = Metadata::load(project_root)?;
+    metadata.remove(&missing.name);
+    metadata.save(project_root)
+}
+
+/// What `prune` cleaned up, or would clean up with `dry_run`.
+#[derive(Serialize)]
+pub struct PruneReport {
+    /// Worktrees git or the metadata still knew about whose directory was gone or empty
+    pub worktrees: Vec<PathBuf>,
+    /// Directories in the trees dir with nothing but empty directories in them
+    pub empty_dirs: Vec<PathBuf>,
+}
+
+/// Remove the empty directories left in the trees dir, then forget the worktrees whose
+/// directory is gone, in git and in the metadata.
+pub fn prune(project_root: &Path, dry_run: bool) -> Result<PruneReport> {
+    let trees_dir = get_trees_dir(project_root);
+    let mut empty_dirs = Vec::new();
+    if trees_dir.is_dir() {
+        for entry in fs::read_dir(&trees_dir)
+            .with_context(|| format!("Failed to read {}", trees_dir.display()))?
+        {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && is_empty_dir(&entry.path())? {
+                empty_dirs.push(entry.path());
+            }
+        }
+    }
+    empty_dirs.sort();
+    let gone = |path: &Path| !path.exists() || empty_dirs.iter().any(|dir| dir == path);
+
+    let vcs = vcs::open(project_root);
+    let mut worktrees: Vec<PathBuf> = vcs
+        .list_worktrees()?
+        .into_iter()
+        .map(|wt| wt.path)
+        .filter(|path| gone(path))
+        .collect();
+    let mut metadata = Metadata::load(project_root)?;
+    let forgotten: Vec<String> = metadata
+        .worktrees
+        .keys()
+        .filter(|name| gone(&managed_path_in(project_root, &metadata, name)))
+        .cloned()
+        .collect();
+    for name in &forgotten {
+        let path = managed_path_in(project_root, &metadata, name);
+        if !worktrees.contains(&path) {
+            worktrees.push(path);
+        }
+    }
+    let report = PruneReport {
+        worktrees,
+        empty_dirs,
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    for dir in &report.empty_dirs {
+        fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    if !report.worktrees.is_empty() {
+        vcs.prune_worktrees()?;
+    }
+    if !forgotten.is_empty() {
+        for name in &forgotten {
+            metadata.remove(name);
+        }
+        metadata.save(project_root)?;
+    }
+    Ok(report)
+}
+
+/// Whether `dir` holds nothing but, recursively, empty directories. Symlinks count as
+/// content.
+fn is_empty_dir(dir: &Path) -> Result<bool> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() || !is_empty_dir(&entry.path())? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Record the current branch in the worktree's metadata. With `rename_dir`, also move the
+/// worktree to a directory named after the branch. Returns the worktree's name afterwards.
+pub fn repair_branch(
+    project_root: &Path,
+    rename: &BranchRename,
+    rename_dir: bool,
+) -> Result<String> {
+    let mut metadata = Metadata::load(project_root)?;
+    let mut meta = metadata.worktrees.remove(&rename.worktree).unwrap_or_default();
+    meta.branch = Some(rename.current.clone());
+
+    let mut name = rename.worktree.clone();
+    if rename_dir {
+        let new_name = auto_worktree_name(project_root, Some(&rename.current));
+        // Worktrees outside the trees dir stay next to the main checkout
+        let new_path = match &meta.path {
+            Some(_) => get_sibling_dir(project_root, &new_name),
+            None => get_trees_dir(project_root).join(&new_name),
+        };
+        vcs::open(project_root).move_worktree(&managed_path(project_root, &name), &new_path)?;
+        if meta.path.is_some() {
+            meta.path = Some(new_path);
+        }
+        name = new_name;
+    }
+
+    metadata.worktrees.insert(name.clone(), meta);
+    metadata.record_operation(&name, "repair");
+    metadata.save(project_root)?;
+    Ok(name)
+}
+
+/// Check that every configured file resolves against the project root. Entries added
+/// from inside a worktree can name paths that only exist in that worktree, or point
+/// into `.epi` itself.
+pub fn diagnose(project_root: &Path, config: &Config) -> Result<Vec<Diagnosis>> {
+    let mut diagnoses = Vec::new();
+    let trees_dir = get_trees_dir(project_root);
+    let worktrees = list_worktrees(project_root)?;
+
+    for entry in &config.files {
+        let src = project_root.join(&entry.path);
+        if src.starts_with(&trees_dir) {
+            diagnoses.push(Diagnosis {
+                entry: entry.path.clone(),
+                problem: "points into a worktree; paths are relative to the project root"
+                    .to_string(),
+            });
+            continue;
+        }
+        if src.starts_with(project_root.join(CONFIG_DIR)) {
+            diagnoses.push(Diagnosis {
+                entry: entry.path.clone(),
+                problem: format!("points into {}", CONFIG_DIR),
+            });
+            continue;
+        }
+        if entry.source(project_root).exists() || !entry.link_type.needs_source() {
+            continue;
+        }
+
+        // A real file in a worktree, rather than a link to the missing source, means the
+        // entry was written relative to that worktree
+        let found_in: Vec<&str> = worktrees
+            .iter()
+            .filter(|wt| {
+                let dst = wt.path.join(&entry.path);
+                let is_link_to_source = fs::read_link(&dst).is_ok_and(|target| target == src);
+                dst.symlink_metadata().is_ok() && !is_link_to_source
+            })
+            .map(|wt| wt.name.as_str())
+            .collect();
+        let problem = if found_in.is_empty() {
+            "does not exist in the project root".to_string()
+        } else {
+            format!(
+                "does not exist in the project root, only in worktree(s): {}",
+                found_in.join(", ")
+            )
+        };
+        diagnoses.push(Diagnosis {
+            entry: entry.path.clone(),
+            problem,
+        });
+    }
+
+    Ok(diagnoses)
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{get_state_dir, Config, CONFIG_DIR};
+use crate::copy;
+use crate::git;
+use crate::messages;
+use crate::vcs;
+use crate::worktree::{
+    add_worktree, find_worktree, is_root_name, name_taken, AddOptions, AddReport,
+};
+
+/// A worktree created by `duplicate_worktree`, with what it took over from the original.
+#[derive(Serialize)]
+pub struct DuplicateReport {
+    #[serde(flatten)]
+    pub added: AddReport,
+    pub from: String,
+    /// Tracked files whose uncommitted changes were carried over
+    pub changed: Vec<String>,
+    /// Untracked files copied from the original
+    pub untracked: Vec<String>,
+}
+
+/// Create `new_name` on a new branch at the commit `name` is on, then carry over the
+/// uncommitted changes of `name`, as unstaged changes, and copy its untracked files.
+/// Configured files are linked as for any new worktree, not copied.
+pub fn duplicate_worktree(
+    project_root: &Path,
+    name: &str,
+    new_name: &str,
+    config: &Config,
+    mut options: AddOptions,
+) -> Result<DuplicateReport> {
+    if project_root.join(vcs::JJ_DIR).is_dir() {
+        anyhow::bail!("duplicate is not supported for jj workspaces");
+    }
+    if is_root_name(project_root, new_name) {
+        return Err(messages::WORKTREE_RESERVED.error(&[("name", new_name)]));
+    }
+    if name_taken(project_root, new_name) {
+        return Err(messages::WORKTREE_EXISTS.error(&[("name", new_name)]));
+    }
+    let source = find_worktree(project_root, name)?;
+    if !source.path.exists() {
+        anyhow::bail!("Worktree '{}' has no directory at {}", name, source.path.display());
+    }
+    // A branch named after the worktree would be checked out instead of created
+    if vcs::open(project_root).branch_exists(new_name)? {
+        anyhow::bail!("Branch '{}' already exists", new_name);
+    }
+
+    let commit = stdout(&source.path, &["rev-parse", "HEAD"])?;
+    let diff = stdout(&source.path, &["diff", "HEAD", "--binary"])?;
+    let changed = stdout(&source.path, &["diff", "HEAD", "--name-only", "--no-renames", "-z"])?;
+    let untracked = stdout(&source.path, &["ls-files", "-z", "-o", "--exclude-standard"])?;
+    let untracked: Vec<String> = paths(&untracked)
+        .into_iter()
+        .filter(|path| !Path::new(path).starts_with(CONFIG_DIR))
+        .filter(|path| {
+            !config.files.iter().any(|entry| Path::new(path).starts_with(&entry.path))
+        })
+        .collect();
+
+    options.start_point = Some(String::from_utf8_lossy(&commit).trim().to_string());
+    let added = add_worktree(project_root, new_name, None, config, &options)?;
+    let worktree_path = added.worktree.path.clone();
+    let created = |step: &str| {
+        format!("Worktree '{}' was created but {} failed", new_name, step)
+    };
+
+    if !diff.is_empty() {
+        apply_diff(project_root, &worktree_path, new_name, &diff)
+            .with_context(|| created("applying its changes"))?;
+    }
+
+    for path in &untracked {
+        copy_untracked(&source.path.join(path), &worktree_path.join(path), config)
+            .with_context(|| created(&format!("copying {}", path)))?;
+    }
+
+    Ok(DuplicateReport {
+        added,
+        from: source.name,
+        changed: paths(&changed),
+        untracked,
+    })
+}
+
+/// Apply the output of `git diff HEAD --binary` to the files of `worktree_path`.
+fn apply_diff(project_root: &Path, worktree_path: &Path, name: &str, diff: &[u8]) -> Result<()> {
+    let state_dir = get_state_dir(project_root);
+    fs::create_dir_all(&state_dir)
+        .with_context(|| format!("Failed to create {}", state_dir.display()))?;
+    let patch = state_dir.join(format!("duplicate-{}.diff", name));
+    fs::write(&patch, diff).with_context(|| format!("Failed to write {}", patch.display()))?;
+    let output = git::output(worktree_path, &["apply", &patch.to_string_lossy()]);
+    let _ = fs::remove_file(&patch);
+    let output = output?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git apply failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn copy_untracked(src: &Path, dst: &Path, config: &Config) -> Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    if src.symlink_metadata()?.file_type().is_symlink() {
+        let target = fs::read_link(src)?;
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&target, dst)?;
+        }
+        #[cfg(windows)]
+        {
+            // Windows needs to know whether the link points to a directory
+            if src.is_dir() {
+                std::os::windows::fs::symlink_dir(&target, dst)?;
+            } else {
+                std::os::windows::fs::symlink_file(&target, dst)?;
+            }
+        }
+        return Ok(());
+    }
+    copy::copy_file(src, dst, &config.copy)
+}
+
+/// The paths of git's `-z` output, unquoted.
+fn paths(stdout: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn stdout(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = git::output(dir, args)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_splits_on_nul() {
+        assert_eq!(paths(b"a\0b c\0\"quoted\"\0"), ["a", "b c", "\"quoted\""]);
+        assert!(paths(b"").is_empty());
+    }
+}
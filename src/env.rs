@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::metadata::Metadata;
+use crate::store;
+use crate::worktree::Worktree;
+
+/// Build the environment passed to commands epiphyte runs inside a worktree.
+///
+/// The following variables are always set:
+/// - `EPI_ROOT`: absolute path of the main checkout
+/// - `EPI_WORKTREE`: worktree name
+/// - `EPI_WORKTREE_PATH`: absolute path of the worktree
+/// - `EPI_BRANCH`: checked-out branch (empty when detached)
+///
+/// Entries from the config `[env]` section follow, then entries from `env_file`,
+/// so later sources override earlier ones.
+pub fn worktree_env(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+    env_file: Option<&Path>,
+) -> Result<Vec<(String, String)>> {
+    let mut env = vec![
+        ("EPI_ROOT".to_string(), project_root.display().to_string()),
+        ("EPI_WORKTREE".to_string(), worktree.name.clone()),
+        (
+            "EPI_WORKTREE_PATH".to_string(),
+            worktree.path.display().to_string(),
+        ),
+        ("EPI_BRANCH".to_string(), worktree.branch.clone()),
+    ];
+
+    env.extend(
+        config
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+
+    if let Some(path) = env_file {
+        env.extend(load_env_file(path)?);
+    }
+
+    Ok(env)
+}
+
+/// Parse a dotenv-style file: `KEY=VALUE` lines, with blank lines, `#` comments,
+/// an optional `export ` prefix and surrounding quotes on values allowed.
+pub fn load_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+
+    let mut env = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            anyhow::bail!(
+                "Invalid line {} in env file {}: expected KEY=VALUE",
+                index + 1,
+                path.display()
+            );
+        };
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        env.push((key.trim().to_string(), value.to_string()));
+    }
+
+    Ok(env)
+}
+
+/// A value epiphyte provides that differs between two worktrees; `None` when one of them
+/// lacks it.
+#[derive(Serialize)]
+pub struct EnvDifference {
+    /// `var`, `port_slot` or `file`
+    pub kind: &'static str,
+    pub key: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// Compare what epiphyte provides two worktrees: the variables commands get, the port
+/// slot templates render with, and the configured files as they are on disk.
+pub fn diff(
+    project_root: &Path,
+    a: &Worktree,
+    b: &Worktree,
+    config: &Config,
+) -> Result<Vec<EnvDifference>> {
+    let a_values = snapshot(project_root, a, config)?;
+    let mut b_values = snapshot(project_root, b, config)?;
+
+    let mut differences = Vec::new();
+    for ((kind, key), a_value) in a_values {
+        let b_value = b_values.remove(&(kind, key.clone()));
+        if b_value.as_ref() != Some(&a_value) {
+            differences.push(EnvDifference {
+                kind,
+                key,
+                a: Some(a_value),
+                b: b_value,
+            });
+        }
+    }
+    differences.extend(
+        b_values
+            .into_iter()
+            .map(|((kind, key), b_value)| EnvDifference {
+                kind,
+                key,
+                a: None,
+                b: Some(b_value),
+            }),
+    );
+    differences.sort_by(|x, y| (x.kind, &x.key).cmp(&(y.kind, &y.key)));
+    Ok(differences)
+}
+
+fn snapshot(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+) -> Result<BTreeMap<(&'static str, String), String>> {
+    // Later entries override earlier ones, as when the environment is applied
+    let mut values: BTreeMap<_, _> = worktree_env(project_root, worktree, config, None)?
+        .into_iter()
+        .map(|(key, value)| (("var", key), value))
+        .collect();
+
+    let metadata = Metadata::load(project_root)?;
+    if let Some(slot) = metadata.get(&worktree.name).and_then(|meta| meta.port_slot) {
+        values.insert(("port_slot", String::new()), slot.to_string());
+    }
+
+    for entry in &config.files {
+        if let Some(state) = describe_file(&worktree.path.join(&entry.path))? {
+            values.insert(("file", entry.path.clone()), state);
+        }
+    }
+    Ok(values)
+}
+
+/// Where a symlink points, or the size and digest of a file, so that two versions of a
+/// linked file compare equal only when they are the same.
+fn describe_file(path: &Path) -> Result<Option<String>> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(None);
+    };
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)
+            .with_context(|| format!("Failed to read link {}", path.display()))?;
+        return Ok(Some(format!("symlink to {}", target.display())));
+    }
+    if metadata.is_dir() {
+        return Ok(Some("directory".to_string()));
+    }
+    let digest = store::hash_file(path)?;
+    Ok(Some(format!("{} bytes, sha256 {}", metadata.len(), &digest[..12])))
+}
@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::io;
+
+/// Broad cause of a failed file operation, to group failures and suggest a remedy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    PermissionDenied,
+    /// The filesystem can't create the symlink, hardlink or clone
+    Unsupported,
+    NoSpace,
+    Other,
+}
+
+impl FailureKind {
+    /// Classify by the first I/O error in the chain of `err`.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<io::Error>())
+            .map_or(FailureKind::Other, FailureKind::of_io)
+    }
+
+    pub fn of_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => FailureKind::PermissionDenied,
+            io::ErrorKind::Unsupported => FailureKind::Unsupported,
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => FailureKind::NoSpace,
+            #[cfg(unix)]
+            _ if err.raw_os_error() == Some(libc::EOPNOTSUPP) => FailureKind::Unsupported,
+            _ => FailureKind::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FailureKind::PermissionDenied => "permission denied",
+            FailureKind::Unsupported => "not supported by the filesystem",
+            FailureKind::NoSpace => "no space left",
+            FailureKind::Other => "other errors",
+        }
+    }
+
+    /// What to do about failures of this kind, `retry` being the command that redoes the
+    /// operation.
+    pub fn hint(self, retry: &str) -> String {
+        match self {
+            FailureKind::PermissionDenied => format!(
+                "check the ownership and permissions of these paths, then run `{}`",
+                retry
+            ),
+            FailureKind::Unsupported => format!(
+                "run `epiphyte bench-io` so links fall back to what the filesystem supports, \
+                then `{}`",
+                retry
+            ),
+            FailureKind::NoSpace => format!("free up disk space, then run `{}`", retry),
+            FailureKind::Other => "run `epiphyte doctor` to check the worktrees".to_string(),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::{Config, GlobalConfig, CONFIG_DIR, CONFIG_FILE};
+use crate::vcs;
+
+/// Projects already shown the `init` hint, one root per line, next to the global config
+const HINTED_FILE: &str = "init-hinted";
+
+/// Whether the project has no config, so commands fall back to the defaults.
+pub fn uninitialized(project_root: &Path) -> bool {
+    !project_root.join(CONFIG_DIR).join(CONFIG_FILE).exists()
+}
+
+/// Suggest `epiphyte init` the first time a command runs in an uninitialized project.
+pub fn hint_init(project_root: &Path) {
+    let hinted_path = GlobalConfig::path().and_then(|path| Some(path.parent()?.join(HINTED_FILE)));
+    let root = project_root.to_string_lossy();
+    if let Some(path) = &hinted_path {
+        let hinted = fs::read_to_string(path).unwrap_or_default();
+        if hinted.lines().any(|line| line == root) {
+            return;
+        }
+    }
+
+    let main_branch = Config::default().main_branch;
+    eprintln!(
+        "Hint: {} has no {}/{}, so epiphyte uses the defaults, e.g. main_branch = \"{}\".",
+        project_root.display(),
+        CONFIG_DIR,
+        CONFIG_FILE,
+        main_branch
+    );
+    if !vcs::open(project_root)
+        .branch_exists(&main_branch)
+        .unwrap_or(true)
+    {
+        eprintln!("      This repository has no '{}' branch.", main_branch);
+    }
+    eprintln!("      Run `epiphyte init` to set up the project. This hint is shown once.");
+
+    // Shown again next time when the global config dir isn't writable
+    if let Some(path) = hinted_path {
+        let _ = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::OpenOptions::new().create(true).append(true).open(&path))
+            .and_then(|mut file| writeln!(file, "{}", root));
+    }
+}
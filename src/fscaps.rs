@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::config::get_state_dir;
+
+const CAPABILITIES_FILE: &str = "fs-capabilities.toml";
+
+/// What the filesystem holding `.epi` supports, as found by `bench-io`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FsCapabilities {
+    pub symlinks: bool,
+    /// Copy-on-write clones of files (FICLONE on Linux)
+    pub reflinks: bool,
+    /// Hardlinks between different directories
+    pub hardlinks: bool,
+    pub case_sensitive: bool,
+}
+
+impl FsCapabilities {
+    /// The capabilities cached by the last probe, if any.
+    pub fn load(project_root: &Path) -> Option<Self> {
+        let path = get_state_dir(project_root).join(CAPABILITIES_FILE);
+        let content = fs::read_to_string(path).ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    fn save(&self, project_root: &Path) -> Result<()> {
+        let path = get_state_dir(project_root).join(CAPABILITIES_FILE);
+        let content = toml::to_string_pretty(self).context("Failed to serialize capabilities")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Try each capability in a scratch directory under `.epi/state` and cache the results
+/// for `link_entry`.
+pub fn probe(project_root: &Path) -> Result<FsCapabilities> {
+    let scratch = get_state_dir(project_root).join(format!("probe-{}", std::process::id()));
+    let result = probe_in(&scratch);
+    let _ = fs::remove_dir_all(&scratch);
+    let capabilities = result?;
+    capabilities.save(project_root)?;
+    Ok(capabilities)
+}
+
+fn probe_in(scratch: &Path) -> Result<FsCapabilities> {
+    let (a, b) = (scratch.join("a"), scratch.join("b"));
+    for dir in [&a, &b] {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create probe dir: {}", dir.display()))?;
+    }
+    let source = a.join("Probe");
+    fs::write(&source, b"epiphyte")
+        .with_context(|| format!("Failed to write probe file: {}", source.display()))?;
+
+    Ok(FsCapabilities {
+        symlinks: probe_symlink(&source, &b.join("symlink")),
+        reflinks: reflink(&source, &b.join("reflink")).is_ok(),
+        hardlinks: fs::hard_link(&source, b.join("hardlink")).is_ok(),
+        case_sensitive: !a.join("PROBE").exists(),
+    })
+}
+
+fn probe_symlink(source: &Path, link: &Path) -> bool {
+    #[cfg(unix)]
+    let created = std::os::unix::fs::symlink(source, link);
+    #[cfg(windows)]
+    let created = std::os::windows::fs::symlink_file(source, link);
+    created.is_ok() && fs::read_link(link).is_ok_and(|target| target == source)
+}
+
+/// Clone `src` to a new file at `dst` sharing its blocks, failing where the filesystem
+/// can't.
+#[cfg(target_os = "linux")]
+pub fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    let source = File::open(src)?;
+    let destination = File::create(dst)?;
+    // SAFETY: both descriptors are open for the duration of the call
+    let result =
+        unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result != 0 {
+        let err = std::io::Error::last_os_error();
+        drop(destination);
+        let _ = fs::remove_file(dst);
+        return Err(err);
+    }
+    destination.set_permissions(source.metadata()?.permissions())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn reflink(_src: &Path, _dst: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
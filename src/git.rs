@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::trace;
+
+/// Process-wide settings for spawned git commands.
+#[derive(Debug, Default)]
+pub struct GitSettings {
+    /// Kill git commands that run longer than this
+    pub timeout: Option<Duration>,
+    /// Echo each command and stream its stderr to the terminal as it runs
+    pub verbose: bool,
+    /// The git to run instead of the first on PATH
+    pub binary: Option<PathBuf>,
+    /// Arguments passed before every git subcommand, e.g. `-c protocol.version=2`
+    pub args: Vec<String>,
+}
+
+static SETTINGS: OnceLock<GitSettings> = OnceLock::new();
+
+static DEFAULT_SETTINGS: GitSettings = GitSettings {
+    timeout: None,
+    verbose: false,
+    binary: None,
+    args: Vec::new(),
+};
+
+/// Keep git from taking optional locks, like the index refresh done by `git status`
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Set the settings for every git command run afterwards. Only the first call has effect.
+pub fn configure(settings: GitSettings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// Keep git from taking optional locks from now on, for checkouts the user can't write.
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::Relaxed);
+}
+
+fn settings() -> &'static GitSettings {
+    SETTINGS.get().unwrap_or(&DEFAULT_SETTINGS)
+}
+
+/// Run git in `dir` and collect its output, like `Command::output`. A non-zero exit is
+/// not an error; exceeding the configured timeout is, and names the exact command.
+pub fn output(dir: &Path, args: &[&str]) -> Result<Output> {
+    let settings = settings();
+    let program = settings.binary.as_deref().unwrap_or(Path::new("git"));
+    let command_line = std::iter::once(program.display().to_string())
+        .chain(settings.args.iter().cloned())
+        .chain(args.iter().map(|arg| arg.to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if settings.verbose {
+        eprintln!("+ {} (in {})", command_line, dir.display());
+    }
+
+    let mut command = Command::new(program);
+    if READ_ONLY.load(Ordering::Relaxed) {
+        command.env("GIT_OPTIONAL_LOCKS", "0");
+    }
+    command
+        .args(&settings.args)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let started = Instant::now();
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            trace::finished(&command, started, None);
+            return Err(err).with_context(|| format!("Failed to run {}", command_line));
+        }
+    };
+
+    let mut stdout = child.stdout.take().context("git stdout was not captured")?;
+    let stderr = child.stderr.take().context("git stderr was not captured")?;
+    let stdout_reader = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stdout.read_to_end(&mut buffer);
+        buffer
+    });
+    let verbose = settings.verbose;
+    let stderr_reader = thread::spawn(move || {
+        let mut reader = BufReader::new(stderr);
+        let mut buffer = Vec::new();
+        let mut line = Vec::new();
+        while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+            if verbose {
+                let _ = std::io::stderr().write_all(&line);
+            }
+            buffer.append(&mut line);
+        }
+        buffer
+    });
+
+    let status = match settings.timeout {
+        None => child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", command_line))?,
+        Some(timeout) => {
+            loop {
+                if let Some(status) = child
+                    .try_wait()
+                    .with_context(|| format!("Failed to wait for {}", command_line))?
+                {
+                    break status;
+                }
+                if started.elapsed() >= timeout {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    trace::finished(&command, started, None);
+                    // The readers are left behind: helpers git spawned may still hold
+                    // the pipes open.
+                    anyhow::bail!(
+                        "{} timed out after {}s in {}. Run with -v to see its output, or \
+                        raise git_timeout in the global config.",
+                        command_line,
+                        timeout.as_secs(),
+                        dir.display()
+                    );
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+    };
+    trace::finished(&command, started, status.code());
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    })
+}
+
+/// Run git in `dir`, failing with its stderr when it exits non-zero.
+pub fn run(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = output(dir, args)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
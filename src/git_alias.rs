@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::perms;
+use crate::remote::shell_quote;
+
+/// Point `git <alias>` at this binary for each alias, in the user's global git config
+/// or, with `local`, in the repository's. Returns the configured alias commands.
+pub fn install_aliases(
+    project_root: &Path,
+    aliases: &[String],
+    local: bool,
+) -> Result<Vec<String>> {
+    let binary = current_binary()?;
+    // Shell aliases run from the top of the checkout; go back to where git was invoked so
+    // worktree detection and relative paths still work
+    let command = format!(
+        "!f() {{ cd \"${{GIT_PREFIX:-.}}\" && {} \"$@\"; }}; f",
+        shell_quote(&binary.to_string_lossy())
+    );
+    let scope = if local { "--local" } else { "--global" };
+    for alias in aliases {
+        git::run(project_root, &["config", scope, &format!("alias.{}", alias), &command])
+            .with_context(|| format!("Failed to configure git alias '{}'", alias))?;
+    }
+    Ok(aliases.iter().map(|alias| format!("git {}", alias)).collect())
+}
+
+/// Write a `git-epi` script into `dir` so git finds `git epi` as an external subcommand.
+pub fn install_shim(dir: &Path) -> Result<PathBuf> {
+    let binary = current_binary()?;
+    perms::create_dir_all(dir, None)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join("git-epi");
+    let script = format!(
+        "#!/bin/sh\nexec {} \"$@\"\n",
+        shell_quote(&binary.to_string_lossy())
+    );
+    fs::write(&path, script).with_context(|| format!("Failed to write {}", path.display()))?;
+    perms::set_mode(&path, 0o755)
+        .with_context(|| format!("Failed to make {} executable", path.display()))?;
+    Ok(path)
+}
+
+/// Whether `dir` is one of the directories in $PATH.
+pub fn is_on_path(dir: &Path) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|entry| entry == dir))
+}
+
+pub fn current_binary() -> Result<PathBuf> {
+    std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Failed to locate the epiphyte binary")
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::git_alias::current_binary;
+use crate::perms;
+use crate::remote::shell_quote;
+
+/// Marks hooks written by `install-hooks`, so reinstalling replaces them but never a
+/// hook the user wrote.
+const MARKER: &str = "# Installed by epiphyte install-hooks";
+
+/// Install post-merge and post-checkout hooks in the main checkout that run
+/// `relink --all --quiet --background`, so copied files follow pulls and branch switches
+/// without slowing them down. Returns the paths of the installed hooks.
+pub fn install(project_root: &Path) -> Result<Vec<PathBuf>> {
+    let binary = current_binary()?;
+    let dir = hooks_dir(project_root)?;
+    perms::create_dir_all(&dir, None)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let relink = format!(
+        "{} relink --all --quiet --background || true",
+        shell_quote(&binary.to_string_lossy())
+    );
+    // Hooks are shared by every worktree of the repository; only the main checkout relinks.
+    // post-checkout gets a third argument of 1 for branch checkouts, 0 for file checkouts
+    let guard = format!(
+        "[ \"$(git rev-parse --show-toplevel)\" = {} ] || exit 0",
+        shell_quote(&project_root.to_string_lossy())
+    );
+    let hooks = [
+        ("post-merge", format!("{}\n{}\n", guard, relink)),
+        (
+            "post-checkout",
+            format!("{}\n[ \"$3\" = 1 ] || exit 0\n{}\n", guard, relink),
+        ),
+    ];
+
+    for (name, _) in &hooks {
+        let path = dir.join(name);
+        if fs::read_to_string(&path).is_ok_and(|existing| !existing.contains(MARKER)) {
+            anyhow::bail!(
+                "{} already exists; add `{}` to it by hand",
+                path.display(),
+                relink
+            );
+        }
+    }
+
+    let mut installed = Vec::new();
+    for (name, body) in hooks {
+        let path = dir.join(name);
+        let script = format!("#!/bin/sh\n{}\n{}", MARKER, body);
+        fs::write(&path, script).with_context(|| format!("Failed to write {}", path.display()))?;
+        perms::set_mode(&path, 0o755)
+            .with_context(|| format!("Failed to make {} executable", path.display()))?;
+        installed.push(path);
+    }
+    Ok(installed)
+}
+
+/// The hooks directory git uses for the checkout, honouring core.hooksPath.
+fn hooks_dir(project_root: &Path) -> Result<PathBuf> {
+    let output = git::output(project_root, &["rev-parse", "--git-path", "hooks"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to locate the git hooks directory");
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(project_root.join(path))
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::get_state_dir;
+
+const COMMAND_LOG_FILE: &str = "commands.jsonl";
+const COMMAND_LOG_LOCK_FILE: &str = "commands.lock";
+
+/// Past this size, the log is cut down to its most recent `KEPT_RECORDS` records.
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+const KEPT_RECORDS: usize = 2000;
+
+/// A command epiphyte ran in a worktree for `exec`, `each`, a hook or `--trace-commands`,
+/// one JSON line of `.epi/state/commands.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    /// Seconds since the Unix epoch at which the command finished
+    pub at: u64,
+    /// For traced processes, the directory they ran in, relative to the project root
+    pub worktree: String,
+    /// `exec`, `each`, `trace`, or the hook event, e.g. `post_create`
+    pub source: String,
+    pub command: String,
+    pub duration_ms: u64,
+    /// None when the command could not start or did not exit on its own, e.g. timed out
+    pub exit_code: Option<i32>,
+}
+
+impl CommandRecord {
+    pub fn new(
+        worktree: &str,
+        source: &str,
+        command: &str,
+        duration: Duration,
+        exit_code: Option<i32>,
+    ) -> Self {
+        CommandRecord {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            worktree: worktree.to_string(),
+            source: source.to_string(),
+            command: command.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            exit_code,
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.exit_code != Some(0)
+    }
+}
+
+/// Append `record` to the command log. The log is a convenience: failing to write it,
+/// e.g. in a checkout the user can only read, never fails the command that ran.
+pub fn record(project_root: &Path, record: &CommandRecord) {
+    let _ = append(project_root, record);
+}
+
+fn append(project_root: &Path, record: &CommandRecord) -> Result<()> {
+    let state_dir = get_state_dir(project_root);
+    fs::create_dir_all(&state_dir)?;
+    let path = state_dir.join(COMMAND_LOG_FILE);
+    // Held by every epiphyte process of the project while it writes the log, so that
+    // cutting it down never drops records appended meanwhile; released when closed
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(state_dir.join(COMMAND_LOG_LOCK_FILE))?;
+    lock.lock()?;
+    let line = serde_json::to_string(record)?;
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", line)?;
+
+    if file.metadata()?.len() > MAX_LOG_BYTES {
+        let records = load(project_root)?;
+        let kept = &records[records.len().saturating_sub(KEPT_RECORDS)..];
+        let mut content = String::new();
+        for record in kept {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        // Readers see the whole log before or after, never a partial one
+        let partial = state_dir.join(format!("{}.partial", COMMAND_LOG_FILE));
+        fs::write(&partial, content)?;
+        fs::rename(&partial, &path)?;
+    }
+    Ok(())
+}
+
+/// Every recorded command, oldest first. Lines that don't parse are skipped.
+pub fn load(project_root: &Path) -> Result<Vec<CommandRecord>> {
+    let path = get_state_dir(project_root).join(COMMAND_LOG_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read command log: {}", path.display()))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// How one command fared across its recorded runs.
+#[derive(Serialize)]
+pub struct CommandStats {
+    pub source: String,
+    pub command: String,
+    pub runs: usize,
+    pub failures: usize,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+    /// Worktrees where the command failed at least once
+    pub failed_in: Vec<String>,
+}
+
+/// Summarize the records per source and command.
+pub fn command_stats(records: &[CommandRecord]) -> Vec<CommandStats> {
+    let mut grouped: BTreeMap<(&str, &str), Vec<&CommandRecord>> = BTreeMap::new();
+    for record in records {
+        grouped
+            .entry((&record.source, &record.command))
+            .or_default()
+            .push(record);
+    }
+    grouped
+        .into_iter()
+        .map(|((source, command), runs)| {
+            let total_ms: u64 = runs.iter().map(|run| run.duration_ms).sum();
+            let mut failed_in: Vec<String> = runs
+                .iter()
+                .filter(|run| run.failed())
+                .map(|run| run.worktree.clone())
+                .collect();
+            failed_in.sort();
+            failed_in.dedup();
+            CommandStats {
+                source: source.to_string(),
+                command: command.to_string(),
+                runs: runs.len(),
+                failures: runs.iter().filter(|run| run.failed()).count(),
+                mean_ms: total_ms / runs.len() as u64,
+                max_ms: runs.iter().map(|run| run.duration_ms).max().unwrap_or_default(),
+                failed_in,
+            }
+        })
+        .collect()
+}
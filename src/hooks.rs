@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{get_state_dir, Hook, HooksConfig};
+use crate::history::{self, CommandRecord};
+use crate::trace;
+use crate::worktree::Worktree;
+
+const LOCKS_DIR: &str = "hooks";
+
+/// Event description written as JSON to the hook's stdin.
+#[derive(Serialize)]
+pub struct HookPayload<'a> {
+    pub event: &'a str,
+    pub root: &'a Path,
+    pub worktree: &'a Worktree,
+    pub files: Vec<&'a str>,
+}
+
+/// Run a hook command through `sh -c` with the given environment and the payload on
+/// stdin. The hook's output goes straight to the terminal; a non-zero exit is
+/// returned as an error.
+///
+/// Before starting, waits for the hook's own lock when it is serialized and for one of
+/// `max_concurrent` slots, both shared by every epiphyte process of the project. A hook
+/// still running after `timeout` seconds is killed.
+pub fn run_hook(
+    hook: &Hook,
+    settings: &HooksConfig,
+    dir: &Path,
+    env: &[(String, String)],
+    payload: &HookPayload,
+) -> Result<()> {
+    let name = payload.event;
+    let command = hook.command();
+    let input = serde_json::to_vec(payload).context("Failed to serialize hook payload")?;
+    // Held until the hook exits; the OS releases the locks when the files are closed
+    let _locks = acquire_locks(payload.root, name, hook.serialize(), settings.max_concurrent)?;
+
+    let started = Instant::now();
+    let result = spawn_and_wait(command, name, dir, env, settings, &input);
+    history::record(
+        payload.root,
+        &CommandRecord::new(
+            &payload.worktree.name,
+            name,
+            command,
+            started.elapsed(),
+            result.as_ref().ok().and_then(|status| status.code()),
+        ),
+    );
+    let status = result?;
+
+    if !status.success() {
+        match status.code() {
+            Some(code) => anyhow::bail!("{} hook exited with status {}", name, code),
+            None => anyhow::bail!("{} hook was terminated by a signal", name),
+        }
+    }
+
+    Ok(())
+}
+
+fn spawn_and_wait(
+    command: &str,
+    name: &str,
+    dir: &Path,
+    env: &[(String, String)],
+    settings: &HooksConfig,
+    input: &[u8],
+) -> Result<ExitStatus> {
+    let mut command_builder = Command::new("sh");
+    command_builder
+        .args(["-c", command])
+        .current_dir(dir)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped());
+    // With a timeout, the hook gets its own process group so that everything it started
+    // can be killed along with it
+    #[cfg(unix)]
+    if settings.timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        command_builder.process_group(0);
+    }
+    let started = Instant::now();
+    let mut child = match command_builder.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            trace::finished(&command_builder, started, None);
+            return Err(err).with_context(|| format!("Failed to run {} hook: {}", name, command));
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks are free to ignore their input and exit before reading it
+        match stdin.write_all(input) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                return Err(err).with_context(|| format!("Failed to write {} hook input", name));
+            }
+            _ => {}
+        }
+    }
+
+    let status = match settings.timeout {
+        None => child
+            .wait()
+            .with_context(|| format!("Failed to wait for {} hook", name))?,
+        Some(timeout) => {
+            loop {
+                if let Some(status) = child
+                    .try_wait()
+                    .with_context(|| format!("Failed to wait for {} hook", name))?
+                {
+                    break status;
+                }
+                if started.elapsed() >= Duration::from_secs(timeout) {
+                    kill_process_group(&mut child);
+                    let _ = child.wait();
+                    trace::finished(&command_builder, started, None);
+                    anyhow::bail!(
+                        "{} hook timed out after {}s. Skip hooks with --no-hooks, or raise \
+                        hooks.timeout or --hook-timeout.",
+                        name,
+                        timeout
+                    );
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+    };
+    trace::finished(&command_builder, started, status.code());
+    Ok(status)
+}
+
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // SAFETY: kill has no memory-safety preconditions; the group was created at spawn
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+}
+
+fn acquire_locks(
+    project_root: &Path,
+    name: &str,
+    serialize: bool,
+    max_concurrent: Option<usize>,
+) -> Result<Vec<File>> {
+    let mut locks = Vec::new();
+    if !serialize && max_concurrent.is_none() {
+        return Ok(locks);
+    }
+
+    let locks_dir = get_state_dir(project_root).join(LOCKS_DIR);
+    fs::create_dir_all(&locks_dir)
+        .with_context(|| format!("Failed to create lock dir: {}", locks_dir.display()))?;
+
+    if serialize {
+        let lock = open_lock(&locks_dir.join(format!("{}.lock", name)))?;
+        if !try_lock(&lock)? {
+            println!("Waiting for another {} hook to finish...", name);
+            lock.lock()
+                .with_context(|| format!("Failed to lock the {} hook", name))?;
+        }
+        locks.push(lock);
+    }
+
+    if let Some(max_concurrent) = max_concurrent {
+        let mut slots = (0..max_concurrent.max(1))
+            .map(|slot| open_lock(&locks_dir.join(format!("slot-{}.lock", slot))))
+            .collect::<Result<Vec<_>>>()?;
+        let mut waiting = false;
+        loop {
+            let mut free = None;
+            for (index, slot) in slots.iter().enumerate() {
+                if try_lock(slot)? {
+                    free = Some(index);
+                    break;
+                }
+            }
+            if let Some(index) = free {
+                locks.push(slots.swap_remove(index));
+                break;
+            }
+            if !waiting {
+                println!("Waiting for a hook slot, {} already running...", slots.len());
+                waiting = true;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    Ok(locks)
+}
+
+fn open_lock(path: &Path) -> Result<File> {
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file: {}", path.display()))
+}
+
+/// Take an exclusive lock without blocking, returning whether it was free.
+fn try_lock(file: &File) -> Result<bool> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(err)) => Err(err).context("Failed to lock hook file"),
+    }
+}
@@ -0,0 +1,298 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::config::{get_store_dir, Config, CopyConfig, FileEntry, LinkType};
+use crate::copy;
+use crate::fscaps::{self, FsCapabilities};
+use crate::perms;
+use crate::plugin;
+use crate::secret;
+use crate::store;
+use crate::template;
+use crate::trust;
+
+/// Settings that shape how entries are materialized, resolved once per command.
+#[derive(Clone)]
+pub struct LinkContext {
+    project_root: PathBuf,
+    /// Config `[env]` entries, available to templates
+    env: BTreeMap<String, String>,
+    /// Name and path of the worktree templates are rendered for
+    worktree: Option<(String, PathBuf)>,
+    store: Option<PathBuf>,
+    /// Unknown when the filesystem could not be probed
+    capabilities: Option<FsCapabilities>,
+    copy: CopyConfig,
+    dir_mode: Option<u32>,
+    file_mode: Option<u32>,
+    /// Fetch secrets again even where they were fetched before
+    refresh_secrets: bool,
+}
+
+impl LinkContext {
+    pub fn new(project_root: &Path, config: &Config) -> Self {
+        let capabilities = FsCapabilities::load(project_root)
+            .or_else(|| fscaps::probe(project_root).ok());
+        // Without hardlinks every blob would be copied twice, into the store and out of it
+        let dedup = config.copy.dedup && capabilities.is_none_or(|caps| caps.hardlinks);
+        Self {
+            project_root: project_root.to_path_buf(),
+            env: config.env.clone(),
+            worktree: None,
+            store: dedup.then(|| get_store_dir(project_root)),
+            capabilities,
+            copy: config.copy.clone(),
+            dir_mode: config.permissions.dir_mode,
+            file_mode: config.permissions.file_mode,
+            refresh_secrets: false,
+        }
+    }
+
+    /// The same settings, rendering templates for the worktree `name` at `path`.
+    pub fn for_worktree(&self, name: &str, path: &Path) -> Self {
+        Self {
+            worktree: Some((name.to_string(), path.to_path_buf())),
+            ..self.clone()
+        }
+    }
+
+    /// The same settings, fetching secrets again with `refresh`.
+    pub fn refreshing_secrets(self, refresh: bool) -> Self {
+        Self {
+            refresh_secrets: refresh,
+            ..self
+        }
+    }
+
+    fn render(&self, template: &Path) -> Result<String> {
+        let (name, path) = self
+            .worktree
+            .as_ref()
+            .context("Templates are only rendered into worktrees")?;
+        template::render_for_worktree(&self.project_root, &self.env, name, path, template)
+    }
+
+    fn call_plugin(&self, name: &str, src: &Path, dst: &Path) -> Result<()> {
+        let (worktree, worktree_path) = self
+            .worktree
+            .as_ref()
+            .context("Plugin entries are only linked into worktrees")?;
+        let path = dst.strip_prefix(worktree_path).unwrap_or(dst).to_string_lossy();
+        let key = format!("files.{}", path);
+        if !trust::is_trusted(&self.project_root, &key, &plugin::executable(name)) {
+            anyhow::bail!(
+                "Plugin {} is not trusted; run `epiphyte trust` after reviewing the config",
+                plugin::executable(name)
+            );
+        }
+        let request = plugin::Request::Link {
+            root: &self.project_root,
+            worktree,
+            worktree_path,
+            path: &path,
+            source: src,
+            destination: dst,
+        };
+        plugin::call(name, worktree_path, &request)
+    }
+
+    /// How an entry is actually materialized: symlinks become copies on filesystems
+    /// without symlink support.
+    fn effective_type<'a>(&self, link_type: &'a LinkType) -> &'a LinkType {
+        match link_type {
+            LinkType::Symlink if self.capabilities.is_some_and(|caps| !caps.symlinks) => {
+                &LinkType::Copy
+            }
+            _ => link_type,
+        }
+    }
+}
+
+pub fn link_entry(
+    src: &Path,
+    dst: &Path,
+    entry: &FileEntry,
+    context: &LinkContext,
+) -> Result<()> {
+    // Fetched before the destination is removed, so a failed fetch keeps the last secret
+    let fetched = match &entry.link_type {
+        LinkType::Secret => {
+            let uri = entry
+                .secret
+                .as_deref()
+                .with_context(|| format!("Secret entry '{}' has no secret URI", entry.path))?;
+            secret::fetch(uri)?
+        }
+        _ => Vec::new(),
+    };
+
+    // Create parent directories for destination
+    if let Some(parent) = dst.parent() {
+        perms::create_dir_all(parent, context.dir_mode)
+            .with_context(|| format!("Failed to create parent dir: {}", parent.display()))?;
+    }
+
+    // Remove existing destination if it exists
+    if dst.exists() || dst.symlink_metadata().is_ok() {
+        if dst.is_dir() && !dst.symlink_metadata()?.file_type().is_symlink() {
+            fs::remove_dir_all(dst)?;
+        } else {
+            fs::remove_file(dst)?;
+        }
+    }
+
+    match context.effective_type(&entry.link_type) {
+        LinkType::Symlink => {
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(src, dst).with_context(|| {
+                    format!("Failed to symlink {} -> {}", src.display(), dst.display())
+                })?;
+            }
+            #[cfg(windows)]
+            {
+                if src.is_dir() {
+                    std::os::windows::fs::symlink_dir(src, dst)?;
+                } else {
+                    std::os::windows::fs::symlink_file(src, dst)?;
+                }
+            }
+        }
+        LinkType::Copy => {
+            if src.is_dir() {
+                copy_dir_recursive(src, dst, context)?;
+            } else {
+                copy_file(src, dst, context).with_context(|| {
+                    format!("Failed to copy {} -> {}", src.display(), dst.display())
+                })?;
+            }
+        }
+        LinkType::Plugin(name) => context.call_plugin(name, src, dst)?,
+        LinkType::Secret => secret::write(dst, &fetched, context.file_mode)?,
+        LinkType::Template => {
+            fs::write(dst, context.render(src)?)
+                .with_context(|| format!("Failed to write {}", dst.display()))?;
+            if let Some(mode) = context.file_mode {
+                perms::set_mode(dst, mode)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `dst` already is what `link_entry` would make of `src`, so linking again
+/// would change nothing. Copied directories are never considered up to date.
+pub fn is_linked(src: &Path, dst: &Path, entry: &FileEntry, context: &LinkContext) -> bool {
+    match context.effective_type(&entry.link_type) {
+        LinkType::Symlink => fs::read_link(dst).is_ok_and(|target| target == src),
+        LinkType::Copy => {
+            let Ok(metadata) = dst.symlink_metadata() else {
+                return false;
+            };
+            if !metadata.is_file() || src.is_dir() {
+                return false;
+            }
+            #[cfg(unix)]
+            if let Some(mode) = context.file_mode {
+                use std::os::unix::fs::PermissionsExt;
+                if metadata.permissions().mode() & 0o7777 != mode {
+                    return false;
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = context;
+            same_contents(src, dst).unwrap_or(false)
+        }
+        // Only the plugin knows; it is asked again every time
+        LinkType::Plugin(_) => false,
+        // Fetching again is slow and may prompt; only done when asked to refresh
+        LinkType::Secret => {
+            !context.refresh_secrets
+                && dst.symlink_metadata().is_ok_and(|metadata| metadata.is_file())
+        }
+        LinkType::Template => {
+            dst.symlink_metadata().is_ok_and(|metadata| metadata.is_file())
+                && context
+                    .render(src)
+                    .is_ok_and(|rendered| fs::read(dst).is_ok_and(|c| c == rendered.as_bytes()))
+        }
+    }
+}
+
+/// Total size in bytes of what the copy entries of `config` would copy into a worktree.
+pub fn copy_size(project_root: &Path, config: &Config) -> u64 {
+    config
+        .files
+        .iter()
+        .filter(|entry| matches!(entry.link_type, LinkType::Copy))
+        .map(|entry| tree_size(&project_root.join(&entry.path)))
+        .sum()
+}
+
+fn tree_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| tree_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+pub fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut a_chunk = [0u8; 8192];
+    let mut b_chunk = [0u8; 8192];
+    loop {
+        let read = a.read(&mut a_chunk)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut b_chunk[..read])?;
+        if a_chunk[..read] != b_chunk[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+fn copy_file(src: &Path, dst: &Path, context: &LinkContext) -> Result<()> {
+    match &context.store {
+        Some(store_dir) => store::link_from_store(store_dir, src, dst, &context.copy)?,
+        // Clones share blocks with the source until either is modified
+        None if context.capabilities.is_some_and(|caps| caps.reflinks) => {
+            if fscaps::reflink(src, dst).is_err() {
+                copy::copy_file(src, dst, &context.copy)?;
+            }
+        }
+        None => copy::copy_file(src, dst, &context.copy)?,
+    }
+    if let Some(mode) = context.file_mode {
+        perms::set_mode(dst, mode)?;
+    }
+    Ok(())
+}
+
+fn copy_dir_recursive(src: &Path, dst: &Path, context: &LinkContext) -> Result<()> {
+    perms::create_dir_all(dst, context.dir_mode)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        if src_path.is_dir() {
+            copy_dir_recursive(&src_path, &dst_path, context)?;
+        } else {
+            copy_file(&src_path, &dst_path, context)?;
+        }
+    }
+    Ok(())
+}
@@ -1,25 +1,118 @@
+mod adopt;
+mod archive;
+mod audit;
+mod cache;
 mod config;
+mod copy;
+mod devcontainer;
+mod doctor;
+mod duplicate;
+mod env;
+mod failure;
+mod first_run;
+mod fscaps;
+mod git;
+mod git_alias;
+mod git_hooks;
+mod history;
+mod hooks;
+mod link;
+mod manifest;
+mod messages;
+mod metadata;
+mod notify;
+mod motd;
+mod network;
+mod patch;
+mod perms;
+mod plugin;
+mod preset;
+mod priority;
+mod progress;
+mod prompt;
+mod remote;
+mod review;
+mod schema;
+mod scratch;
+mod secret;
+mod store;
+mod sync;
+mod template;
+mod trace;
+mod trust;
+mod vcs;
 mod worktree;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use inquire::{error::InquireError, MultiSelect};
-use std::io::Write;
-use std::path::Path;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use inquire::{error::InquireError, MultiSelect, Select};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tabwriter::TabWriter;
 
-use config::{find_project_root, Config, FileEntry, LinkType};
+use config::{
+    find_project_root, get_state_dir, is_read_only, ChangeKind, Config, FileEntry, GlobalConfig,
+    LinkType,
+};
+use doctor::MissingAction;
+use failure::FailureKind;
+use git::GitSettings;
+use metadata::Metadata;
+use patch::Patch;
 use worktree::{
-    add_worktree, detect_current_worktree, ensure_on_main_branch, enter_worktree,
-    get_worktree_path, import_all_worktrees, is_path_tracked, link_entries_to_worktrees,
-    list_ignored_files, list_worktrees, relink_worktree, remove_symlinks_from_worktrees,
-    resolve_worktree_name, select_worktree_name,
+    add_worktree, auto_worktree_name, branch_description, detect_current_checkout,
+    detect_current_worktree, ensure_on_main_branch, enter_worktree, exec_in_worktree,
+    existing_link_destinations, fetch_all, find_branch_checkout, find_worktree,
+    get_worktree_path, adopt_worktree, import_all_worktrees, is_root_name,
+    is_path_tracked, link_entries_to_worktrees, link_health, link_one, list_ignored_files,
+    merged_worktrees,
+    list_managed_worktrees, list_untracked_paths, list_worktrees, managed_path,
+    relink_worktree, remove_symlinks_from_worktrees, remove_worktree, rename_worktree,
+    resolve_worktree_name,
+    run_maintenance, select_branch, select_worktree_name, set_worktree_lock, unsaved_changes,
+    upstream_status, worktree_locks, worktree_status,
+    AddOptions, LinkReport, Worktree, ROOT_WORKTREE,
 };
 
 #[derive(Parser)]
 #[command(name = "epiphyte")]
 #[command(about = "A git worktree management tool", long_about = None)]
 struct Cli {
+    /// Print each git command and stream its stderr as it runs
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Run as if epiphyte was started in this directory
+    #[arg(short = 'C', global = true, value_name = "PATH")]
+    directory: Option<PathBuf>,
+
+    /// Exit non-zero when any warning was reported, e.g. missing sources or partial failures
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Skip anything that needs the network; also on when EPI_OFFLINE is set to anything
+    /// but `0`. With EPI_OFFLINE unset and no IPv4 or IPv6 default route, optional network
+    /// access is skipped, and commands that need the network warn but still try
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Record every process epiphyte spawns (git, hooks, shells) with its arguments,
+    /// directory, duration and exit status in the command log; `stderr` also prints them
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "TARGET",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "log"
+    )]
+    trace_commands: Option<TraceTarget>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,146 +120,1557 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize epiphyte configuration in the current repository
-    Init,
+    Init {
+        /// Rebuild the config and metadata from existing worktrees in .epi/trees
+        #[arg(long)]
+        adopt_existing: bool,
+    },
+
+    /// Apply a configuration preset for a development environment
+    Preset {
+        /// Preset to apply
+        #[arg(value_enum)]
+        preset: preset::Preset,
+
+        /// Do not configure hooks the preset would add
+        #[arg(long)]
+        no_hooks: bool,
+    },
 
     /// Add a new worktree
     Add {
         /// Name for the worktree (also used as branch name if no branch specified)
-        name: String,
+        #[arg(required_unless_present_any = ["auto_name", "branch", "pick_branch"])]
+        name: Option<String>,
 
-        /// Existing branch to checkout (creates new branch if not specified)
-        #[arg(short, long)]
-        branch: Option<String>,
+        /// Existing branch to checkout (creates new branch if not specified); without a
+        /// value, pick one from a list. A branch only on a remote, e.g. `origin/fix-123`, is
+        /// fetched and checked out on a local branch tracking it
+        #[arg(short, long, num_args = 0..=1, value_name = "BRANCH")]
+        branch: Option<Option<String>>,
+
+        /// Pick the branch from local and remote branches, most recent first
+        #[arg(long, conflicts_with = "branch")]
+        pick_branch: bool,
+
+        /// Start the new branch from this branch or commit instead of HEAD of the main
+        /// checkout, e.g. `release/2.0`
+        #[arg(long, value_name = "REF", conflicts_with_all = ["branch", "pick_branch"])]
+        base: Option<String>,
 
         /// Enter the worktree in a new shell after creation
         #[arg(short, long)]
         enter: bool,
+
+        /// Generate the name from the branch, or the next free review-<n>
+        #[arg(long, conflicts_with = "name")]
+        auto_name: bool,
+
+        /// Add the worktree to a named group
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Create the worktree with a profile, running only the hooks whose
+        /// `only_profiles` include it, e.g. `fullstack`
+        #[arg(long, value_name = "PROFILE")]
+        profile: Option<String>,
+
+        /// Tag the worktree, running the hooks whose `only_tags` include it; repeatable
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Describe the branch (git's branch.<name>.description), shown by `list --long`
+        /// and `info`
+        #[arg(long, value_name = "TEXT")]
+        describe: Option<String>,
+
+        /// Create the worktree without checking out files (fast in partial clones)
+        #[arg(long)]
+        no_checkout: bool,
+
+        /// Skip the pre_create and post_create hooks
+        #[arg(long)]
+        no_hooks: bool,
+
+        /// Kill hooks still running after this many seconds, overriding hooks.timeout
+        #[arg(long, value_name = "SECS", conflicts_with = "no_hooks")]
+        hook_timeout: Option<u64>,
+
+        /// Apply a patch or diff, a file or an http(s) URL, to the new worktree's files,
+        /// reporting the files it could not apply cleanly
+        #[arg(
+            long,
+            value_name = "PATCH",
+            conflicts_with_all = ["branch", "pick_branch", "no_checkout"]
+        )]
+        apply: Option<String>,
+
+        /// Print the created worktree and link results as JSON
+        #[arg(long, conflicts_with = "enter")]
+        json: bool,
     },
 
     /// List all worktrees managed by epiphyte
     #[command(visible_alias = "ls")]
-    List,
+    List {
+        /// Keep refreshing the list until interrupted
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Seconds between refreshes in watch mode
+        #[arg(long, default_value_t = 2, requires = "watch")]
+        interval: u64,
+
+        /// Print tab-separated name, branch and path lines for scripts
+        #[arg(long, conflicts_with = "watch")]
+        porcelain: bool,
+
+        /// List the worktrees of the [remote] checkout over SSH (experimental)
+        #[arg(long)]
+        remote: bool,
+
+        /// Show worktrees in sections with counts
+        #[arg(long, value_enum, conflicts_with = "porcelain")]
+        group_by: Option<ListGrouping>,
+
+        /// Also show the first line of each branch's description
+        #[arg(short, long, conflicts_with_all = ["porcelain", "remote"])]
+        long: bool,
+
+        /// Also show the pull request and CI status of each branch, as `gh` reports it
+        #[arg(long, conflicts_with_all = ["porcelain", "remote"])]
+        status: bool,
+
+        /// With --status, ask `gh` again instead of reusing its answers of the last minutes
+        #[arg(long, requires = "status")]
+        refresh: bool,
+
+        /// Print worktrees with their group, dirty file count and upstream as JSON
+        #[arg(long, conflicts_with_all = ["watch", "porcelain", "remote", "group_by"])]
+        json: bool,
+
+        /// With --json, print one object per line as soon as each worktree's status is known
+        #[arg(long, requires = "json")]
+        stream: bool,
+    },
+
+    /// Remove a managed worktree and its directory, optionally deleting its branch
+    #[command(visible_alias = "rm")]
+    Remove {
+        /// Name of the worktree to remove
+        name: String,
+
+        /// Remove the worktree even if it has local changes, and delete its branch even if
+        /// it is not merged
+        #[arg(short, long)]
+        force: bool,
+
+        /// Delete the worktree's branch without asking
+        #[arg(long, conflicts_with = "keep_branch")]
+        delete_branch: bool,
+
+        /// Keep the worktree's branch without asking
+        #[arg(long)]
+        keep_branch: bool,
+
+        /// Save its uncommitted and untracked files in .epi/archive first, then remove it
+        /// even if it has local changes
+        #[arg(long, conflicts_with = "force")]
+        archive: bool,
+
+        /// Print the removed worktree as JSON; the branch is kept unless --delete-branch
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Rename a managed worktree, moving its directory and linking files again
+    Rename {
+        /// Current name of the worktree
+        old: String,
+
+        /// New name of the worktree
+        new: String,
+
+        /// Also rename the worktree's branch to the new name
+        #[arg(short, long)]
+        branch: bool,
+
+        /// Print the new path, the branch rename and the link results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove worktrees whose branch is merged into the main branch, with their branch
+    Clean {
+        /// Remove them without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// List the worktrees that would be removed without removing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Save the local changes of worktrees that have some in .epi/archive and remove
+        /// them too, instead of skipping them
+        #[arg(long)]
+        archive: bool,
+
+        /// Print the removed and skipped worktrees as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Lock a worktree so that git won't prune, move or remove it, e.g. while the drive it
+    /// lives on is unmounted
+    Lock {
+        /// Name of the worktree (auto-detected if inside a worktree)
+        name: Option<String>,
+
+        /// Why the worktree is locked, shown by list
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// Unlock a worktree locked with `lock`
+    Unlock {
+        /// Name of the worktree (auto-detected if inside a worktree)
+        name: Option<String>,
+    },
+
+    /// Clean up after worktree directories deleted by hand: forget them in git and the
+    /// metadata, and remove empty directories left in the trees dir
+    Prune {
+        /// Report what would be cleaned up without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print what was cleaned up as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Re-link/copy files from config to an existing worktree
     Relink {
         /// Name of the worktree to relink (auto-detected if inside a worktree)
+        #[arg(conflicts_with = "all")]
         name: Option<String>,
+
+        /// Relink every managed worktree
+        #[arg(long)]
+        all: bool,
+
+        /// Only report problems
+        #[arg(short, long, conflicts_with = "json")]
+        quiet: bool,
+
+        /// Fetch secret entries again from their secret manager
+        #[arg(long)]
+        refresh_secrets: bool,
+
+        /// Run at the low CPU and I/O priority of copy.background_priority, so copies
+        /// don't slow down foreground work
+        #[arg(long)]
+        background: bool,
+
+        /// Print the link results as JSON
+        #[arg(long)]
+        json: bool,
     },
 
-    /// Import existing worktrees into epiphyte
-    Import {
-        /// Import all worktrees not managed by epiphyte
+    /// Link a file of the project root into worktrees without adding it to the config
+    ///
+    /// Uses the same machinery as configured files, for one-off experiments and for
+    /// debugging how a link type behaves.
+    Link {
+        /// Path of the file, relative to the project root
+        path: String,
+
+        /// Worktree to link into (auto-detected if inside a worktree)
+        #[arg(conflicts_with = "all")]
+        name: Option<String>,
+
+        /// Link into every managed worktree
+        #[arg(long)]
+        all: bool,
+
+        /// How to link the file: symlink, copy or the name of a link plugin
+        #[arg(long = "type", value_name = "TYPE", default_value = "symlink")]
+        link_type: LinkType,
+
+        /// Print the link results as JSON
         #[arg(long)]
+        json: bool,
+    },
+
+    /// Import existing worktrees into epiphyte: move every worktree it doesn't manage
+    /// into .epi/trees and link the configured files into it
+    Import {
+        /// Import all worktrees not managed by epiphyte, which is what import does anyway;
+        /// kept for scripts written when it was required
+        #[arg(long, hide = true)]
         all: bool,
+
+        /// Print the import report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Create a worktree on a new branch at the commit of another, carrying over its
+    /// uncommitted changes and untracked files, to try another approach to a change
+    Duplicate {
+        /// Worktree to duplicate
+        name: String,
+
+        /// Name for the new worktree and its branch
+        new_name: String,
+
+        /// Print the created worktree and what it took over as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Import one existing worktree into epiphyte: move it into .epi/trees and link the
+    /// configured files into it
+    Adopt {
+        /// Path of the worktree
+        path: PathBuf,
+
+        /// Name of the worktree in epiphyte, the name of its directory by default
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Print the imported worktree as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Enter a worktree in a new shell
     #[command(visible_alias = "e")]
     Enter {
-        /// Name of the worktree to enter (auto-detected if inside a worktree)
+        /// Name of the worktree to enter (auto-detected if inside a worktree); `@root` is
+        /// the main checkout
+        name: Option<String>,
+
+        /// Print the worktree path instead of spawning a shell
+        #[arg(short, long)]
+        print: bool,
+
+        /// Open an SSH session into the worktree of the [remote] checkout (experimental)
+        #[arg(long, requires = "name")]
+        remote: bool,
+
+        /// Exec into the worktree's running dev container instead of a local shell
+        #[arg(long, conflicts_with_all = ["print", "remote"])]
+        container: bool,
+
+        /// Write the worktree path to this file instead of spawning a shell, for shell
+        /// functions that `cd` into it after epiphyte exits
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["print", "remote", "container"])]
+        print_cd_file: Option<PathBuf>,
+
+        /// Start in this subdirectory of the worktree, remembered for the next `enter`;
+        /// pass `.` to go back to the top
+        #[arg(long, value_name = "SUBPATH", conflicts_with_all = ["remote", "container"])]
+        dir: Option<PathBuf>,
+
+        /// Start in the directory the last shell of the worktree exited in; needs the
+        /// snippet of `epiphyte shell-init` in the shell's startup file
+        #[arg(long, conflicts_with_all = ["dir", "remote", "container"])]
+        last_dir: bool,
+    },
+
+    /// Show the branch, local changes, upstream and linked files of worktrees
+    Status {
+        /// Name of the worktree; all managed worktrees when omitted
+        name: Option<String>,
+
+        /// Print the statuses as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show what epiphyte and git know about a worktree: branch and its description,
+    /// group, profile, tags and last operation
+    Info {
+        /// Name of the worktree (auto-detected if inside a worktree); `@root` is the main
+        /// checkout
+        name: Option<String>,
+
+        /// Print the details as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the commands recently run by exec, each and hooks, and the processes traced
+    /// by --trace-commands, with their duration and exit status
+    History {
+        /// Summarize per command instead: the slowest ones and the ones failing most
+        #[arg(long)]
+        command_stats: bool,
+
+        /// Number of records, or of commands per summary table, to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the records or the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Enter a worktree's .scratch directory in a new shell, creating it if needed
+    ///
+    /// The directory is ignored by git and tagged for backup tools to skip, for
+    /// experiments that should not end up in commits.
+    Scratch {
+        /// Name of the worktree (auto-detected if inside a worktree)
         name: Option<String>,
+
+        /// Print the scratch directory path instead of spawning a shell
+        #[arg(short, long)]
+        print: bool,
     },
 
     /// Enter the repository root in a new shell
-    Root,
+    Root {
+        /// Print the repository root instead of spawning a shell
+        #[arg(short, long)]
+        print: bool,
+    },
+
+    /// Run a command inside a worktree
+    ///
+    /// The command runs with EPI_ROOT, EPI_WORKTREE, EPI_WORKTREE_PATH and EPI_BRANCH set,
+    /// plus the variables from the config [env] section and --env-file.
+    Exec {
+        /// Name of the worktree (auto-detected if inside a worktree); `@root` is the main
+        /// checkout
+        name: Option<String>,
+
+        /// Load extra environment variables from a KEY=VALUE file
+        #[arg(long)]
+        env_file: Option<PathBuf>,
+
+        /// Command and arguments to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Run a command, or a shell, in the current directory with a worktree's environment
+    ///
+    /// Loads what `enter` and `exec` give the worktree (EPI_* variables, its port slot and
+    /// the [env] section) without changing directory, like `poetry run` or `nix shell`.
+    Shell {
+        /// Name of the worktree (auto-detected if inside a worktree); `@root` is the main
+        /// checkout
+        name: Option<String>,
+
+        /// Load extra environment variables from a KEY=VALUE file
+        #[arg(long)]
+        env_file: Option<PathBuf>,
+
+        /// Command and arguments to run; without one, starts an interactive shell
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+
+    /// Rebase all worktrees onto the main branch
+    Sync {
+        /// Resume worktrees left with unresolved conflicts by the previous sync
+        #[arg(long = "continue")]
+        resume: bool,
+    },
+
+    /// Fetch all remotes once and show upstream tracking for each worktree
+    FetchAll {
+        /// Also run `git maintenance run` after fetching
+        #[arg(long)]
+        maintenance: bool,
+    },
+
+    /// Run a command in every worktree
+    ///
+    /// Give either the name of a template from the config [each] table, run through
+    /// `sh -c`, or a command after `--`.
+    Each {
+        /// Name of a command template from the config [each] table
+        #[arg(required_unless_present = "command", conflicts_with = "command")]
+        template: Option<String>,
+
+        /// Command and arguments to run
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+
+    /// Show which worktree has a branch checked out
+    Find {
+        /// Branch name
+        branch: String,
+    },
+
+    /// Check worktrees and configuration for unsafe symlinks and paths
+    Audit,
+
+    /// Probe the filesystem for symlinks, reflinks, hardlinks and case sensitivity, and
+    /// cache the results used to pick how files are linked
+    BenchIo,
+
+    /// Show the commands of the project's config (hooks, each templates, notify and
+    /// enter commands) and trust them to run until they change
+    Trust,
+
+    /// Check the configuration and worktree metadata against the repository
+    Doctor {
+        /// Update the metadata of worktrees whose branch was renamed, and recreate or prune
+        /// worktrees whose directory was deleted
+        #[arg(long)]
+        fix: bool,
+
+        /// With --fix, also rename the worktree directories after their new branch
+        #[arg(long, requires = "fix")]
+        rename_dirs: bool,
+    },
+
+    /// Create a worktree tagged for review, or remove review worktrees once merged
+    Review {
+        /// Pull request number or URL (fetched from origin), or a branch name
+        #[arg(required_unless_present = "sweep")]
+        target: Option<String>,
+
+        /// Remove review worktrees whose pull request or branch has been merged
+        #[arg(long, conflicts_with = "target")]
+        sweep: bool,
+
+        /// Enter the worktree in a new shell after creation
+        #[arg(short, long, conflicts_with = "sweep")]
+        enter: bool,
+
+        /// Ask `gh` about pull requests again instead of using answers cached for up to
+        /// five minutes
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// Make epiphyte available as a git subcommand, e.g. `git wt list`
+    InstallGitAlias {
+        /// Alias names to configure
+        #[arg(long = "alias", value_delimiter = ',', default_value = "epi,wt")]
+        aliases: Vec<String>,
+
+        /// Configure the aliases for this repository only instead of globally
+        #[arg(long)]
+        local: bool,
+
+        /// Also write a `git-epi` script into this directory, which should be on PATH
+        #[arg(long, value_name = "DIR")]
+        shim: Option<PathBuf>,
+    },
+
+    /// Print the shell snippet that lets `enter --last-dir` restore where you left off
+    ///
+    /// Add `eval "$(epiphyte shell-init bash)"` to ~/.bashrc, or the equivalent for your
+    /// shell. Shells started by `enter` then record their directory when they exit.
+    ShellInit {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Install git hooks in the main checkout that relink every worktree after a pull,
+    /// merge or branch checkout, keeping copies fresh
+    InstallHooks,
+
+    /// Create the worktrees declared in the config and report undeclared ones
+    Apply {
+        /// Show the planned actions without executing them
+        #[arg(long)]
+        plan: bool,
+
+        /// Print the plan as JSON
+        #[arg(long, requires = "plan")]
+        json: bool,
+    },
+
+    /// Set up a fresh clone for CI: create the declared worktrees, run their hooks and
+    /// link files into every worktree, exiting non-zero if anything failed
+    Provision {
+        /// Trust the commands of the config without asking, as CI runs the repository's
+        /// own config
+        #[arg(long)]
+        trust: bool,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Reconcile the worktrees with the config: create declared worktrees, record renamed
+    /// branches and relink files, e.g. from cron or a pre-push hook
+    Watch {
+        /// Fix all drift in a single pass and exit; required, as there is no daemon yet
+        #[arg(long)]
+        once: bool,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Inspect the environment epiphyte provides worktrees
+    #[command(subcommand)]
+    Env(EnvCommands),
 
     /// Manage files in the configuration
     #[command(subcommand)]
     Files(FilesCommands),
+
+    /// Operate on groups of worktrees
+    #[command(subcommand)]
+    Group(GroupCommands),
+
+    /// Inspect the configuration format
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Low-level commands for integrations, with stable output and no prompts
+    #[command(subcommand, hide = true)]
+    Plumbing(PlumbingCommands),
+
+    /// Any other command runs the `epi-<command>` plugin from PATH
+    #[command(external_subcommand)]
+    Plugin(Vec<String>),
+}
+
+impl Commands {
+    /// Whether the command writes to the project, its worktrees or their git state.
+    fn mutates(&self) -> bool {
+        match self {
+            Commands::List { .. }
+            | Commands::Enter { .. }
+            | Commands::Root { .. }
+            | Commands::Exec { .. }
+            | Commands::Shell { .. }
+            | Commands::Info { .. }
+            | Commands::Status { .. }
+            | Commands::History { .. }
+            | Commands::ShellInit { .. }
+            | Commands::Each { .. }
+            | Commands::Find { .. }
+            | Commands::Audit
+            | Commands::Trust
+            | Commands::Env(_)
+            | Commands::Config(_) => false,
+            Commands::Doctor { fix, .. } => *fix,
+            Commands::Prune { dry_run, .. } | Commands::Clean { dry_run, .. } => !dry_run,
+            Commands::Apply { plan, .. } => !plan,
+            Commands::InstallGitAlias { local, .. } => *local,
+            Commands::Group(command) => matches!(command, GroupCommands::Remove { .. }),
+            Commands::Files(command) => !matches!(command, FilesCommands::List),
+            Commands::Plumbing(command) => matches!(command, PlumbingCommands::LinkOne { .. }),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ListGrouping {
+    /// The branch name up to the first `/`, e.g. `feature` or `hotfix`
+    Prefix,
+    /// The worktree group set with `add --group`
+    Group,
 }
 
 #[derive(Subcommand)]
-enum FilesCommands {
-    /// Add a file to the configuration
-    Add {
-        /// Path to the file (relative to project root)
-        path: Option<String>,
+enum GroupCommands {
+    /// List groups and their worktrees
+    #[command(visible_alias = "ls")]
+    List,
 
-        /// Copy the file instead of symlinking
-        #[arg(short, long)]
-        copy: bool,
+    /// Enter a worktree of the group in a new shell (prompts when there are several)
+    Enter {
+        /// Group name
+        group: String,
+    },
 
-        /// Add ignored files from the repository root (prompted)
-        #[arg(long)]
-        ignored: bool,
+    /// Run a command in every worktree of the group
+    Each {
+        /// Group name
+        group: String,
+
+        /// Command and arguments to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
     },
 
-    /// Remove a file from the configuration
+    /// Remove every worktree of the group
     #[command(visible_alias = "rm")]
     Remove {
-        /// Path to the file to remove
-        path: String,
+        /// Group name
+        group: String,
+
+        /// Remove worktrees even if they have local changes
+        #[arg(short, long)]
+        force: bool,
     },
+}
 
-    /// List files in the configuration
-    #[command(visible_alias = "ls")]
-    List,
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print every config key with its type, default and description
+    Schema {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = SchemaFormat::Toml)]
+        format: SchemaFormat,
+
+        /// Describe the per-user config instead of the project config
+        #[arg(long)]
+        global: bool,
+    },
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let project_root = find_project_root()?;
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TraceTarget {
+    /// The command log shown by `history`
+    Log,
+    /// The command log and stderr
+    Stderr,
+}
 
-    match cli.command {
-        Commands::Init => {
-            let config = Config::default();
-            config.save(&project_root)?;
+#[derive(Clone, Copy, ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaFormat {
+    /// Annotated TOML
+    Toml,
+    /// JSON Schema
+    Json,
+}
+
+/// Output of these commands is kept stable for scripts and editor integrations.
+#[derive(Subcommand)]
+enum PlumbingCommands {
+    /// Print the project root
+    ResolveRoot,
+
+    /// Print the tab-separated name, branch and path of a worktree
+    ResolveWorktree {
+        /// Worktree name (detected from the current directory if omitted)
+        name: Option<String>,
+    },
+
+    /// Link or copy one configured file into a worktree, printing the tab-separated
+    /// outcome (created, replaced, repaired or unchanged) and destination
+    LinkOne {
+        /// Worktree name
+        worktree: String,
+
+        /// Configured file path, relative to the project root
+        path: String,
+    },
+
+    /// Print the project root, main branch, configured files and worktrees as JSON
+    ManifestDump,
+}
+
+#[derive(Subcommand)]
+enum EnvCommands {
+    /// Show the variables, port slot and linked files that differ between two worktrees
+    Diff {
+        /// First worktree; `@root` is the main checkout
+        a: String,
+
+        /// Second worktree
+        b: String,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FilesCommands {
+    /// Add a file to the configuration
+    Add {
+        /// Path to the file (relative to project root)
+        path: Option<String>,
+
+        /// Copy the file instead of symlinking; without --copy or --symlink, the first
+        /// matching [[link_rules]] entry picks
+        #[arg(short, long)]
+        copy: bool,
+
+        /// Symlink the file, whatever [[link_rules]] say
+        #[arg(long, conflicts_with = "copy")]
+        symlink: bool,
+
+        /// Fetch the file's content from a secret manager at link time instead, from
+        /// op://vault/item/field (1Password) or vault://<path>#<field> (HashiCorp Vault)
+        #[arg(
+            long,
+            value_name = "URI",
+            requires = "path",
+            conflicts_with_all = ["copy", "symlink", "ignored"]
+        )]
+        secret: Option<String>,
+
+        /// Add ignored files from the repository root (prompted)
+        #[arg(long)]
+        ignored: bool,
+
+        /// Link only into these existing worktrees (comma-separated)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["pick", "no_link"])]
+        to: Vec<String>,
+
+        /// Choose which existing worktrees receive the file (prompted)
+        #[arg(long, conflicts_with = "no_link")]
+        pick: bool,
+
+        /// Only apply to worktrees created from now on
+        #[arg(long)]
+        no_link: bool,
+
+        /// Print the added paths and link results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove a file from the configuration
+    #[command(visible_alias = "rm")]
+    Remove {
+        /// Path to the file to remove
+        path: String,
+
+        /// Print the symlink removal results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List files in the configuration
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Manage files rendered per worktree from templates
+    #[command(subcommand)]
+    Template(TemplateCommands),
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// Turn a file of the project root into a template
+    ///
+    /// Moves the file to .epi/templates/<path>.tmpl, offers to replace the project path,
+    /// its directory name and port numbers with variables, configures the template
+    /// entry and renders it into every worktree.
+    Init {
+        /// Path of the file, relative to the project root
+        path: String,
+    },
+}
+
+fn main() -> Result<()> {
+    // Looked up before anything can fail, so that every error is reported as JSON
+    let json = std::env::args()
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--json");
+    match try_main() {
+        Err(err) if json => {
+            let causes: Vec<String> = err.chain().skip(1).map(|cause| cause.to_string()).collect();
+            let output = serde_json::json!({
+                "error": {
+                    "id": messages::id_of(&err),
+                    "message": err.to_string(),
+                    "causes": causes,
+                }
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+fn try_main() -> Result<()> {
+    let global_config = GlobalConfig::load()?;
+    let args = expand_alias(std::env::args().collect(), &global_config);
+    let cli = Cli::parse_from(args);
+    if let Some(target) = cli.trace_commands {
+        trace::enable(target == TraceTarget::Stderr);
+    }
+    // Before finding the project root, which may already ask git
+    git::configure(GitSettings {
+        timeout: global_config.git_timeout.map(Duration::from_secs),
+        verbose: cli.verbose,
+        binary: global_config.git_binary.clone(),
+        args: global_config.git_args.clone(),
+    });
+    if let Some(directory) = &cli.directory {
+        std::env::set_current_dir(directory)
+            .with_context(|| format!("Failed to change to {}", directory.display()))?;
+    }
+    // Sourced from shell startup files, which run outside of any repository
+    if let Commands::ShellInit { shell } = cli.command {
+        print!("{}", shell_init(shell));
+        return Ok(());
+    }
+    let project_root = find_project_root()?;
+    trace::set_project_root(&project_root);
+    let read_only = is_read_only(&project_root);
+    if read_only && cli.command.mutates() {
+        let root = project_root.display().to_string();
+        return Err(messages::PROJECT_READ_ONLY.error(&[("root", &root)]));
+    }
+    network::init(cli.offline);
+    git::set_read_only(read_only);
+
+    if !matches!(cli.command, Commands::Init { .. }) && first_run::uninitialized(&project_root) {
+        if global_config.require_init {
+            let root = project_root.display().to_string();
+            return Err(messages::CONFIG_REQUIRED.error(&[("root", &root)]));
+        }
+        if adopt::config_missing(&project_root) {
+            count_warnings(1);
+            eprintln!(
+                "Warning: .epi/config.toml is missing but .epi/trees has worktrees; run \
+                `epiphyte init --adopt-existing` to rebuild it"
+            );
+        } else {
+            first_run::hint_init(&project_root);
+        }
+    }
+
+    let strict = cli.strict || Config::load(&project_root).is_ok_and(|config| config.strict);
+    run(cli.command, global_config, project_root)?;
+    let warnings = WARNINGS.load(Ordering::Relaxed);
+    if strict && warnings > 0 {
+        anyhow::bail!("{} warning(s) reported and strict mode is on", warnings);
+    }
+    Ok(())
+}
+
+fn run(command: Commands, global_config: GlobalConfig, project_root: PathBuf) -> Result<()> {
+    match command {
+        Commands::Init { adopt_existing } => {
+            let (config, ()) = Config::update(&project_root, |config| {
+                *config = if adopt_existing {
+                    adopt::adopt_existing(&project_root)?
+                } else {
+                    let mut config = Config::default();
+                    // Repositories whose main branch is e.g. `master` start out with it
+                    let vcs = vcs::open(&project_root);
+                    if !vcs.branch_exists(&config.main_branch)? {
+                        let current = vcs.current_branch()?;
+                        if !current.is_empty() {
+                            config.main_branch = current;
+                        }
+                    }
+                    config
+                };
+                Ok(())
+            })?;
             println!(
                 "Initialized epiphyte configuration at {}/.epi/config.toml",
                 project_root.display()
             );
+            if adopt_existing {
+                println!("Main branch: {}", config.main_branch);
+                print_section("Adopted files", &config.files, |entry| {
+                    format!("{}\t[{}]", entry.path, entry.link_type.name())
+                });
+            }
+            for preset in preset::detect(&project_root) {
+                let name = preset.to_possible_value().map(|v| v.get_name().to_string());
+                println!(
+                    "Tip: run `epiphyte preset {}` to set up worktrees for this repository",
+                    name.unwrap_or_default()
+                );
+            }
+        }
+
+        Commands::Preset { preset, no_hooks } => {
+            let (config, changes) = Config::update(&project_root, |config| {
+                preset::apply(&project_root, config, preset, !no_hooks)
+            })?;
+            if changes.notes.is_empty() {
+                println!("Preset already applied");
+            }
+            for note in &changes.notes {
+                println!("{}", note);
+            }
+            let report =
+                link_entries_to_worktrees(&project_root, &changes.entries, None, &config)?;
+            print_link_changes(&report);
+            print_link_problems(&report);
         }
 
         Commands::Add {
             name,
             branch,
             enter,
+            auto_name: _,
+            pick_branch,
+            base,
+            group,
+            profile,
+            tags,
+            describe,
+            no_checkout,
+            no_hooks,
+            hook_timeout,
+            apply,
+            json,
         } => {
-            let config = Config::load(&project_root)?;
+            let branch = if pick_branch || matches!(branch, Some(None)) {
+                match select_branch(&project_root)? {
+                    Some(branch) => Some(branch),
+                    None => return Ok(()),
+                }
+            } else {
+                branch.flatten()
+            };
+            let name = match name {
+                Some(name) => name,
+                None => auto_worktree_name(&project_root, branch.as_deref()),
+            };
+            let mut config = Config::load(&project_root)?;
+            if no_hooks {
+                config.hooks.disable();
+            }
+            if hook_timeout.is_some() {
+                config.hooks.timeout = hook_timeout;
+            }
             ensure_on_main_branch(&project_root, &config.main_branch)?;
-            let path = add_worktree(&project_root, &name, branch.as_deref(), &config)?;
-            println!("Created worktree '{}' at {}", name, path.display());
+            if let Some(limit) = config.copy.confirm_above {
+                let size = link::copy_size(&project_root, &config);
+                if size > limit
+                    && !prompt::confirm(
+                        global_config.confirm,
+                        ChangeKind::LargeCopy,
+                        &format!(
+                            "Copy {} of files into worktree '{}'?",
+                            format_size(size),
+                            name
+                        ),
+                    )?
+                {
+                    println!("Add cancelled");
+                    return Ok(());
+                }
+            }
+            let patch = apply
+                .map(|source| Patch::load(&project_root, &source))
+                .transpose()?;
+            let options = AddOptions {
+                no_checkout,
+                profile,
+                tags,
+                description: describe,
+                start_point: base,
+                progress: !json,
+            };
+            let mut added =
+                add_worktree(&project_root, &name, branch.as_deref(), &config, &options)?;
+            if let Some(patch) = patch {
+                added.patch = Some(patch.apply(&added.worktree.path)?);
+            }
+            if let Some(group) = group {
+                let mut metadata = Metadata::load(&project_root)?;
+                metadata.entry(&name).group = Some(group);
+                metadata.save(&project_root)?;
+            }
+            if json {
+                count_warnings(added.links.problems());
+                println!("{}", serde_json::to_string_pretty(&added)?);
+            } else {
+                println!(
+                    "Created worktree '{}' at {}",
+                    name,
+                    added.worktree.path.display()
+                );
+                if let Some(patch) = &added.patch {
+                    println!(
+                        "Applied {} to {} file(s)",
+                        patch.source,
+                        patch.changed.len()
+                    );
+                    eprint_section("Patch conflicts", &patch.conflicts, |path| path.clone());
+                }
+                print_link_problems(&added.links);
+            }
+            let conflicted = added.patch.as_ref().is_some_and(|p| !p.conflicts.is_empty());
+            if !added.links.failed.is_empty() || conflicted {
+                std::process::exit(1);
+            }
             if enter {
                 println!("Entering worktree...");
-                enter_worktree(&path)?;
+                let worktree = &added.worktree;
+                enter_managed_worktree(&project_root, worktree, &worktree.path, &config)?;
             }
         }
 
-        Commands::List => {
-            let worktrees = list_worktrees(&project_root)?;
-            if worktrees.is_empty() {
-                println!("No worktrees found");
+        Commands::List {
+            watch,
+            interval,
+            porcelain,
+            remote,
+            group_by,
+            long,
+            status,
+            refresh,
+            json,
+            stream,
+        } => {
+            let config = Config::load(&project_root)?;
+            if json {
+                return print_worktree_statuses(&project_root, &config, stream);
+            }
+            let remote = if remote {
+                Some(config.remote()?.clone())
             } else {
-                let mut output = Vec::new();
-                let mut writer = TabWriter::new(&mut output);
-                for wt in worktrees {
-                    writeln!(
-                        writer,
-                        "{}\t{}\t{}",
-                        wt.name,
-                        wt.branch,
-                        wt.path.display()
-                    )?;
+                None
+            };
+            let load = || match &remote {
+                Some(remote) => remote::list_worktrees(remote),
+                None => list_managed_worktrees(&project_root, &config),
+            };
+
+            if porcelain {
+                for wt in load()? {
+                    println!("{}\t{}\t{}", wt.name, wt.branch, wt.path.display());
+                }
+                return Ok(());
+            }
+            // Remote worktrees never contain the current directory
+            let current = match remote {
+                Some(_) => None,
+                None => detect_current_checkout(&project_root)?,
+            };
+            let cache = status.then(|| cache::Cache::new(&project_root, refresh));
+            let render = |worktrees: Vec<Worktree>| {
+                let current = current.as_deref();
+                let columns = ListColumns { long, status: cache.as_ref() };
+                render_worktree_list(&project_root, worktrees, group_by, columns, current)
+            };
+            if !watch {
+                print!("{}", render(load()?)?);
+                return Ok(());
+            }
+
+            loop {
+                let listing = render(load()?)?;
+                // Clear the screen and move the cursor home before redrawing
+                print!("\x1b[2J\x1b[H");
+                println!("Every {}s: epiphyte list\n", interval);
+                print!("{}", listing);
+                std::io::stdout().flush()?;
+                std::thread::sleep(Duration::from_secs(interval));
+            }
+        }
+
+        Commands::Remove {
+            name,
+            force,
+            delete_branch,
+            keep_branch,
+            archive,
+            json,
+        } => {
+            if is_root_name(&project_root, &name) {
+                return Err(messages::WORKTREE_RESERVED.error(&[("name", &name)]));
+            }
+            if !managed_path(&project_root, &name).exists() {
+                return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", &name)]));
+            }
+            let config = Config::load(&project_root)?;
+            let worktree = find_worktree(&project_root, &name)?;
+            let mut archived = None;
+            if archive {
+                archived = archive::archive_worktree(&project_root, &worktree, &config)?;
+            } else {
+                // --force is the confirmation
+                let changes = unsaved_changes(&project_root, &worktree, &config)?;
+                if !changes.is_empty() && !force {
+                    let message = format!(
+                        "Remove worktree '{}' and discard its local changes ({})?",
+                        name,
+                        changes.join(", ")
+                    );
+                    if !prompt::confirm(global_config.confirm, ChangeKind::Discard, &message)? {
+                        println!("Removal cancelled");
+                        return Ok(());
+                    }
+                }
+            }
+            // What is left besides the changes is the files epiphyte linked itself
+            let path = remove_worktree(&project_root, &name, true, &config)?;
+            let branch = worktree.branch;
+
+            // Detached worktrees have no branch, and the main branch is never offered
+            let delete_branch = if branch.is_empty() || branch == config.main_branch {
+                false
+            } else if delete_branch || keep_branch || json {
+                delete_branch
+            } else {
+                let message = format!("Also delete branch '{}'?", branch);
+                prompt::offer(prompt::DELETE_BRANCH, &message)?
+            };
+            if delete_branch {
+                vcs::open(&project_root)
+                    .delete_branch(&branch, force)
+                    .with_context(|| {
+                        format!(
+                            "Removed worktree '{}', but failed to delete branch '{}'",
+                            name, branch
+                        )
+                    })?;
+            }
+
+            if json {
+                let output = serde_json::json!({
+                    "name": name,
+                    "path": path,
+                    "branch": branch,
+                    "branch_deleted": delete_branch,
+                    "archive": archived,
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                if let Some(archived) = &archived {
+                    println!("Archived local changes to {}", archived.display());
+                }
+                println!("Removed worktree '{}' at {}", name, path.display());
+                if delete_branch {
+                    println!("Deleted branch '{}'", branch);
+                }
+            }
+        }
+
+        Commands::Rename {
+            old,
+            new,
+            branch,
+            json,
+        } => {
+            let config = Config::load(&project_root)?;
+            let report = rename_worktree(&project_root, &old, &new, branch, &config)?;
+            if json {
+                count_warnings(report.links.problems());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!(
+                    "Renamed worktree '{}' to '{}' at {}",
+                    old,
+                    new,
+                    report.path.display()
+                );
+                if let Some((from, to)) = &report.branch {
+                    println!("Renamed branch '{}' to '{}'", from, to);
+                }
+                print_link_problems(&report.links);
+            }
+        }
+
+        Commands::Clean {
+            yes,
+            dry_run,
+            archive,
+            json,
+        } => {
+            let config = Config::load(&project_root)?;
+            let mut candidates = Vec::new();
+            let mut skipped = Vec::new();
+            for worktree in merged_worktrees(&project_root, &config)? {
+                let changes = unsaved_changes(&project_root, &worktree, &config)?;
+                if changes.is_empty() || archive {
+                    candidates.push(worktree);
+                } else {
+                    skipped.push((worktree.name, changes));
                 }
-                writer.flush()?;
-                print!("{}", String::from_utf8_lossy(&output));
             }
+            let names: Vec<&str> = candidates.iter().map(|wt| wt.name.as_str()).collect();
+            let message = format!(
+                "Remove {} merged worktree(s) and their branch: {}?",
+                names.len(),
+                names.join(", ")
+            );
+            let confirmed = dry_run
+                || candidates.is_empty()
+                || yes
+                || prompt::confirm(global_config.confirm, ChangeKind::Destructive, &message)?;
+            if !confirmed {
+                println!("Removal cancelled");
+                return Ok(());
+            }
+
+            let vcs = vcs::open(&project_root);
+            let mut removed = Vec::new();
+            let mut archived = Vec::new();
+            let mut failed = Vec::new();
+            for worktree in candidates {
+                if dry_run {
+                    removed.push(worktree);
+                    continue;
+                }
+                let saved = if archive {
+                    archive::archive_worktree(&project_root, &worktree, &config)
+                } else {
+                    Ok(None)
+                };
+                let result = saved
+                    .and_then(|saved| {
+                        if let Some(saved) = saved {
+                            archived.push((worktree.name.clone(), saved));
+                        }
+                        remove_worktree(&project_root, &worktree.name, true, &config)
+                    })
+                    // Merged, so deleting the branch loses nothing even if git's own check
+                    // against HEAD disagrees
+                    .and_then(|_| vcs.delete_branch(&worktree.branch, true));
+                match result {
+                    Ok(()) => removed.push(worktree),
+                    Err(err) => failed.push((worktree.name, format!("{:#}", err))),
+                }
+            }
+
+            if json {
+                let skipped: Vec<_> = skipped
+                    .iter()
+                    .map(|(name, changes)| serde_json::json!({ "name": name, "changes": changes }))
+                    .collect();
+                let failed: Vec<_> = failed
+                    .iter()
+                    .map(|(name, error)| serde_json::json!({ "name": name, "error": error }))
+                    .collect();
+                let archived: Vec<_> = archived
+                    .iter()
+                    .map(|(name, path)| serde_json::json!({ "name": name, "archive": path }))
+                    .collect();
+                let output = serde_json::json!({
+                    "removed": removed,
+                    "archived": archived,
+                    "skipped": skipped,
+                    "failed": failed,
+                    "dry_run": dry_run,
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+                return Ok(());
+            }
+            if removed.is_empty() && skipped.is_empty() && failed.is_empty() {
+                println!("No merged worktrees");
+                return Ok(());
+            }
+            let label = if dry_run {
+                "Would remove worktrees"
+            } else {
+                "Removed worktrees"
+            };
+            print_section(label, &removed, |wt| format!("{}\t{}", wt.name, wt.branch));
+            print_section("Archived local changes", &archived, |(name, path)| {
+                format!("{}\t{}", name, path.display())
+            });
+            eprint_section("Skipped worktrees with local changes", &skipped, |(name, changes)| {
+                format!("{}\t{}", name, changes.join(", "))
+            });
+            eprint_section("Failed to remove worktrees", &failed, |(name, error)| {
+                format!("{}\t{}", name, error)
+            });
+        }
+
+        Commands::Lock { name, reason } => {
+            let name = resolve_worktree_name(&project_root, name.as_deref())?;
+            let path = set_worktree_lock(&project_root, &name, true, reason.as_deref())?;
+            println!("Locked worktree '{}' at {}", name, path.display());
         }
 
-        Commands::Relink { name } => {
+        Commands::Unlock { name } => {
             let name = resolve_worktree_name(&project_root, name.as_deref())?;
+            let path = set_worktree_lock(&project_root, &name, false, None)?;
+            println!("Unlocked worktree '{}' at {}", name, path.display());
+        }
+
+        Commands::Prune { dry_run, json } => {
+            let report = doctor::prune(&project_root, dry_run)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else if report.worktrees.is_empty() && report.empty_dirs.is_empty() {
+                println!("Nothing to prune");
+            } else {
+                let (worktrees, empty_dirs) = if dry_run {
+                    ("Would prune worktrees", "Would remove empty directories")
+                } else {
+                    ("Pruned worktrees", "Removed empty directories")
+                };
+                print_section(worktrees, &report.worktrees, |path| path.display().to_string());
+                print_section(empty_dirs, &report.empty_dirs, |path| {
+                    path.display().to_string()
+                });
+            }
+        }
+
+        Commands::Relink {
+            name,
+            all,
+            quiet,
+            refresh_secrets,
+            background,
+            json,
+        } => {
             let config = Config::load(&project_root)?;
-            relink_worktree(&project_root, &name, &config)?;
-            println!("Re-linked files for worktree '{}'", name);
+            if background {
+                priority::lower(config.copy.background_priority);
+            }
+            let (worktrees, target) = if all {
+                let worktrees = list_managed_worktrees(&project_root, &config)?;
+                (worktrees, "all worktrees".to_string())
+            } else {
+                let name = resolve_worktree_name(&project_root, name.as_deref())?;
+                let target = format!("worktree '{}'", name);
+                (vec![find_worktree(&project_root, &name)?], target)
+            };
+            let names: Vec<&str> = worktrees.iter().map(|wt| wt.name.as_str()).collect();
+            let mut existing = Vec::new();
+            for worktree in &worktrees {
+                existing.extend(existing_link_destinations(&project_root, worktree, &config)?);
+            }
+            if !existing.is_empty()
+                && !prompt::confirm(
+                    global_config.confirm,
+                    ChangeKind::Overwrite,
+                    &format!("Replace {} existing file(s) in {}?", existing.len(), target),
+                )?
+            {
+                println!("Relink cancelled");
+                return Ok(());
+            }
+            let mut report = LinkReport::default();
+            for worktree in &worktrees {
+                report.extend(relink_worktree(&project_root, worktree, &config, refresh_secrets)?);
+            }
+            if json {
+                count_warnings(report.problems());
+                let output = if all {
+                    serde_json::json!({ "worktrees": names, "links": report })
+                } else {
+                    serde_json::json!({ "worktree": names[0], "links": report })
+                };
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else if quiet {
+                print_link_problems(&report);
+            } else {
+                println!(
+                    "Re-linked files for {}: {} created, {} replaced, {} repaired, \
+                    {} unchanged, {} failed",
+                    target,
+                    report.created.len(),
+                    report.replaced.len(),
+                    report.repaired.len(),
+                    report.unchanged.len(),
+                    report.failed.len()
+                );
+                print_link_changes(&report);
+                print_section("Unchanged", &report.unchanged, |unchanged| {
+                    format!("{}\t{}", unchanged.worktree, unchanged.path.display())
+                });
+                print_link_problems(&report);
+            }
+            if !report.failed.is_empty() {
+                std::process::exit(1);
+            }
         }
 
-        Commands::Import { all } => {
-            if !all {
-                anyhow::bail!("Only --all is supported for import");
+        Commands::Link {
+            path,
+            name,
+            all,
+            link_type,
+            json,
+        } => {
+            let config = Config::load(&project_root)?;
+            if matches!(link_type, LinkType::Template | LinkType::Secret) {
+                anyhow::bail!(
+                    "Link type '{}' needs configuration; use `epiphyte files template init` or \
+                    `epiphyte files add --secret`",
+                    link_type.name()
+                );
+            }
+            if !Path::new(&path)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            {
+                anyhow::bail!("The path must be relative to the project root: {}", path);
+            }
+            let entry = FileEntry {
+                path,
+                link_type,
+                secret: None,
+            };
+            let targets = if all {
+                None
+            } else {
+                let name = resolve_worktree_name(&project_root, name.as_deref())?;
+                get_worktree_path(&project_root, &name)?;
+                Some(vec![name])
+            };
+
+            let existing: Vec<String> = list_managed_worktrees(&project_root, &config)?
+                .into_iter()
+                .filter(|wt| targets.as_ref().is_none_or(|targets| targets.contains(&wt.name)))
+                .filter(|wt| wt.path.join(&entry.path).symlink_metadata().is_ok())
+                .map(|wt| wt.name)
+                .collect();
+            if !existing.is_empty()
+                && !prompt::confirm(
+                    global_config.confirm,
+                    ChangeKind::Overwrite,
+                    &format!(
+                        "Replace '{}' in {} worktree(s)?",
+                        entry.path,
+                        existing.len()
+                    ),
+                )?
+            {
+                println!("Link cancelled");
+                return Ok(());
+            }
+
+            let report = link_entries_to_worktrees(
+                &project_root,
+                std::slice::from_ref(&entry),
+                targets.as_deref(),
+                &config,
+            )?;
+            if json {
+                count_warnings(report.problems());
+                let output = serde_json::json!({ "entry": entry, "links": report });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                if !report.changed() {
+                    println!("No worktrees updated");
+                }
+                print_link_changes(&report);
+                print_section("Unchanged", &report.unchanged, |unchanged| {
+                    format!("{}\t{}", unchanged.worktree, unchanged.path.display())
+                });
+                print_link_problems(&report);
+            }
+            if !report.failed.is_empty() || !report.missing.is_empty() {
+                std::process::exit(1);
             }
+        }
 
+        Commands::Import { all: _, json } => {
             let config = Config::load(&project_root)?;
             let report = import_all_worktrees(&project_root, &config)?;
+            let relink_errors = report.moved.iter().filter(|item| item.relink_error.is_some());
+            count_warnings(relink_errors.count());
+            if json {
+                count_warnings(report.failed.len());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
             if report.moved.is_empty() {
                 println!("No worktrees imported");
             } else {
@@ -176,43 +1680,998 @@ fn main() -> Result<()> {
                     if let Some(err) = &item.relink_error {
                         line.push_str(&format!("\t{}", err));
                     }
-                    line
+                    line
+                });
+            }
+
+            print_section("Skipped worktrees", &report.skipped, |item| {
+                format!("{}\t{}", item.path.display(), item.reason)
+            });
+
+            let failures: Vec<_> = report
+                .failed
+                .iter()
+                .map(|item| (item.kind, item.path.display().to_string(), item.error.as_str()))
+                .collect();
+            eprint_failures("Failed to import worktrees", &failures, "epiphyte import");
+        }
+
+        Commands::Duplicate {
+            name,
+            new_name,
+            json,
+        } => {
+            let config = Config::load(&project_root)?;
+            let options = AddOptions {
+                progress: !json,
+                ..Default::default()
+            };
+            let duplicated =
+                duplicate::duplicate_worktree(&project_root, &name, &new_name, &config, options)?;
+            let links = &duplicated.added.links;
+            if json {
+                count_warnings(links.problems());
+                println!("{}", serde_json::to_string_pretty(&duplicated)?);
+            } else {
+                println!(
+                    "Created worktree '{}' at {} from '{}'",
+                    new_name,
+                    duplicated.added.worktree.path.display(),
+                    duplicated.from
+                );
+                println!(
+                    "Carried over changes to {} file(s) and {} untracked file(s)",
+                    duplicated.changed.len(),
+                    duplicated.untracked.len()
+                );
+                print_link_problems(links);
+            }
+            if !links.failed.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Adopt { path, name, json } => {
+            let config = Config::load(&project_root)?;
+            let moved = adopt_worktree(&project_root, &path, name.as_deref(), &config)?;
+            count_warnings(usize::from(moved.relink_error.is_some()));
+            if json {
+                println!("{}", serde_json::to_string_pretty(&moved)?);
+                return Ok(());
+            }
+            println!(
+                "Adopted worktree '{}': {} -> {}",
+                moved.name,
+                moved.from.display(),
+                moved.to.display()
+            );
+            if let Some(err) = &moved.relink_error {
+                eprintln!("Warning: {}", err);
+            }
+        }
+
+        Commands::Enter {
+            name,
+            print,
+            remote,
+            container,
+            print_cd_file,
+            dir,
+            last_dir,
+        } => {
+            if remote {
+                let config = Config::load(&project_root)?;
+                let remote = config.remote()?;
+                let name = name.unwrap_or_default();
+                let worktree = remote::list_worktrees(remote)?
+                    .into_iter()
+                    .find(|wt| wt.name == name)
+                    .with_context(|| format!("Worktree '{}' not found on {}", name, remote.host))?;
+                if print {
+                    println!("{}", worktree.path.display());
+                    return Ok(());
+                }
+                println!(
+                    "Entering worktree '{}' at {}:{}",
+                    name,
+                    remote.host,
+                    worktree.path.display()
+                );
+                return remote::enter(remote, &worktree.path);
+            }
+            let name = match name {
+                Some(name) => name,
+                None => match select_worktree_name(&project_root)? {
+                    Some(name) => name,
+                    None => return Ok(()),
+                },
+            };
+            let Some(name) = resolve_missing_target(&project_root, name, &global_config)? else {
+                return Ok(());
+            };
+            let explicit_dir = dir.is_some();
+            if print {
+                let worktree = find_worktree(&project_root, &name)?;
+                let path = resolve_enter_dir(
+                    &project_root,
+                    &worktree.name,
+                    &worktree.path,
+                    dir,
+                    last_dir,
+                )?;
+                println!("{}", path.display());
+                return Ok(());
+            }
+            if let Some(cd_file) = print_cd_file {
+                let worktree = find_worktree(&project_root, &name)?;
+                let path = resolve_enter_dir(
+                    &project_root,
+                    &worktree.name,
+                    &worktree.path,
+                    dir,
+                    last_dir,
+                )?;
+                fs::write(&cd_file, format!("{}\n", path.display()))
+                    .with_context(|| format!("Failed to write {}", cd_file.display()))?;
+                return Ok(());
+            }
+            let config = Config::load(&project_root)?;
+            let worktree = find_worktree(&project_root, &name)?;
+            if container {
+                let devcontainer = config
+                    .devcontainer
+                    .as_ref()
+                    .context("No [devcontainer] section in the config")?;
+                return devcontainer::enter(&project_root, &worktree, devcontainer);
+            }
+            if !explicit_dir
+                && detect_current_worktree(&project_root)?.as_deref() == Some(name.as_str())
+            {
+                return Ok(());
+            }
+            let path =
+                resolve_enter_dir(&project_root, &worktree.name, &worktree.path, dir, last_dir)?;
+            println!("Entering worktree '{}' at {}", name, path.display());
+            enter_managed_worktree(&project_root, &worktree, &path, &config)?;
+        }
+
+        Commands::Status { name, json } => {
+            let config = Config::load(&project_root)?;
+            let metadata = Metadata::load(&project_root)?;
+            let mut worktrees = list_managed_worktrees(&project_root, &config)?;
+            if let Some(name) = &name {
+                worktrees.retain(|wt| wt.name == *name);
+                if worktrees.is_empty() {
+                    return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", name)]));
+                }
+            }
+
+            let locks = worktree_locks(&project_root)?;
+            let mut statuses = Vec::new();
+            for wt in worktrees {
+                let links = link_health(&project_root, &wt, &config);
+                statuses.push((worktree_status(&project_root, wt, &metadata, &locks)?, links));
+            }
+            if json {
+                let mut output = Vec::new();
+                for (status, links) in &statuses {
+                    let mut value = serde_json::to_value(status)?;
+                    value["links"] = serde_json::to_value(links)?;
+                    output.push(value);
+                }
+                println!("{}", serde_json::to_string_pretty(&output)?);
+                return Ok(());
+            }
+            if statuses.is_empty() {
+                println!("No worktrees found");
+                return Ok(());
+            }
+
+            let mut writer = TabWriter::new(std::io::stdout());
+            for (status, links) in &statuses {
+                let branch = match status.worktree.branch.as_str() {
+                    "" => "(detached)",
+                    branch => branch,
+                };
+                let changes = match status.dirty {
+                    0 => "clean".to_string(),
+                    dirty => format!("{} changed", dirty),
+                };
+                let tracking = match &status.upstream {
+                    Some(upstream) => {
+                        format!("{} +{} -{}", upstream.upstream, upstream.ahead, upstream.behind)
+                    }
+                    None => "(no upstream)".to_string(),
+                };
+                let mut problems = Vec::new();
+                if !links.missing.is_empty() {
+                    problems.push(format!("missing: {}", links.missing.join(", ")));
+                }
+                if !links.differs.is_empty() {
+                    problems.push(format!("differ: {}", links.differs.join(", ")));
+                }
+                let files = if links.is_ok() {
+                    format!("{} files linked", links.linked)
+                } else {
+                    problems.join("; ")
+                };
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}",
+                    status.worktree.name, branch, changes, tracking, files
+                )?;
+            }
+            writer.flush()?;
+            if statuses.iter().any(|(_, links)| !links.is_ok()) {
+                eprintln!("Run `epiphyte relink` in a worktree to link its files again");
+            }
+        }
+
+        Commands::Info { name, json } => {
+            let name = resolve_worktree_name(&project_root, name.as_deref())?;
+            let worktree = find_worktree(&project_root, &name)?;
+            let description = branch_description(&project_root, &worktree.branch)?;
+            let meta = Metadata::load(&project_root)?
+                .get(&worktree.name)
+                .cloned()
+                .unwrap_or_default();
+            if json {
+                let output = serde_json::json!({
+                    "worktree": worktree,
+                    "description": description,
+                    "group": meta.group,
+                    "profile": meta.profile,
+                    "tags": meta.tags,
+                    "pull_request": meta.pull_request,
+                    "last_operation": meta.last_operation,
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+                return Ok(());
+            }
+
+            let mut fields = vec![
+                ("name", worktree.name.clone()),
+                ("branch", worktree.branch.clone()),
+                ("path", worktree.path.display().to_string()),
+            ];
+            fields.extend(description.map(|description| ("description", description)));
+            fields.extend(meta.group.map(|group| ("group", group)));
+            fields.extend(meta.profile.map(|profile| ("profile", profile)));
+            if !meta.tags.is_empty() {
+                fields.push(("tags", meta.tags.join(", ")));
+            }
+            fields.extend(meta.pull_request.map(|number| ("pull request", format!("#{}", number))));
+            fields.extend(meta.last_operation.map(|operation| {
+                let summary = format!("{} {}", operation.command, motd::ago(operation.at));
+                ("last operation", summary)
+            }));
+            let mut writer = TabWriter::new(std::io::stdout());
+            for (label, value) in fields {
+                let mut lines = value.lines();
+                writeln!(writer, "{}\t{}", label, lines.next().unwrap_or_default())?;
+                for line in lines {
+                    writeln!(writer, "\t{}", line)?;
+                }
+            }
+            writer.flush()?;
+        }
+
+        // Handled before looking for the project root
+        Commands::ShellInit { .. } => unreachable!(),
+
+        Commands::History {
+            command_stats,
+            limit,
+            json,
+        } => {
+            let records = history::load(&project_root)?;
+            if !command_stats {
+                let recent = &records[records.len().saturating_sub(limit)..];
+                if json {
+                    println!("{}", serde_json::to_string_pretty(recent)?);
+                } else if recent.is_empty() {
+                    println!("No commands recorded yet");
+                } else {
+                    let mut writer = TabWriter::new(std::io::stdout());
+                    for record in recent.iter().rev() {
+                        writeln!(
+                            writer,
+                            "{}\t{}\t{}\t{}\t{}\t{}",
+                            motd::ago(record.at),
+                            record.worktree,
+                            record.source,
+                            format_exit_code(record.exit_code),
+                            format_duration_ms(record.duration_ms),
+                            record.command
+                        )?;
+                    }
+                    writer.flush()?;
+                }
+                return Ok(());
+            }
+
+            let stats = history::command_stats(&records);
+            let mut slowest: Vec<_> = stats.iter().collect();
+            slowest.sort_by_key(|stat| std::cmp::Reverse(stat.mean_ms));
+            slowest.truncate(limit);
+            let mut failing: Vec<_> = stats.iter().filter(|stat| stat.failures > 0).collect();
+            failing.sort_by_key(|stat| std::cmp::Reverse((stat.failures, stat.runs)));
+            failing.truncate(limit);
+            if json {
+                let output = serde_json::json!({
+                    "slowest": slowest,
+                    "most_failing": failing,
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+                return Ok(());
+            }
+            if slowest.is_empty() {
+                println!("No commands recorded yet");
+                return Ok(());
+            }
+            print_section("Slowest commands", &slowest, |stat| {
+                format!(
+                    "{}\t{}\tmean {}\tmax {}\t{} runs",
+                    stat.source,
+                    stat.command,
+                    format_duration_ms(stat.mean_ms),
+                    format_duration_ms(stat.max_ms),
+                    stat.runs
+                )
+            });
+            print_section("Most failing commands", &failing, |stat| {
+                format!(
+                    "{}\t{}\t{}/{} failed\tin {}",
+                    stat.source,
+                    stat.command,
+                    stat.failures,
+                    stat.runs,
+                    stat.failed_in.join(", ")
+                )
+            });
+        }
+
+        Commands::Scratch { name, print } => {
+            let name = match name {
+                Some(name) => name,
+                None => match detect_current_worktree(&project_root)? {
+                    Some(name) => name,
+                    None => match select_worktree_name(&project_root)? {
+                        Some(name) => name,
+                        None => return Ok(()),
+                    },
+                },
+            };
+            let config = Config::load(&project_root)?;
+            let worktree = find_worktree(&project_root, &name)?;
+            let dir = scratch::ensure(&worktree.path, config.permissions.dir_mode)?;
+            if print {
+                println!("{}", dir.display());
+                return Ok(());
+            }
+            println!("Entering scratch directory of '{}' at {}", name, dir.display());
+            if config.enter_command.is_some() {
+                trust::ensure_trusted(&project_root, &config)?;
+            }
+            let env = env::worktree_env(&project_root, &worktree, &config, None)?;
+            enter_worktree(&dir, &env, config.enter_command.as_deref())?;
+        }
+
+        Commands::Root { print } => {
+            if print {
+                println!("{}", project_root.display());
+                return Ok(());
+            }
+            let current_dir =
+                std::env::current_dir().context("Failed to get current directory")?;
+            if current_dir == project_root {
+                return Ok(());
+            }
+            println!("Entering repo root at {}", project_root.display());
+            enter_worktree(&project_root, &[], None)?;
+        }
+
+        Commands::Exec {
+            name,
+            env_file,
+            command,
+        } => {
+            let name = resolve_worktree_name(&project_root, name.as_deref())?;
+            let config = Config::load(&project_root)?;
+            let worktree = find_worktree(&project_root, &name)?;
+            let env =
+                env::worktree_env(&project_root, &worktree, &config, env_file.as_deref())?;
+            let code = exec_recorded(&project_root, &worktree, "exec", &command, &env)?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+
+        Commands::Shell {
+            name,
+            env_file,
+            command,
+        } => {
+            let name = resolve_worktree_name(&project_root, name.as_deref())?;
+            let config = Config::load(&project_root)?;
+            let worktree = find_worktree(&project_root, &name)?;
+            let env =
+                env::worktree_env(&project_root, &worktree, &config, env_file.as_deref())?;
+            let current_dir =
+                std::env::current_dir().context("Failed to get current directory")?;
+            if command.is_empty() {
+                if config.enter_command.is_some() {
+                    trust::ensure_trusted(&project_root, &config)?;
+                }
+                eprintln!("Shell with the environment of '{}'; exit to return", name);
+                enter_worktree(&current_dir, &env, config.enter_command.as_deref())?;
+                return Ok(());
+            }
+            let code = exec_in_worktree(&current_dir, &command, &env)?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+
+        Commands::Sync { resume } => {
+            let config = Config::load(&project_root)?;
+            let report = sync::sync_worktrees(&project_root, &config, resume)?;
+
+            print_section("Synced worktrees", &report.synced, |name| name.clone());
+            print_section("Skipped worktrees", &report.skipped, |(name, reason)| {
+                format!("{}\t{}", name, reason)
+            });
+            print_section("Aborted rebases", &report.aborted, |name| name.clone());
+            print_section(
+                "Reused recorded resolutions",
+                &report.reused,
+                |(name, paths)| format!("{}\t{}", name, paths.join(", ")),
+            );
+            eprint_section("Unresolved conflicts", &report.unresolved, |name| name.clone());
+            eprint_section("Failed to sync worktrees", &report.failed, |(name, error)| {
+                format!("{}\t{}", name, error)
+            });
+            if !report.unresolved.is_empty() {
+                eprintln!("Run `epiphyte sync --continue` after resolving them");
+            }
+            // Rebases left half done must not pass for a successful sync in scripts
+            if !report.unresolved.is_empty() || !report.failed.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::FetchAll { maintenance } => {
+            network::ensure_online("fetch-all")?;
+            println!("Fetching all remotes...");
+            fetch_all(&project_root)?;
+            if maintenance {
+                println!("Running git maintenance...");
+                run_maintenance(&project_root)?;
+            }
+
+            let config = Config::load(&project_root)?;
+            let worktrees = list_managed_worktrees(&project_root, &config)?;
+            if worktrees.is_empty() {
+                println!("No worktrees found");
+            } else {
+                let mut output = Vec::new();
+                let mut writer = TabWriter::new(&mut output);
+                for wt in worktrees {
+                    let tracking = match upstream_status(&project_root, &wt.branch)? {
+                        Some(status) => format!(
+                            "{}\t+{} -{}",
+                            status.upstream, status.ahead, status.behind
+                        ),
+                        None => "(no upstream)".to_string(),
+                    };
+                    writeln!(writer, "{}\t{}\t{}", wt.name, wt.branch, tracking)?;
+                }
+                writer.flush()?;
+                print!("{}", String::from_utf8_lossy(&output));
+            }
+        }
+
+        Commands::Each { template, command } => {
+            let config = Config::load(&project_root)?;
+            let command = match template {
+                Some(template) => {
+                    let Some(script) = config.each.get(&template) else {
+                        let known: Vec<&str> = config.each.keys().map(String::as_str).collect();
+                        if known.is_empty() {
+                            anyhow::bail!(
+                                "Unknown template '{}': the config has no [each] templates",
+                                template
+                            );
+                        }
+                        anyhow::bail!(
+                            "Unknown template '{}'. Available templates: {}",
+                            template,
+                            known.join(", ")
+                        );
+                    };
+                    trust::ensure_trusted(&project_root, &config)?;
+                    vec!["sh".to_string(), "-c".to_string(), script.clone()]
+                }
+                None => command,
+            };
+            let names: Vec<String> = list_managed_worktrees(&project_root, &config)?
+                .into_iter()
+                .map(|wt| wt.name)
+                .collect();
+            if names.is_empty() {
+                println!("No worktrees found");
+                return Ok(());
+            }
+            run_in_worktrees(&project_root, &config, &names, &command)?;
+        }
+
+        Commands::Find { branch } => match find_branch_checkout(&project_root, &branch)? {
+            Some((label, path)) => println!("{}\t{}", label, path.display()),
+            None => {
+                eprintln!("Branch '{}' is not checked out in any worktree", branch);
+                std::process::exit(1);
+            }
+        },
+
+        Commands::Audit => {
+            let config = Config::load(&project_root)?;
+            let findings = audit::audit(&project_root, &config)?;
+            if findings.is_empty() {
+                println!("No issues found");
+            } else {
+                eprint_section("Audit findings", &findings, |finding| {
+                    format!("{}\t{}", finding.location, finding.issue)
+                });
+                std::process::exit(1);
+            }
+        }
+
+        Commands::InstallHooks => {
+            let installed = git_hooks::install(&project_root)?;
+            print_section("Installed git hooks", &installed, |path| {
+                path.display().to_string()
+            });
+        }
+
+        Commands::InstallGitAlias {
+            aliases,
+            local,
+            shim,
+        } => {
+            let installed = git_alias::install_aliases(&project_root, &aliases, local)?;
+            print_section("Configured git aliases", &installed, |alias| alias.clone());
+            if let Some(dir) = shim {
+                let path = git_alias::install_shim(&dir)?;
+                println!("Wrote {}", path.display());
+                if !git_alias::is_on_path(&dir) {
+                    count_warnings(1);
+                    eprintln!(
+                        "Warning: {} is not on PATH, so git won't find `git epi` through it",
+                        dir.display()
+                    );
+                }
+            }
+        }
+
+        Commands::Review {
+            target,
+            sweep,
+            enter,
+            refresh,
+        } => {
+            let config = Config::load(&project_root)?;
+            if sweep {
+                let merged = review::merged(&project_root, &config, refresh)?;
+                if merged.is_empty() {
+                    println!("No merged review worktrees");
+                    return Ok(());
+                }
+                if !prompt::confirm(
+                    global_config.confirm,
+                    ChangeKind::Destructive,
+                    &format!(
+                        "Remove {} merged review worktree(s): {}?",
+                        merged.len(),
+                        merged.join(", ")
+                    ),
+                )? {
+                    println!("Removal cancelled");
+                    return Ok(());
+                }
+                let mut removed = Vec::new();
+                let mut failed = Vec::new();
+                for name in merged {
+                    match review::remove(&project_root, &name, &config) {
+                        Ok(path) => removed.push((name, path)),
+                        Err(err) => failed.push((name, err.to_string())),
+                    }
+                }
+                print_section("Removed worktrees", &removed, |(name, path)| {
+                    format!("{}\t{}", name, path.display())
+                });
+                eprint_section("Failed to remove worktrees", &failed, |(name, error)| {
+                    format!("{}\t{}", name, error)
+                });
+                return Ok(());
+            }
+
+            ensure_on_main_branch(&project_root, &config.main_branch)?;
+            let target = target.unwrap_or_default();
+            let added = review::create(&project_root, &target, &config, refresh)?;
+            println!(
+                "Created review worktree '{}' at {}",
+                added.worktree.name,
+                added.worktree.path.display()
+            );
+            print_link_problems(&added.links);
+            if enter {
+                let worktree = &added.worktree;
+                enter_managed_worktree(&project_root, worktree, &worktree.path, &config)?;
+            }
+        }
+
+        Commands::BenchIo => {
+            let caps = fscaps::probe(&project_root)?;
+            let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+            let mut writer = TabWriter::new(std::io::stdout());
+            writeln!(writer, "symlinks\t{}", yes_no(caps.symlinks))?;
+            writeln!(writer, "reflinks\t{}", yes_no(caps.reflinks))?;
+            writeln!(writer, "hardlinks\t{}", yes_no(caps.hardlinks))?;
+            writeln!(writer, "case sensitive\t{}", yes_no(caps.case_sensitive))?;
+            writer.flush()?;
+
+            let config = Config::load(&project_root)?;
+            let mut notes = Vec::new();
+            if !caps.symlinks {
+                notes.push("symlink entries are copied instead");
+            }
+            if caps.reflinks {
+                notes.push("copies are cloned, sharing blocks until modified");
+            }
+            if config.copy.dedup && !caps.hardlinks {
+                notes.push("copy.dedup is ignored, plain copies are made instead");
+            }
+            print_section("Linking", &notes, |note| note.to_string());
+            if !caps.case_sensitive {
+                let mut seen = BTreeMap::new();
+                let mut collisions = Vec::new();
+                for entry in &config.files {
+                    if let Some(other) = seen.insert(entry.path.to_lowercase(), &entry.path) {
+                        collisions.push(format!("{}\t{}", other, entry.path));
+                    }
+                }
+                eprint_section("Entries that collide on this filesystem", &collisions, |line| {
+                    line.clone()
+                });
+            }
+        }
+
+        Commands::Trust => {
+            let config = Config::load(&project_root)?;
+            let commands = config.commands();
+            if commands.is_empty() {
+                println!("The config runs no commands");
+                return Ok(());
+            }
+            print_section("Commands", &commands, |(key, command)| {
+                format!("{}\t{}", key, command)
+            });
+            trust::trust(&project_root, &config)?;
+            println!("Trusted {} command(s) for {}", commands.len(), project_root.display());
+        }
+
+        Commands::Doctor { fix, rename_dirs } => {
+            let config = Config::load(&project_root)?;
+            let diagnoses = doctor::diagnose(&project_root, &config)?;
+            let renames = doctor::renamed_branches(&project_root, &config)?;
+            let missing = doctor::missing_worktrees(&project_root, &config)?;
+            if diagnoses.is_empty() && renames.is_empty() && missing.is_empty() {
+                println!("No problems found");
+                return Ok(());
+            }
+            eprint_section("Configured files", &diagnoses, |diagnosis| {
+                format!("{}\t{}", diagnosis.entry, diagnosis.problem)
+            });
+            let mut unresolved = Vec::new();
+            for missing in missing {
+                let action = if fix {
+                    doctor::missing_action(&missing, global_config.on_missing_worktree, false)?
+                } else {
+                    None
+                };
+                match action {
+                    Some(MissingAction::Recreate) => {
+                        doctor::recreate_missing(&project_root, &missing, &config)?;
+                        println!("Recreated worktree '{}'", missing.name);
+                    }
+                    Some(MissingAction::Prune) => {
+                        doctor::prune_missing(&project_root, &missing)?;
+                        println!("Pruned worktree '{}'", missing.name);
+                    }
+                    Some(MissingAction::Pick) | None => unresolved.push(missing),
+                }
+            }
+            eprint_section("Missing worktree directories", &unresolved, |missing| {
+                format!("{}\t{}", missing.name, missing.path.display())
+            });
+            if !unresolved.is_empty() && !fix {
+                eprintln!(
+                    "Run `epiphyte doctor --fix` to recreate or prune them, as \
+                    on_missing_worktree in the global config says"
+                );
+            }
+            if fix {
+                let mut repaired = Vec::new();
+                for rename in &renames {
+                    let name = doctor::repair_branch(&project_root, rename, rename_dirs)?;
+                    repaired.push((rename, name));
+                }
+                print_section("Repaired worktrees", &repaired, |(rename, name)| {
+                    let mut line = format!("{}\t{}", rename.worktree, rename.current);
+                    if *name != rename.worktree {
+                        line.push_str(&format!("\tmoved to {}", name));
+                    }
+                    line
+                });
+            } else if !renames.is_empty() {
+                eprint_section("Renamed branches", &renames, |rename| {
+                    format!("{}\t{} -> {}", rename.worktree, rename.recorded, rename.current)
+                });
+                eprintln!(
+                    "Run `epiphyte doctor --fix` to update their metadata, adding \
+                    --rename-dirs to also rename their directories"
+                );
+            }
+            if !diagnoses.is_empty() || !unresolved.is_empty() || !fix {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Apply { plan: dry_run, json } => {
+            let config = Config::load(&project_root)?;
+            let plan = manifest::plan_apply(&project_root, &config)?;
+            if dry_run {
+                let actions = plan.actions();
+                if json {
+                    let output = serde_json::json!({ "actions": actions });
+                    println!("{}", serde_json::to_string_pretty(&output)?);
+                } else if actions.is_empty() {
+                    println!("Nothing to do");
+                } else {
+                    for action in actions {
+                        match action {
+                            manifest::PlannedAction::Create { name, branch } => match branch {
+                                Some(branch) => println!("create\t{}\t{}", name, branch),
+                                None => println!("create\t{}", name),
+                            },
+                            manifest::PlannedAction::Keep { name } => println!("keep\t{}", name),
+                            manifest::PlannedAction::Extra { name, path } => {
+                                println!("extra\t{}\t{}", name, path.display())
+                            }
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            let report = manifest::apply(&project_root, &config, plan)?;
+
+            print_section("Created worktrees", &report.created, |(name, path)| {
+                format!("{}\t{}", name, path.display())
+            });
+            print_section("Existing worktrees", &report.present, |name| name.clone());
+            print_section("Undeclared worktrees", &report.extra, |(name, path)| {
+                format!("{}\t{}", name, path.display())
+            });
+            eprint_section("Failed to create worktrees", &report.failed, |(name, error)| {
+                format!("{}\t{}", name, error)
+            });
+        }
+
+        Commands::Provision { trust, json } => {
+            let config = Config::load(&project_root)?;
+            if trust {
+                trust::trust(&project_root, &config)?;
+            } else {
+                trust::ensure_trusted(&project_root, &config)?;
+            }
+            let report = manifest::provision(&project_root, &config)?;
+            if json {
+                count_warnings(report.links.problems());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_section("Created worktrees", &report.created, |name| name.clone());
+                print_section("Relinked worktrees", &report.relinked, |name| name.clone());
+                print_provision_problems(&report);
+            }
+            if !report.ok {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Watch { once, json } => {
+            if !once {
+                anyhow::bail!("Only --once is supported for watch");
+            }
+            let config = Config::load(&project_root)?;
+            trust::ensure_trusted(&project_root, &config)?;
+            let report = manifest::reconcile(&project_root, &config)?;
+            if json {
+                count_warnings(report.provision.links.problems());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                if !report.changed() {
+                    println!("No drift found");
+                }
+                print_section("Created worktrees", &report.provision.created, |name| {
+                    name.clone()
+                });
+                print_section("Recorded renamed branches", &report.repaired, |name| {
+                    name.clone()
+                });
+                print_link_changes(&report.provision.links);
+                print_section("Undeclared worktrees, left alone", &report.undeclared, |name| {
+                    name.clone()
+                });
+                print_provision_problems(&report.provision);
+            }
+            if !report.provision.ok {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Env(EnvCommands::Diff { a, b, json }) => {
+            let config = Config::load(&project_root)?;
+            let a = find_worktree(&project_root, &a)?;
+            let b = find_worktree(&project_root, &b)?;
+            let differences = env::diff(&project_root, &a, &b, &config)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&differences)?);
+            } else if differences.is_empty() {
+                println!("No differences between '{}' and '{}'", a.name, b.name);
+            } else {
+                let mut writer = TabWriter::new(std::io::stdout());
+                writeln!(writer, "\t\t{}\t{}", a.name, b.name)?;
+                for difference in &differences {
+                    let missing = || "-".to_string();
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}",
+                        difference.kind,
+                        difference.key,
+                        difference.a.clone().unwrap_or_else(missing),
+                        difference.b.clone().unwrap_or_else(missing)
+                    )?;
+                }
+                writer.flush()?;
+            }
+        }
+
+        Commands::Plugin(args) => {
+            let (name, args) = args.split_first().context("No command given")?;
+            let code = plugin::run_command(&project_root, name, args)?;
+            std::process::exit(code);
+        }
+
+        Commands::Plumbing(command) => match command {
+            PlumbingCommands::ResolveRoot => println!("{}", project_root.display()),
+            PlumbingCommands::ResolveWorktree { name } => {
+                let name = resolve_worktree_name(&project_root, name.as_deref())?;
+                let wt = find_worktree(&project_root, &name)?;
+                println!("{}\t{}\t{}", wt.name, wt.branch, wt.path.display());
+            }
+            PlumbingCommands::LinkOne { worktree, path } => {
+                let config = Config::load(&project_root)?;
+                let report = link_one(&project_root, &worktree, &path, &config)?;
+                if let Some(failure) = report.failed.first() {
+                    anyhow::bail!("{}: {}", failure.path.display(), failure.error);
+                }
+                if let Some(src) = report.missing.first() {
+                    anyhow::bail!("Source does not exist: {}", src.display());
+                }
+                let outcomes = [
+                    ("created", &report.created),
+                    ("replaced", &report.replaced),
+                    ("repaired", &report.repaired),
+                    ("unchanged", &report.unchanged),
+                ];
+                for (outcome, linked) in outcomes {
+                    for linked in linked {
+                        println!("{}\t{}", outcome, linked.path.display());
+                    }
+                }
+            }
+            PlumbingCommands::ManifestDump => {
+                let config = Config::load(&project_root)?;
+                let manifest = serde_json::json!({
+                    "root": project_root,
+                    "main_branch": config.main_branch,
+                    "files": config.files,
+                    "worktrees": list_worktrees(&project_root)?,
                 });
+                println!("{}", serde_json::to_string_pretty(&manifest)?);
             }
+        },
 
-            print_section("Skipped worktrees", &report.skipped, |item| {
-                format!("{}\t{}", item.path.display(), item.reason)
-            });
+        Commands::Group(group_cmd) => {
+            let metadata = Metadata::load(&project_root)?;
 
-            eprint_section("Failed to import worktrees", &report.failed, |item| {
-                format!("{}\t{}", item.path.display(), item.error)
-            });
-        }
+            match group_cmd {
+                GroupCommands::List => {
+                    let groups = metadata.groups();
+                    if groups.is_empty() {
+                        println!("No groups found");
+                    }
+                    for (group, members) in groups {
+                        println!("{}\t{}", group, members.join(", "));
+                    }
+                }
 
-        Commands::Enter { name } => {
-            let name = match name {
-                Some(name) => name,
-                None => match select_worktree_name(&project_root)? {
-                    Some(name) => name,
-                    None => return Ok(()),
-                },
-            };
-            if detect_current_worktree(&project_root)?.as_deref() == Some(name.as_str()) {
-                return Ok(());
-            }
-            let path = get_worktree_path(&project_root, &name)?;
-            println!("Entering worktree '{}' at {}", name, path.display());
-            enter_worktree(&path)?;
-        }
+                GroupCommands::Enter { group } => {
+                    let members = group_members(&metadata, &group)?;
+                    let name = if members.len() == 1 {
+                        members[0].clone()
+                    } else if let Some(name) =
+                        prompt::env_choice(prompt::SELECT_WORKTREE, &members)?
+                    {
+                        name
+                    } else {
+                        match Select::new("Select worktree", members).prompt() {
+                            Ok(name) => name,
+                            Err(
+                                InquireError::OperationCanceled
+                                | InquireError::OperationInterrupted,
+                            ) => return Ok(()),
+                            Err(err) => {
+                                return Err(err).context("Failed to prompt for worktree selection")
+                            }
+                        }
+                    };
+                    let config = Config::load(&project_root)?;
+                    let worktree = find_worktree(&project_root, &name)?;
+                    println!("Entering worktree '{}' at {}", name, worktree.path.display());
+                    enter_managed_worktree(&project_root, &worktree, &worktree.path, &config)?;
+                }
 
-        Commands::Root => {
-            let current_dir =
-                std::env::current_dir().context("Failed to get current directory")?;
-            if current_dir == project_root {
-                return Ok(());
+                GroupCommands::Each { group, command } => {
+                    let config = Config::load(&project_root)?;
+                    let names = group_members(&metadata, &group)?;
+                    run_in_worktrees(&project_root, &config, &names, &command)?;
+                }
+
+                GroupCommands::Remove { group, force } => {
+                    let members = group_members(&metadata, &group)?;
+                    // With --force, local changes are discarded without asking about them
+                    if !prompt::confirm(
+                        global_config.confirm,
+                        ChangeKind::Destructive,
+                        &format!(
+                            "Remove {} worktree(s) in group '{}': {}?",
+                            members.len(),
+                            group,
+                            members.join(", ")
+                        ),
+                    )? {
+                        println!("Removal cancelled");
+                        return Ok(());
+                    }
+
+                    let config = Config::load(&project_root)?;
+                    let mut removed = Vec::new();
+                    let mut failed = Vec::new();
+                    for name in members {
+                        match remove_worktree(&project_root, &name, force, &config) {
+                            Ok(path) => removed.push((name, path)),
+                            Err(err) => failed.push((name, err.to_string())),
+                        }
+                    }
+                    print_section("Removed worktrees", &removed, |(name, path)| {
+                        format!("{}\t{}", name, path.display())
+                    });
+                    eprint_section("Failed to remove worktrees", &failed, |(name, error)| {
+                        format!("{}\t{}", name, error)
+                    });
+                }
             }
-            println!("Entering repo root at {}", project_root.display());
-            enter_worktree(&project_root)?;
         }
 
         Commands::Files(files_cmd) => {
@@ -222,25 +2681,49 @@ fn main() -> Result<()> {
                 FilesCommands::Add {
                     path,
                     copy,
+                    symlink,
+                    secret,
                     ignored,
+                    to,
+                    pick,
+                    no_link,
+                    json,
                 } => {
-                    let link_type = if copy {
-                        LinkType::Copy
+                    // None picks by the link rules
+                    let link_type = if secret.is_some() {
+                        Some(LinkType::Secret)
+                    } else if copy {
+                        Some(LinkType::Copy)
+                    } else if symlink {
+                        Some(LinkType::Symlink)
                     } else {
-                        LinkType::Symlink
+                        None
                     };
 
-                    let paths = match (ignored, path) {
+                    let entries = |paths: Vec<String>| -> Result<Vec<FileEntry>> {
+                        paths
+                            .into_iter()
+                            .map(|path| {
+                                Ok(FileEntry {
+                                    link_type: match &link_type {
+                                        Some(link_type) => link_type.clone(),
+                                        None => config.link_type_for(&path)?,
+                                    },
+                                    path,
+                                    secret: secret.clone(),
+                                })
+                            })
+                            .collect()
+                    };
+                    let new_entries = match (ignored, path) {
                         (true, Some(_)) => {
                             anyhow::bail!("--ignored cannot be used with a path")
                         }
-                        (true, None) => select_ignored_files(&project_root, &config)?,
+                        (true, None) => entries(select_ignored_files(&project_root, &config)?)?,
                         (false, Some(path)) => {
                             if config.files.iter().any(|f| f.path == path) {
-                                anyhow::bail!(
-                                    "File '{}' is already in the configuration",
-                                    path
-                                );
+                                return Err(messages::FILE_ALREADY_CONFIGURED
+                                    .error(&[("path", &path)]));
                             }
                             if is_path_tracked(&project_root, &path)? {
                                 anyhow::bail!(
@@ -248,87 +2731,144 @@ fn main() -> Result<()> {
                                     path
                                 );
                             }
-                            vec![path]
+                            entries(vec![path])?
+                        }
+                        (false, None)
+                            if std::io::stdin().is_terminal()
+                                || prompt::has_env_answer(prompt::SELECT_FILES) =>
+                        {
+                            browse_untracked_files(&project_root, &config, link_type.as_ref())?
                         }
                         (false, None) => {
                             anyhow::bail!(
-                                "Path is required unless --ignored is used"
+                                "Path is required unless --ignored is used or stdin is a terminal"
                             )
                         }
                     };
 
-                    if paths.is_empty() {
+                    if new_entries.is_empty() {
                         return Ok(());
                     }
 
-                    let new_entries: Vec<FileEntry> = paths
-                        .into_iter()
-                        .map(|path| FileEntry {
-                            path,
-                            link_type: link_type.clone(),
-                        })
-                        .collect();
+                    let targets = if no_link {
+                        Some(Vec::new())
+                    } else if pick {
+                        match select_link_targets(&project_root, &config)? {
+                            Some(targets) => Some(targets),
+                            None => return Ok(()),
+                        }
+                    } else if !to.is_empty() {
+                        for name in &to {
+                            get_worktree_path(&project_root, name)?;
+                        }
+                        Some(to)
+                    } else {
+                        None
+                    };
+
+                    let (latest, new_entries) = Config::update(&project_root, |latest| {
+                        // Another `files add` may have saved some of them meanwhile
+                        let added: Vec<FileEntry> = new_entries
+                            .iter()
+                            .filter(|entry| !latest.files.iter().any(|f| f.path == entry.path))
+                            .cloned()
+                            .collect();
+                        latest.files.extend(added.clone());
+                        Ok(added)
+                    })?;
+                    config = latest;
+                    if new_entries.is_empty() && !json {
+                        println!("Files already added to configuration meanwhile");
+                        return Ok(());
+                    }
                     let count = new_entries.len();
                     let single_path = new_entries.first().map(|entry| entry.path.clone());
 
-                    config.files.extend(new_entries.clone());
-                    config.save(&project_root)?;
-                    if count == 1 {
-                        println!(
-                            "Added '{}' to configuration",
-                            single_path.unwrap()
-                        );
-                    } else {
-                        println!("Added {} file(s) to configuration", count);
+                    if !json {
+                        if count == 1 {
+                            println!(
+                                "Added '{}' to configuration",
+                                single_path.unwrap()
+                            );
+                        } else {
+                            println!("Added {} file(s) to configuration", count);
+                        }
                     }
 
-                    let report =
-                        link_entries_to_worktrees(&project_root, &new_entries)?;
-                    if report.linked.is_empty() {
-                        println!("No worktrees updated");
-                    } else {
-                        println!("Linked files to worktrees:");
-                        for (name, linked_path) in report.linked {
-                            println!("{}\t{}", name, linked_path.display());
-                        }
+                    let report = link_entries_to_worktrees(
+                        &project_root,
+                        &new_entries,
+                        targets.as_deref(),
+                        &config,
+                    )?;
+                    if json {
+                        let added: Vec<&str> =
+                            new_entries.iter().map(|entry| entry.path.as_str()).collect();
+                        count_warnings(report.problems());
+                        let output = serde_json::json!({ "added": added, "links": report });
+                        println!("{}", serde_json::to_string_pretty(&output)?);
+                        return Ok(());
                     }
-                    if !report.failed.is_empty() {
-                        eprintln!("Warning: failed to link some files:");
-                        for (name, failed_path, error) in report.failed {
-                            eprintln!(
-                                "{}\t{}\t{}",
-                                name,
-                                failed_path.display(),
-                                error
-                            );
-                        }
+                    if !report.changed() {
+                        println!("No worktrees updated");
                     }
+                    print_link_changes(&report);
+                    print_link_problems(&report);
                 }
 
-                FilesCommands::Remove { path } => {
-                    let initial_len = config.files.len();
-                    config.files.retain(|f| f.path != path);
-                    if config.files.len() == initial_len {
-                        anyhow::bail!("File '{}' not found in configuration", path);
+                FilesCommands::Remove { path, json } => {
+                    let (latest, ()) = Config::update(&project_root, |latest| {
+                        let initial_len = latest.files.len();
+                        latest.files.retain(|f| f.path != path);
+                        if latest.files.len() == initial_len {
+                            return Err(messages::FILE_NOT_CONFIGURED.error(&[("path", &path)]));
+                        }
+                        Ok(())
+                    })?;
+                    config = latest;
+
+                    let report = remove_symlinks_from_worktrees(&project_root, &path, &config)?;
+                    if json {
+                        count_warnings(report.failed.len());
+                        let output = serde_json::json!({ "removed": path, "symlinks": report });
+                        println!("{}", serde_json::to_string_pretty(&output)?);
+                        return Ok(());
                     }
-                    config.save(&project_root)?;
                     println!("Removed '{}' from configuration", path);
-
-                    let report = remove_symlinks_from_worktrees(&project_root, &path)?;
                     if report.removed.is_empty() {
                         println!("No symlinks removed from worktrees");
                     } else {
                         println!("Removed symlinks from worktrees:");
-                        for (name, removed_path) in report.removed {
-                            println!("{}\t{}", name, removed_path.display());
+                        for removed in &report.removed {
+                            println!("{}\t{}", removed.worktree, removed.path.display());
                         }
                     }
-                    if !report.failed.is_empty() {
-                        eprintln!("Warning: failed to remove some symlinks:");
-                        for (name, failed_path, error) in report.failed {
-                            eprintln!("{}\t{}\t{}", name, failed_path.display(), error);
-                        }
+                    eprint_section("Failed to remove symlinks", &report.failed, |failure| {
+                        format!(
+                            "{}\t{}\t{}",
+                            failure.worktree,
+                            failure.path.display(),
+                            failure.error
+                        )
+                    });
+                }
+
+                FilesCommands::Template(TemplateCommands::Init { path }) => {
+                    if is_path_tracked(&project_root, &path)? {
+                        anyhow::bail!(
+                            "File '{}' is tracked by git; only untracked files can be templates",
+                            path
+                        );
                     }
+                    let init = template::init(&project_root, &mut config, &path)?;
+                    println!("Created template {}", init.template.display());
+                    print_section("Replaced values", &init.replaced, |(value, variable)| {
+                        format!("{}\t{}", value, variable)
+                    });
+                    let report =
+                        link_entries_to_worktrees(&project_root, &[init.entry], None, &config)?;
+                    print_link_changes(&report);
+                    print_link_problems(&report);
                 }
 
                 FilesCommands::List => {
@@ -338,11 +2878,7 @@ fn main() -> Result<()> {
                         let mut output = Vec::new();
                         let mut writer = TabWriter::new(&mut output);
                         for entry in &config.files {
-                            let link_type = match entry.link_type {
-                                LinkType::Copy => "copy",
-                                LinkType::Symlink => "symlink",
-                            };
-                            writeln!(writer, "{}\t[{}]", entry.path, link_type)?;
+                            writeln!(writer, "{}\t[{}]", entry.path, entry.link_type.name())?;
                         }
                         writer.flush()?;
                         print!("{}", String::from_utf8_lossy(&output));
@@ -350,11 +2886,436 @@ fn main() -> Result<()> {
                 }
             }
         }
+
+        Commands::Config(ConfigCommands::Schema { format, global }) => match format {
+            SchemaFormat::Toml => print!("{}", schema::render_toml(global)),
+            SchemaFormat::Json => {
+                let schema = schema::render_json(global)?;
+                println!("{}", serde_json::to_string_pretty(&schema)?);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// The directory `enter` starts in: `dir` inside the worktree, remembered in the metadata
+/// for next time, or else the remembered one if it still exists. With `last_dir`, the
+/// directory the worktree's last shell exited in comes first.
+fn resolve_enter_dir(
+    project_root: &Path,
+    name: &str,
+    worktree_path: &Path,
+    dir: Option<PathBuf>,
+    last_dir: bool,
+) -> Result<PathBuf> {
+    if let Some(path) = last_dir
+        .then(|| exit_dir(project_root, name, worktree_path))
+        .flatten()
+    {
+        return Ok(path);
+    }
+    let Some(dir) = dir else {
+        let last_dir = Metadata::load(project_root)?
+            .get(name)
+            .and_then(|meta| meta.last_dir.clone());
+        return Ok(last_dir
+            .map(|dir| worktree_path.join(dir))
+            .filter(|path| path.is_dir())
+            .unwrap_or_else(|| worktree_path.to_path_buf()));
+    };
+
+    if !dir
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        anyhow::bail!("--dir must be a relative path inside the worktree: {}", dir.display());
+    }
+    let dir: PathBuf = dir
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    let path: PathBuf = worktree_path.components().chain(dir.components()).collect();
+    if !path.is_dir() {
+        anyhow::bail!("No directory {} in worktree '{}'", dir.display(), name);
+    }
+    // Remembering is a convenience; entering still works where the state isn't writable
+    let remembered = Metadata::load(project_root).and_then(|mut metadata| {
+        metadata.entry(name).last_dir =
+            (!dir.as_os_str().is_empty()).then(|| dir.to_string_lossy().to_string());
+        metadata.save(project_root)
+    });
+    if let Err(err) = remembered {
+        eprintln!("Warning: failed to remember the directory: {:#}", err);
+    }
+    Ok(path)
+}
+
+/// The worktree to enter instead of `name` when its directory was deleted, after
+/// recreating or pruning it as `on_missing_worktree` says. None when there is nothing
+/// left to enter.
+fn resolve_missing_target(
+    project_root: &Path,
+    name: String,
+    global_config: &GlobalConfig,
+) -> Result<Option<String>> {
+    if is_root_name(project_root, &name) || managed_path(project_root, &name).exists() {
+        return Ok(Some(name));
+    }
+    let config = Config::load(project_root)?;
+    let Some(missing) = doctor::missing_worktrees(project_root, &config)?
+        .into_iter()
+        .find(|missing| missing.name == name)
+    else {
+        return Ok(Some(name));
+    };
+    // Without an action, entering fails below with the usual error
+    match doctor::missing_action(&missing, global_config.on_missing_worktree, true)? {
+        None => Ok(Some(name)),
+        Some(MissingAction::Recreate) => {
+            doctor::recreate_missing(project_root, &missing, &config)?;
+            // On stderr, as `enter -p` prints the path for the shell to cd into
+            eprintln!("Recreated worktree '{}' at {}", name, missing.path.display());
+            Ok(Some(name))
+        }
+        Some(MissingAction::Prune) => {
+            doctor::prune_missing(project_root, &missing)?;
+            eprintln!("Pruned worktree '{}'", name);
+            Ok(None)
+        }
+        Some(MissingAction::Pick) => select_worktree_name(project_root),
+    }
+}
+
+/// Where the shell snippet of `shell-init` records the directory a worktree's shell
+/// exited in, passed to the shell as EPI_LAST_DIR_FILE.
+fn exit_dir_file(project_root: &Path, name: &str) -> PathBuf {
+    get_state_dir(project_root).join("last-dir").join(name)
+}
+
+/// The recorded exit directory of the worktree's shell, if it is still a directory of
+/// the worktree.
+fn exit_dir(project_root: &Path, name: &str, worktree_path: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(exit_dir_file(project_root, name)).ok()?;
+    let path = fs::canonicalize(content.trim_end_matches('\n')).ok()?;
+    let worktree_path = fs::canonicalize(worktree_path).ok()?;
+    (path.is_dir() && path.starts_with(&worktree_path)).then_some(path)
+}
+
+fn shell_init(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"if [ -n "$EPI_LAST_DIR_FILE" ]; then
+    trap 'pwd > "$EPI_LAST_DIR_FILE" 2>/dev/null' EXIT
+fi
+"#
+        }
+        Shell::Zsh => {
+            r#"if [[ -n "$EPI_LAST_DIR_FILE" ]]; then
+    _epi_save_dir() { pwd > "$EPI_LAST_DIR_FILE" 2>/dev/null }
+    zshexit_functions+=(_epi_save_dir)
+fi
+"#
+        }
+        Shell::Fish => {
+            r#"if set -q EPI_LAST_DIR_FILE
+    function __epi_save_dir --on-event fish_exit
+        pwd > $EPI_LAST_DIR_FILE 2>/dev/null
+    end
+end
+"#
+        }
+    }
+}
+
+/// Enter a managed worktree with its environment, printing the MOTD first when enabled.
+fn enter_managed_worktree(
+    project_root: &Path,
+    worktree: &Worktree,
+    dir: &Path,
+    config: &Config,
+) -> Result<()> {
+    if config.enter_command.is_some() {
+        trust::ensure_trusted(project_root, config)?;
+    }
+    let mut env = env::worktree_env(project_root, worktree, config, None)?;
+    let exit_dir_file = exit_dir_file(project_root, &worktree.name);
+    // The state dir may not be writable; the shell then just doesn't record its directory
+    if exit_dir_file.parent().is_some_and(|dir| fs::create_dir_all(dir).is_ok()) {
+        env.push((
+            "EPI_LAST_DIR_FILE".to_string(),
+            exit_dir_file.display().to_string(),
+        ));
+    }
+    if config.motd {
+        match motd::render(project_root, worktree, config) {
+            Ok(banner) => print!("{}", banner),
+            Err(err) => eprintln!("Warning: failed to summarize worktree: {:#}", err),
+        }
     }
+    enter_worktree(dir, &env, config.enter_command.as_deref())
+}
 
+/// Run `command` in each named worktree in turn, exiting with status 1 after reporting
+/// the worktrees where it failed.
+fn run_in_worktrees(
+    project_root: &Path,
+    config: &Config,
+    names: &[String],
+    command: &[String],
+) -> Result<()> {
+    let mut failed = Vec::new();
+    for name in names {
+        println!("==> {}", name);
+        let worktree = find_worktree(project_root, name)?;
+        let env = env::worktree_env(project_root, &worktree, config, None)?;
+        let code = exec_recorded(project_root, &worktree, "each", command, &env)?;
+        if code != 0 {
+            failed.push((name, code));
+        }
+    }
+    eprint_section("Command failed in worktrees", &failed, |(name, code)| {
+        format!("{}\texit status {}", name, code)
+    });
+    if !failed.is_empty() {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
+/// Run `command` in the worktree and add it to the command history under `source`.
+fn exec_recorded(
+    project_root: &Path,
+    worktree: &Worktree,
+    source: &str,
+    command: &[String],
+    env: &[(String, String)],
+) -> Result<i32> {
+    let started = Instant::now();
+    let result = exec_in_worktree(&worktree.path, command, env);
+    history::record(
+        project_root,
+        &history::CommandRecord::new(
+            &worktree.name,
+            source,
+            &command.join(" "),
+            started.elapsed(),
+            result.as_ref().ok().copied(),
+        ),
+    );
+    result
+}
+
+fn group_members(metadata: &Metadata, group: &str) -> Result<Vec<String>> {
+    let members = metadata.group_members(group);
+    if members.is_empty() {
+        anyhow::bail!("Group '{}' has no worktrees", group);
+    }
+    Ok(members)
+}
+
+/// Gather the status of every worktree in parallel. Streaming prints each as an NDJSON
+/// line in the order they finish; otherwise they are printed as one array in list order.
+fn print_worktree_statuses(project_root: &Path, config: &Config, stream: bool) -> Result<()> {
+    let worktrees = list_managed_worktrees(project_root, config)?;
+    let metadata = Metadata::load(project_root)?;
+    let locks = worktree_locks(project_root)?;
+    // A few workers share the queue, so that hundreds of worktrees don't mean hundreds
+    // of threads each running git
+    let workers = std::thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .min(worktrees.len());
+    let queue = std::sync::Mutex::new(worktrees.into_iter().enumerate());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (queue, metadata, locks) = (&queue, &metadata, &locks);
+            scope.spawn(move || loop {
+                let next = queue.lock().map(|mut queue| queue.next());
+                let Ok(Some((index, wt))) = next else {
+                    break;
+                };
+                let status = worktree_status(project_root, wt, metadata, locks);
+                if sender.send((index, status)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut statuses = Vec::new();
+        let mut stdout = std::io::stdout().lock();
+        for (index, status) in receiver {
+            let status = status?;
+            if stream {
+                let line = serde_json::to_string(&status)?;
+                match writeln!(stdout, "{}", line).and_then(|()| stdout.flush()) {
+                    // The consumer stopped reading; the remaining statuses are not needed
+                    Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                    result => result?,
+                }
+            } else {
+                statuses.push((index, status));
+            }
+        }
+        if !stream {
+            statuses.sort_by_key(|(index, _)| *index);
+            let statuses: Vec<_> = statuses.into_iter().map(|(_, status)| status).collect();
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&statuses)?)?;
+        }
+        Ok(())
+    })
+}
+
+/// Optional columns of `list`.
+struct ListColumns<'a> {
+    /// The first line of each branch's description
+    long: bool,
+    /// The pull request and CI status of each branch, looked up through this cache
+    status: Option<&'a cache::Cache>,
+}
+
+/// List worktrees, marking the `current` one with `*` and sorting it first. Inside the
+/// main checkout, `@root` is listed as current. The optional `columns` follow the path.
+fn render_worktree_list(
+    project_root: &Path,
+    mut worktrees: Vec<Worktree>,
+    group_by: Option<ListGrouping>,
+    columns: ListColumns,
+    current: Option<&str>,
+) -> Result<String> {
+    if worktrees.is_empty() {
+        return Ok("No worktrees found\n".to_string());
+    }
+    if current == Some(ROOT_WORKTREE) {
+        worktrees.insert(0, find_worktree(project_root, ROOT_WORKTREE)?);
+    }
+    worktrees.sort_by_key(|wt| Some(wt.name.as_str()) != current);
+    let locks = worktree_locks(project_root)?;
+    let mut descriptions = BTreeMap::new();
+    if columns.long {
+        for wt in &worktrees {
+            if let Some(description) = branch_description(project_root, &wt.branch)? {
+                let summary = description.lines().next().unwrap_or_default().to_string();
+                descriptions.insert(wt.name.clone(), summary);
+            }
+        }
+    }
+    let mut statuses = BTreeMap::new();
+    if let Some(cache) = columns.status {
+        for wt in &worktrees {
+            let status = review::branch_status(project_root, cache, &wt.branch);
+            statuses.insert(wt.name.clone(), status.unwrap_or_else(|| "-".to_string()));
+        }
+    }
+    let row = |wt: &Worktree| {
+        let marker = match current {
+            Some(name) if name == wt.name => "* ",
+            Some(_) => "  ",
+            None => "",
+        };
+        let mut row = format!("{}{}\t{}\t{}", marker, wt.name, wt.branch, wt.path.display());
+        match locks.get(&wt.path).map(String::as_str) {
+            Some("") => row.push_str("\t(locked)"),
+            Some(reason) => row.push_str(&format!("\t(locked: {})", reason)),
+            None => {}
+        }
+        if let Some(status) = statuses.get(&wt.name) {
+            row.push_str(&format!("\t{}", status));
+        }
+        if let Some(description) = descriptions.get(&wt.name) {
+            row.push_str(&format!("\t{}", description));
+        }
+        row
+    };
+
+    let mut output = Vec::new();
+    let mut writer = TabWriter::new(&mut output);
+    let Some(group_by) = group_by else {
+        for wt in &worktrees {
+            writeln!(writer, "{}", row(wt))?;
+        }
+        writer.flush()?;
+        drop(writer);
+        return Ok(highlight_current(&String::from_utf8_lossy(&output)));
+    };
+
+    let metadata = Metadata::load(project_root)?;
+    let mut sections: BTreeMap<String, Vec<&Worktree>> = BTreeMap::new();
+    let mut ungrouped = Vec::new();
+    for wt in &worktrees {
+        let key = match group_by {
+            ListGrouping::Prefix => wt
+                .branch
+                .split_once('/')
+                .map(|(prefix, _)| prefix.to_string()),
+            ListGrouping::Group => metadata.get(&wt.name).and_then(|meta| meta.group.clone()),
+        };
+        match key {
+            Some(key) => sections.entry(key).or_default().push(wt),
+            None => ungrouped.push(wt),
+        }
+    }
+    let mut sections: Vec<(String, Vec<&Worktree>)> = sections.into_iter().collect();
+    if !ungrouped.is_empty() {
+        let label = match group_by {
+            ListGrouping::Prefix => "(no prefix)",
+            ListGrouping::Group => "(no group)",
+        };
+        sections.push((label.to_string(), ungrouped));
+    }
+
+    for (index, (label, members)) in sections.iter().enumerate() {
+        if index > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "{} ({})", label, members.len())?;
+        for wt in members {
+            writeln!(writer, "  {}", row(wt))?;
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+    Ok(highlight_current(&String::from_utf8_lossy(&output)))
+}
+
+/// Color the line marked current when printing to a terminal, unless NO_COLOR is set.
+fn highlight_current(listing: &str) -> String {
+    if !std::io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some() {
+        return listing.to_string();
+    }
+    listing
+        .lines()
+        .map(|line| match line.trim_start().starts_with("* ") {
+            true => format!("\x1b[1;32m{}\x1b[0m\n", line),
+            false => format!("{}\n", line),
+        })
+        .collect()
+}
+
+/// Replace the subcommand with its alias expansion from the global config.
+/// Built-in commands and their aliases always take precedence.
+fn expand_alias(args: Vec<String>, global_config: &GlobalConfig) -> Vec<String> {
+    let Some(first) = args.get(1) else {
+        return args;
+    };
+    let Some(expansion) = global_config.alias.get(first) else {
+        return args;
+    };
+    let is_builtin = Cli::command().get_subcommands().any(|cmd| {
+        cmd.get_name() == first || cmd.get_all_aliases().any(|alias| alias == first)
+    });
+    if is_builtin {
+        return args;
+    }
+
+    let mut expanded = vec![args[0].clone()];
+    expanded.extend(expansion.split_whitespace().map(|arg| arg.to_string()));
+    expanded.extend(args.into_iter().skip(2));
+    expanded
+}
+
 fn select_ignored_files(
     project_root: &Path,
     config: &Config,
@@ -369,7 +3330,10 @@ fn select_ignored_files(
         return Ok(Vec::new());
     }
 
-    let selection = MultiSelect::new("Select root ignored files to add", candidates).prompt();
+    let selection = match prompt::env_choices(prompt::SELECT_FILES, &candidates)? {
+        Some(files) => Ok(files),
+        None => MultiSelect::new("Select root ignored files to add", candidates).prompt(),
+    };
 
     let selected = match selection {
         Ok(files) => files,
@@ -391,6 +3355,200 @@ fn select_ignored_files(
     Ok(selected)
 }
 
+/// Prompt for untracked and ignored paths anywhere in the repository, then for which of
+/// them are copied rather than symlinked, preselecting those `link_type` or else the link
+/// rules copy. Returns no entries when a prompt is cancelled.
+fn browse_untracked_files(
+    project_root: &Path,
+    config: &Config,
+    link_type: Option<&LinkType>,
+) -> Result<Vec<FileEntry>> {
+    let candidates: Vec<String> = list_untracked_paths(project_root)?
+        .into_iter()
+        .filter(|p| !config.files.iter().any(|f| f.path == *p))
+        .collect();
+    if candidates.is_empty() {
+        println!("No untracked files found to add");
+        return Ok(Vec::new());
+    }
+
+    let selection = match prompt::env_choices(prompt::SELECT_FILES, &candidates)? {
+        Some(files) => Ok(files),
+        None => MultiSelect::new("Select files to add", candidates).prompt(),
+    };
+    let selected = match selection {
+        Ok(selected) => selected,
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            return Ok(Vec::new());
+        }
+        Err(err) => return Err(err).context("Failed to prompt for file selection"),
+    };
+    if selected.is_empty() {
+        println!("No files selected");
+        return Ok(Vec::new());
+    }
+
+    let mut copied_by_default = Vec::new();
+    for (index, path) in selected.iter().enumerate() {
+        let link_type = match link_type {
+            Some(link_type) => link_type.clone(),
+            None => config.link_type_for(path)?,
+        };
+        if matches!(link_type, LinkType::Copy) {
+            copied_by_default.push(index);
+        }
+    }
+    let copy_prompt =
+        MultiSelect::new("Select files to copy instead of symlinking", selected.clone())
+            .with_default(&copied_by_default);
+    let copy_selection = match prompt::env_choices(prompt::SELECT_COPIED, &selected)? {
+        Some(copied) => Ok(copied),
+        None => copy_prompt.prompt(),
+    };
+    let copied = match copy_selection {
+        Ok(copied) => copied,
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            return Ok(Vec::new());
+        }
+        Err(err) => return Err(err).context("Failed to prompt for copied files"),
+    };
+
+    Ok(selected
+        .into_iter()
+        .map(|path| FileEntry {
+            link_type: if copied.contains(&path) {
+                LinkType::Copy
+            } else {
+                LinkType::Symlink
+            },
+            path,
+            secret: None,
+        })
+        .collect())
+}
+
+/// Prompt for the existing worktrees that should receive new entries.
+/// Returns None when the prompt is cancelled.
+fn select_link_targets(project_root: &Path, config: &Config) -> Result<Option<Vec<String>>> {
+    let names: Vec<String> = list_managed_worktrees(project_root, config)?
+        .into_iter()
+        .map(|wt| wt.name)
+        .collect();
+    if names.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+
+    if let Some(selected) = prompt::env_choices(prompt::SELECT_LINK_TARGETS, &names)? {
+        return Ok(Some(selected));
+    }
+    match MultiSelect::new("Select worktrees to link into now", names).prompt() {
+        Ok(selected) => Ok(Some(selected)),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(None),
+        Err(err) => Err(err).context("Failed to prompt for worktree selection"),
+    }
+}
+
+fn print_link_changes(report: &LinkReport) {
+    print_section("Created", &report.created, |created| {
+        format!("{}\t{}", created.worktree, created.path.display())
+    });
+    print_section("Replaced", &report.replaced, |replaced| {
+        format!("{}\t{}", replaced.worktree, replaced.path.display())
+    });
+    print_section("Repaired", &report.repaired, |repaired| {
+        format!("{}\t{}", repaired.worktree, repaired.path.display())
+    });
+}
+
+fn print_provision_problems(report: &manifest::ProvisionReport) {
+    print_link_problems(&report.links);
+    eprint_section("Failed worktrees", &report.failed, |failure| {
+        format!("{}\t{}", failure.worktree, failure.error)
+    });
+}
+
+fn print_link_problems(report: &LinkReport) {
+    eprint_section("Source files do not exist", &report.missing, |src| {
+        src.display().to_string()
+    });
+    if !report.missing.is_empty() {
+        eprintln!(
+            "  hint: create them in the project root, or drop their entries with \
+            `epiphyte files remove`"
+        );
+    }
+    let failures: Vec<_> = report
+        .failed
+        .iter()
+        .map(|failure| {
+            let subject = format!("{}\t{}", failure.worktree, failure.path.display());
+            (failure.kind, subject, failure.error.as_str())
+        })
+        .collect();
+    eprint_failures("Failed to link files", &failures, "epiphyte relink --all");
+}
+
+/// Failures listed per group before the rest are only counted.
+const FAILURES_SHOWN: usize = 5;
+
+/// Report (kind, subject, error) failures grouped by kind, a few subjects each, with what
+/// to do about each kind. Errors are only printed for failures of no known kind.
+fn eprint_failures(label: &str, failures: &[(FailureKind, String, &str)], retry: &str) {
+    if failures.is_empty() {
+        return;
+    }
+    count_warnings(failures.len());
+    eprintln!("Warning: {} ({}):", label, failures.len());
+    let mut groups: BTreeMap<FailureKind, Vec<String>> = BTreeMap::new();
+    for (kind, subject, error) in failures {
+        let line = match kind {
+            FailureKind::Other => format!("{}\t{}", subject, error),
+            _ => subject.clone(),
+        };
+        groups.entry(*kind).or_default().push(line);
+    }
+    for (kind, lines) in groups {
+        eprintln!("{} ({}):", kind.label(), lines.len());
+        for line in lines.iter().take(FAILURES_SHOWN) {
+            eprintln!("  {}", line);
+        }
+        if lines.len() > FAILURES_SHOWN {
+            eprintln!("  ... and {} more (--json lists all)", lines.len() - FAILURES_SHOWN);
+        }
+        eprintln!("  hint: {}", kind.hint(retry));
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn format_duration_ms(ms: u64) -> String {
+    match ms {
+        0..1000 => format!("{}ms", ms),
+        1000..60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        _ => format!("{}m{:02}s", ms / 60_000, ms % 60_000 / 1000),
+    }
+}
+
+fn format_exit_code(code: Option<i32>) -> String {
+    match code {
+        Some(0) => "ok".to_string(),
+        Some(code) => format!("exit {}", code),
+        None => "killed".to_string(),
+    }
+}
+
 fn print_section<T, F>(label: &str, items: &[T], mut render: F)
 where
     F: FnMut(&T) -> String,
@@ -404,6 +3562,13 @@ where
     }
 }
 
+/// Warnings reported so far; with `--strict` any of them fails the command.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_warnings(count: usize) {
+    WARNINGS.fetch_add(count, Ordering::Relaxed);
+}
+
 fn eprint_section<T, F>(label: &str, items: &[T], mut render: F)
 where
     F: FnMut(&T) -> String,
@@ -411,8 +3576,43 @@ where
     if items.is_empty() {
         return;
     }
+    count_warnings(items.len());
     eprintln!("Warning: {}:", label);
     for item in items {
         eprintln!("{}", render(item));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn global_config(aliases: &[(&str, &str)]) -> GlobalConfig {
+        GlobalConfig {
+            alias: aliases
+                .iter()
+                .map(|(name, expansion)| (name.to_string(), expansion.to_string()))
+                .collect(),
+            ..GlobalConfig::default()
+        }
+    }
+
+    #[test]
+    fn expand_alias_replaces_subcommand() {
+        let config = global_config(&[("co", "add --base main")]);
+        assert_eq!(expand_alias(args("epi co x -q"), &config), args("epi add --base main x -q"));
+    }
+
+    #[test]
+    fn expand_alias_keeps_builtins_and_unknown_commands() {
+        let config = global_config(&[("ls", "status"), ("list", "status")]);
+        assert_eq!(expand_alias(args("epi ls"), &config), args("epi ls"));
+        assert_eq!(expand_alias(args("epi list"), &config), args("epi list"));
+        assert_eq!(expand_alias(args("epi other"), &config), args("epi other"));
+        assert_eq!(expand_alias(args("epi"), &config), args("epi"));
+    }
+}
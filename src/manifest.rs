@@ -0,0 +1,220 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, WorktreeSpec};
+use crate::doctor;
+use crate::worktree::{
+    add_worktree, ensure_on_main_branch, list_managed_worktrees, list_worktrees, relink_worktree,
+    AddOptions, LinkReport,
+};
+
+/// Differences between the worktrees declared in the config and those on disk.
+#[derive(Default)]
+pub struct ApplyPlan {
+    pub create: Vec<WorktreeSpec>,
+    pub present: Vec<String>,
+    pub extra: Vec<(String, PathBuf)>,
+}
+
+/// A single step of a plan, as emitted by `--plan --json`.
+#[derive(Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum PlannedAction {
+    /// Create the worktree and link configured files into it
+    Create {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+    /// Nothing to do
+    Keep { name: String },
+    /// Present on disk but not declared; reported only
+    Extra { name: String, path: PathBuf },
+}
+
+impl ApplyPlan {
+    pub fn actions(&self) -> Vec<PlannedAction> {
+        let create = self.create.iter().map(|spec| PlannedAction::Create {
+            name: spec.name.clone(),
+            branch: spec.branch.clone(),
+        });
+        let keep = self
+            .present
+            .iter()
+            .map(|name| PlannedAction::Keep { name: name.clone() });
+        let extra = self.extra.iter().map(|(name, path)| PlannedAction::Extra {
+            name: name.clone(),
+            path: path.clone(),
+        });
+        create.chain(keep).chain(extra).collect()
+    }
+}
+
+#[derive(Default)]
+pub struct ApplyReport {
+    pub created: Vec<(String, PathBuf)>,
+    pub present: Vec<String>,
+    pub extra: Vec<(String, PathBuf)>,
+    pub failed: Vec<(String, String)>,
+    /// Link results of the created worktrees
+    pub links: LinkReport,
+}
+
+pub fn plan_apply(project_root: &Path, config: &Config) -> Result<ApplyPlan> {
+    let existing = list_worktrees(project_root)?;
+    let mut plan = ApplyPlan::default();
+
+    for spec in &config.worktrees {
+        if existing.iter().any(|wt| wt.name == spec.name) {
+            plan.present.push(spec.name.clone());
+        } else {
+            plan.create.push(spec.clone());
+        }
+    }
+
+    for wt in existing {
+        if !config.worktrees.iter().any(|spec| spec.name == wt.name) {
+            plan.extra.push((wt.name, wt.path));
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Create every declared worktree that is missing. Worktrees that are not declared
+/// are only reported, never removed.
+pub fn apply(project_root: &Path, config: &Config, plan: ApplyPlan) -> Result<ApplyReport> {
+    let mut report = ApplyReport {
+        present: plan.present,
+        extra: plan.extra,
+        ..Default::default()
+    };
+
+    if !plan.create.is_empty() {
+        ensure_on_main_branch(project_root, &config.main_branch)?;
+    }
+
+    for spec in plan.create {
+        let options = AddOptions {
+            profile: spec.profile.clone(),
+            tags: spec.tags.clone(),
+            ..Default::default()
+        };
+        match add_worktree(project_root, &spec.name, spec.branch.as_deref(), config, &options) {
+            Ok(added) => {
+                if let Some(failure) = added.links.failed.first() {
+                    let error = format!("{}: {}", failure.path.display(), failure.error);
+                    report.failed.push((spec.name.clone(), error));
+                }
+                report.created.push((spec.name, added.worktree.path));
+                report.links.extend(added.links);
+            }
+            Err(err) => report.failed.push((spec.name, err.to_string())),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Outcome of `provision`, printed as JSON for CI.
+#[derive(Serialize)]
+pub struct ProvisionReport {
+    pub created: Vec<String>,
+    pub relinked: Vec<String>,
+    pub links: LinkReport,
+    pub failed: Vec<ProvisionFailure>,
+    /// Whether every worktree was created and every file linked
+    pub ok: bool,
+}
+
+#[derive(Serialize)]
+pub struct ProvisionFailure {
+    pub worktree: String,
+    pub error: String,
+}
+
+/// Turn a fresh clone into a ready workspace: create the declared worktrees, running
+/// their hooks, and relink the files of every other managed worktree.
+pub fn provision(project_root: &Path, config: &Config) -> Result<ProvisionReport> {
+    let plan = plan_apply(project_root, config)?;
+    let applied = apply(project_root, config, plan)?;
+    let created: Vec<String> = applied.created.into_iter().map(|(name, _)| name).collect();
+    let mut failed: Vec<ProvisionFailure> = applied
+        .failed
+        .into_iter()
+        .map(|(worktree, error)| ProvisionFailure {
+            worktree,
+            error: error.trim().to_string(),
+        })
+        .collect();
+
+    let mut links = applied.links;
+    let mut relinked = Vec::new();
+    for worktree in list_managed_worktrees(project_root, config)? {
+        if created.contains(&worktree.name) {
+            continue;
+        }
+        match relink_worktree(project_root, &worktree, config, false) {
+            Ok(report) => {
+                links.extend(report);
+                relinked.push(worktree.name);
+            }
+            Err(err) => failed.push(ProvisionFailure {
+                worktree: worktree.name,
+                error: format!("{:#}", err),
+            }),
+        }
+    }
+
+    let ok = failed.is_empty() && links.failed.is_empty();
+    Ok(ProvisionReport {
+        created,
+        relinked,
+        links,
+        failed,
+        ok,
+    })
+}
+
+/// Outcome of `watch --once`.
+#[derive(Serialize)]
+pub struct ReconcileReport {
+    #[serde(flatten)]
+    pub provision: ProvisionReport,
+    /// Worktrees whose renamed branch was recorded in their metadata
+    pub repaired: Vec<String>,
+    /// Managed worktrees the config doesn't declare, left alone
+    pub undeclared: Vec<String>,
+}
+
+impl ReconcileReport {
+    pub fn changed(&self) -> bool {
+        !self.provision.created.is_empty()
+            || self.provision.links.changed()
+            || !self.repaired.is_empty()
+    }
+}
+
+/// Fix every drift between the config, the metadata and the worktrees in one pass: create
+/// declared worktrees, record renamed branches and relink files everywhere.
+pub fn reconcile(project_root: &Path, config: &Config) -> Result<ReconcileReport> {
+    let undeclared = match config.worktrees.is_empty() {
+        true => Vec::new(),
+        false => plan_apply(project_root, config)?
+            .extra
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+    };
+    let mut repaired = Vec::new();
+    for rename in doctor::renamed_branches(project_root, config)? {
+        repaired.push(doctor::repair_branch(project_root, &rename, false)?);
+    }
+    let provision = provision(project_root, config)?;
+    Ok(ReconcileReport {
+        provision,
+        repaired,
+        undeclared,
+    })
+}
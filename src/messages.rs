@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// A user-facing message with a stable ID. Wrappers match on the ID, which `--json`
+/// errors include, rather than on the English text, which may change or be translated.
+/// `{name}` placeholders in the text are filled in by `format`.
+pub struct Message {
+    pub id: &'static str,
+    text: &'static str,
+}
+
+const fn message(id: &'static str, text: &'static str) -> Message {
+    Message { id, text }
+}
+
+pub const WORKTREE_NOT_FOUND: Message =
+    message("worktree.not_found", "Worktree '{name}' does not exist");
+pub const WORKTREE_NOT_LISTED: Message = message(
+    "worktree.not_found",
+    "Worktree '{name}' does not exist.\n{worktrees}",
+);
+pub const WORKTREE_EXISTS: Message =
+    message("worktree.exists", "Worktree '{name}' already exists");
+pub const WORKTREE_RESERVED: Message = message(
+    "worktree.reserved",
+    "'{name}' is reserved for the main checkout",
+);
+pub const NOT_IN_WORKTREE: Message = message(
+    "worktree.not_detected",
+    "Not inside a worktree. Please specify a worktree name.\n{worktrees}",
+);
+pub const CONFIG_UNTRUSTED: Message = message(
+    "config.untrusted",
+    "The config of {root} is not trusted; review it and run `epiphyte trust`",
+);
+pub const CONFIG_REQUIRED: Message = message(
+    "config.required",
+    "{root} has no .epi/config.toml; run `epiphyte init` first (require_init is set in the \
+    global config)",
+);
+pub const PROJECT_READ_ONLY: Message = message(
+    "project.read_only",
+    "{root} is read-only for this user; only inspection commands such as list, find, audit \
+    and files list are available",
+);
+pub const FILE_ALREADY_CONFIGURED: Message = message(
+    "files.already_configured",
+    "File '{path}' is already in the configuration",
+);
+pub const FILE_NOT_CONFIGURED: Message = message(
+    "files.not_configured",
+    "File '{path}' not found in configuration",
+);
+
+/// ID of errors that don't come from the catalog yet.
+pub const UNCATALOGUED: &str = "error";
+
+impl Message {
+    /// The text with each `{key}` replaced by its value.
+    pub fn format(&self, args: &[(&str, &str)]) -> String {
+        args.iter().fold(self.text.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{}}}", key), value)
+        })
+    }
+
+    pub fn error(&self, args: &[(&str, &str)]) -> anyhow::Error {
+        anyhow::Error::new(CatalogError {
+            id: self.id,
+            text: self.format(args),
+        })
+    }
+}
+
+/// An error built from a catalog message, found again in an error chain by `id_of`.
+#[derive(Debug)]
+pub struct CatalogError {
+    id: &'static str,
+    text: String,
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+/// The ID of the first catalog message in the chain of `err`.
+pub fn id_of(err: &anyhow::Error) -> &'static str {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<CatalogError>())
+        .map_or(UNCATALOGUED, |err| err.id)
+}
@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::get_state_dir;
+
+const METADATA_FILE: &str = "worktrees.toml";
+
+/// The most recent epiphyte command that changed a worktree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub command: String,
+    /// Seconds since the Unix epoch
+    pub at: u64,
+}
+
+/// What epiphyte records about a worktree beyond what git knows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorktreeMeta {
+    /// Branch checked out when the worktree was created or last repaired by `doctor --fix`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Profile given to `add --profile`, selecting hooks with `only_profiles`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Commit a branch created along with the worktree started at, to tell once it has
+    /// commits of its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_commit: Option<String>,
+    /// Pull request a `review` worktree was created for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_operation: Option<Operation>,
+    /// Subdirectory `enter --dir` last started in, relative to the worktree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_dir: Option<String>,
+    /// Location of a worktree outside .epi/trees, e.g. created with `layout = "sibling"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Added to the ports of `{{port BASE}}` in templates rendered into the worktree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port_slot: Option<u16>,
+}
+
+/// Per-worktree metadata stored in `.epi/state/worktrees.toml`, keyed by worktree name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub worktrees: BTreeMap<String, WorktreeMeta>,
+}
+
+impl Metadata {
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let path = get_state_dir(project_root).join(METADATA_FILE);
+        if !path.exists() {
+            return Ok(Metadata::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse metadata: {}", path.display()))
+    }
+
+    pub fn save(&self, project_root: &Path) -> Result<()> {
+        let state_dir = get_state_dir(project_root);
+        fs::create_dir_all(&state_dir)
+            .with_context(|| format!("Failed to create state dir: {}", state_dir.display()))?;
+        let path = state_dir.join(METADATA_FILE);
+        let content = toml::to_string_pretty(self).context("Failed to serialize metadata")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write metadata: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WorktreeMeta> {
+        self.worktrees.get(name)
+    }
+
+    pub fn entry(&mut self, name: &str) -> &mut WorktreeMeta {
+        self.worktrees.entry(name.to_string()).or_default()
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.worktrees.remove(name);
+    }
+
+    /// Note that `command` just ran on the worktree `name`.
+    pub fn record_operation(&mut self, name: &str, command: &str) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.entry(name).last_operation = Some(Operation {
+            command: command.to_string(),
+            at,
+        });
+    }
+
+    /// Give the worktree `name` the lowest port slot no other worktree has, from 1 up.
+    pub fn allocate_port_slot(&mut self, name: &str) -> u16 {
+        let taken: Vec<u16> = self
+            .worktrees
+            .iter()
+            .filter(|(other, _)| *other != name)
+            .filter_map(|(_, meta)| meta.port_slot)
+            .collect();
+        let slot = (1..).find(|slot| !taken.contains(slot)).unwrap_or_default();
+        self.entry(name).port_slot = Some(slot);
+        slot
+    }
+
+    /// Names of the worktrees in `group`, sorted.
+    pub fn group_members(&self, group: &str) -> Vec<String> {
+        self.worktrees
+            .iter()
+            .filter(|(_, meta)| meta.group.as_deref() == Some(group))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// All groups with their members, sorted by group name.
+    pub fn groups(&self) -> BTreeMap<String, Vec<String>> {
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, meta) in &self.worktrees {
+            if let Some(group) = &meta.group {
+                groups.entry(group.clone()).or_default().push(name.clone());
+            }
+        }
+        groups
+    }
+}
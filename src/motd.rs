@@ -0,0 +1,76 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::metadata::Metadata;
+use crate::worktree::{
+    branch_description, dirty_file_count, missing_links, upstream_status, Worktree,
+};
+
+/// A short summary of a worktree's state, printed when entering it.
+pub fn render(project_root: &Path, worktree: &Worktree, config: &Config) -> Result<String> {
+    let mut lines = Vec::new();
+
+    let mut branch = format!("branch {}", worktree.branch);
+    if let Some(upstream) = upstream_status(project_root, &worktree.branch)? {
+        branch.push_str(&format!(
+            " ({}, ahead {}, behind {})",
+            upstream.upstream, upstream.ahead, upstream.behind
+        ));
+    }
+    lines.push(branch);
+    if let Some(description) = branch_description(project_root, &worktree.branch)? {
+        lines.push(description.lines().next().unwrap_or_default().to_string());
+    }
+
+    let dirty = dirty_file_count(&worktree.path)?;
+    lines.push(match dirty {
+        0 => "working tree clean".to_string(),
+        count => format!("{} changed file(s)", count),
+    });
+
+    if !config.files.is_empty() {
+        let missing = missing_links(project_root, &worktree.path, config);
+        lines.push(if missing.is_empty() {
+            format!("{} linked file(s) ok", config.files.len())
+        } else {
+            format!(
+                "{} of {} linked file(s) missing: {} (run `epiphyte relink`)",
+                missing.len(),
+                config.files.len(),
+                missing.join(", ")
+            )
+        });
+    }
+
+    let metadata = Metadata::load(project_root)?;
+    if let Some(operation) = metadata
+        .get(&worktree.name)
+        .and_then(|meta| meta.last_operation.as_ref())
+    {
+        lines.push(format!(
+            "last epiphyte operation: {} {}",
+            operation.command,
+            ago(operation.at)
+        ));
+    }
+
+    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let rule = "-".repeat(width);
+    Ok(format!("{}\n{}\n{}\n", rule, lines.join("\n"), rule))
+}
+
+pub fn ago(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(timestamp);
+    let seconds = now.saturating_sub(timestamp);
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+/// Offline only because no default route was found, which may be wrong, e.g. behind
+/// some VPNs
+static DETECTED: AtomicBool = AtomicBool::new(false);
+
+/// Decide whether to skip network access for the rest of the process: as `--offline`
+/// (`requested`) or EPI_OFFLINE, `0` meaning online, say; or, with neither, when the
+/// machine looks offline without touching the network, i.e. on Linux when there is no
+/// IPv4 or IPv6 default route.
+pub fn init(requested: bool) {
+    let env = std::env::var("EPI_OFFLINE").ok().filter(|value| !value.is_empty());
+    if requested || env.as_deref().is_some_and(|value| value != "0") {
+        OFFLINE.store(true, Ordering::Relaxed);
+    } else if env.is_none() && !has_default_route().unwrap_or(true) {
+        // Assume online when the routing tables can't be read
+        OFFLINE.store(true, Ordering::Relaxed);
+        DETECTED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Whether to skip optional network access, like fetching before `add` or asking `gh`.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Whether running offline was asked for, rather than detected.
+pub fn is_offline_requested() -> bool {
+    is_offline() && !DETECTED.load(Ordering::Relaxed)
+}
+
+/// Whether there is an IPv4 or IPv6 default route, or None when neither routing table
+/// can be read.
+#[cfg(target_os = "linux")]
+fn has_default_route() -> Option<bool> {
+    let ipv4 = std::fs::read_to_string("/proc/net/route").ok();
+    let ipv6 = std::fs::read_to_string("/proc/net/ipv6_route").ok();
+    if ipv4.is_none() && ipv6.is_none() {
+        return None;
+    }
+    Some(
+        ipv4.is_some_and(|routes| has_ipv4_default(&routes))
+            || ipv6.is_some_and(|routes| has_ipv6_default(&routes)),
+    )
+}
+
+/// Whether `/proc/net/route` lists a default route.
+#[cfg(target_os = "linux")]
+fn has_ipv4_default(routes: &str) -> bool {
+    // Columns are interface, destination, gateway, ...; the default route has
+    // destination 0.0.0.0
+    routes
+        .lines()
+        .skip(1)
+        .any(|line| line.split_whitespace().nth(1) == Some("00000000"))
+}
+
+/// Whether `/proc/net/ipv6_route` lists a default route.
+#[cfg(target_os = "linux")]
+fn has_ipv6_default(routes: &str) -> bool {
+    // Columns are destination, prefix length, ..., interface; the default route is ::/0,
+    // except the unreachable placeholder the kernel keeps on the loopback interface
+    routes.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() == 10
+            && fields[0].bytes().all(|b| b == b'0')
+            && fields[1] == "00"
+            && fields[9] != "lo"
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_default_route() -> Option<bool> {
+    None
+}
+
+/// Fail when running offline as asked, saying what needed the network. When offline
+/// was only detected, warn and let it try.
+pub fn ensure_online(what: &str) -> Result<()> {
+    if is_offline_requested() {
+        anyhow::bail!(
+            "{} needs the network, but epiphyte is running offline (--offline or EPI_OFFLINE)",
+            what
+        );
+    }
+    if is_offline() {
+        eprintln!(
+            "Warning: {} needs the network, but there is no default route; trying anyway",
+            what
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    const IPV4_HEADER: &str = "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\n";
+
+    #[test]
+    fn ipv4_default_route() {
+        let routes = format!(
+            "{}eth0\t00000000\t0102A8C0\t0003\t0\t0\t100\t00000000\n\
+             eth0\t0002A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\n",
+            IPV4_HEADER
+        );
+        assert!(has_ipv4_default(&routes));
+    }
+
+    #[test]
+    fn ipv4_local_routes_only() {
+        let routes = format!(
+            "{}eth0\t0002A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\n",
+            IPV4_HEADER
+        );
+        assert!(!has_ipv4_default(&routes));
+        assert!(!has_ipv4_default(IPV4_HEADER));
+    }
+
+    #[test]
+    fn ipv6_default_route() {
+        let routes = "00000000000000000000000000000000 00 00000000000000000000000000000000 00 \
+                      fe800000000000000000000000000001 00000400 00000001 00000000 00000003 eth0\n";
+        assert!(has_ipv6_default(routes));
+    }
+
+    #[test]
+    fn ipv6_loopback_placeholder_is_no_route() {
+        let routes = "fe800000000000000000000000000000 40 00000000000000000000000000000000 00 \
+                      00000000000000000000000000000000 00000100 00000001 00000000 00000001 eth0\n\
+                      00000000000000000000000000000000 00 00000000000000000000000000000000 00 \
+                      00000000000000000000000000000000 ffffffff 00000001 00000000 00200200 lo\n";
+        assert!(!has_ipv6_default(routes));
+    }
+}
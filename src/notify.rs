@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use crate::config::NotifyConfig;
+use crate::network;
+use crate::plugin;
+use crate::trace;
+use crate::trust;
+
+/// A worktree lifecycle event, sent as JSON to the webhook, the notify command and
+/// plugins.
+#[derive(Serialize)]
+pub struct Event<'a> {
+    pub event: &'a str,
+    pub root: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+}
+
+/// Deliver `event` to the configured targets. Notifications are best effort: failures
+/// are printed as warnings and never fail the command that triggered them.
+pub fn send(config: &NotifyConfig, event: &Event) {
+    if config.webhook.is_none() && config.command.is_none() && config.plugins.is_empty() {
+        return;
+    }
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(err) => {
+            eprintln!("Warning: failed to serialize {} event: {}", event.event, err);
+            return;
+        }
+    };
+
+    // Webhooks are best effort; offline they would only time out
+    if let Some(url) = config.webhook.as_ref().filter(|_| !network::is_offline()) {
+        let result = pipe_to(
+            // The response body is of no interest
+            Command::new("curl").stdout(Stdio::null()).args([
+                "-fsS",
+                "--max-time",
+                "10",
+                "-X",
+                "POST",
+                "-H",
+                "Content-Type: application/json",
+                "--data-binary",
+                "@-",
+                url,
+            ]),
+            &body,
+        );
+        if let Err(err) = result {
+            eprintln!("Warning: webhook for {} event failed: {:#}", event.event, err);
+        }
+    }
+
+    for name in &config.plugins {
+        let key = format!("notify.plugins.{}", name);
+        if !trust::is_trusted(event.root, &key, &plugin::executable(name)) {
+            eprintln!(
+                "Warning: skipped plugin {} for {} event, it is not trusted; run `epiphyte \
+                trust` after reviewing the config",
+                plugin::executable(name),
+                event.event
+            );
+            continue;
+        }
+        if let Err(err) = plugin::call(name, event.root, &plugin::Request::Event(event)) {
+            eprintln!("Warning: plugin for {} event failed: {:#}", event.event, err);
+        }
+    }
+
+    if let Some(command) = &config.command {
+        // Events are sent after the fact, with no chance to ask for trust
+        if !trust::is_trusted(event.root, "notify.command", command) {
+            eprintln!(
+                "Warning: skipped the notify command for {} event, it is not trusted; run \
+                `epiphyte trust` after reviewing it",
+                event.event
+            );
+            return;
+        }
+        let result = pipe_to(
+            Command::new("sh")
+                .args(["-c", command])
+                .env("EPI_EVENT", event.event),
+            &body,
+        );
+        if let Err(err) = result {
+            eprintln!("Warning: notify command for {} event failed: {:#}", event.event, err);
+        }
+    }
+}
+
+fn pipe_to(command: &mut Command, input: &[u8]) -> Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let started = Instant::now();
+    let status = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The receiver may exit without reading its input
+                let _ = stdin.write_all(input);
+            }
+            child.wait().with_context(|| format!("Failed to wait for {}", program))
+        });
+    trace::finished(command, started, status.as_ref().ok().and_then(|status| status.code()));
+    let status = status?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::get_state_dir;
+use crate::git;
+use crate::network;
+use crate::trace;
+
+/// Outcome of applying a patch to a new worktree with `add --apply`.
+#[derive(Serialize)]
+pub struct PatchReport {
+    pub source: String,
+    /// Files changed by the patch, conflicted ones included
+    pub changed: Vec<String>,
+    /// Files left with conflict markers where the patch did not apply cleanly
+    pub conflicts: Vec<String>,
+}
+
+/// A patch read from disk or downloaded, ready to apply.
+pub struct Patch {
+    source: String,
+    path: PathBuf,
+    downloaded: bool,
+}
+
+impl Patch {
+    /// Locate the patch at `source`, a file path or an http(s) URL, and check that it
+    /// parses. URLs are downloaded into `.epi/state`; both happen before any worktree is
+    /// created.
+    pub fn load(project_root: &Path, source: &str) -> Result<Self> {
+        let patch = Self::fetch(project_root, source)?;
+        let path = patch.path.to_string_lossy();
+        let output = git::output(project_root, &["apply", "--stat", &path])?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} is not a valid patch: {}",
+                source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(patch)
+    }
+
+    fn fetch(project_root: &Path, source: &str) -> Result<Self> {
+        if !(source.starts_with("http://") || source.starts_with("https://")) {
+            let path = fs::canonicalize(source)
+                .with_context(|| format!("Patch file not found: {}", source))?;
+            return Ok(Patch {
+                source: source.to_string(),
+                path,
+                downloaded: false,
+            });
+        }
+
+        network::ensure_online("Downloading a patch")?;
+        let state_dir = get_state_dir(project_root);
+        fs::create_dir_all(&state_dir)
+            .with_context(|| format!("Failed to create state dir: {}", state_dir.display()))?;
+        let path = state_dir.join(format!("patch-{}.diff", std::process::id()));
+        let output = trace::output(
+            Command::new("curl")
+                .args(["-fsSL", "--max-time", "60", "-o"])
+                .arg(&path)
+                .arg(source),
+        )
+        .context("Failed to run curl")?;
+        if !output.status.success() {
+            let _ = fs::remove_file(&path);
+            anyhow::bail!(
+                "Failed to download {}: {}",
+                source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(Patch {
+            source: source.to_string(),
+            path,
+            downloaded: true,
+        })
+    }
+
+    /// Apply the patch to the worktree's files and index, falling back to a three-way
+    /// merge that leaves conflict markers when the context does not match.
+    pub fn apply(&self, worktree_path: &Path) -> Result<PatchReport> {
+        let path = self.path.to_string_lossy();
+        let output = git::output(worktree_path, &["apply", "--3way", &path])?;
+        let conflicts = lines(&git::output(
+            worktree_path,
+            &["diff", "--name-only", "--diff-filter=U"],
+        )?);
+        if !output.status.success() && conflicts.is_empty() {
+            anyhow::bail!(
+                "Failed to apply {}: {}",
+                self.source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let changed = lines(&git::output(worktree_path, &["diff", "HEAD", "--name-only"])?);
+        Ok(PatchReport {
+            source: self.source.clone(),
+            changed,
+            conflicts,
+        })
+    }
+}
+
+impl Drop for Patch {
+    fn drop(&mut self) {
+        if self.downloaded {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn lines(output: &std::process::Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect()
+}
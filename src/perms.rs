@@ -0,0 +1,59 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Create `path` and any missing parents. New directories get `mode` when given; the
+/// umask still applies, so a mode can only be narrowed by it.
+pub fn create_dir_all(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    builder.create(path)
+}
+
+/// Create a directory epiphyte owns, and set `mode` on it even when it already exists.
+pub fn ensure_dir(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    create_dir_all(path, mode)?;
+    match mode {
+        Some(mode) => set_mode(path, mode),
+        None => Ok(()),
+    }
+}
+
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// Whether the current user may create entries in `path`. Accounts for ownership and
+/// read-only mounts, not just the mode bits.
+pub fn is_writable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+            return false;
+        };
+        // SAFETY: `path` is a valid NUL-terminated string that outlives the call
+        unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        fs::metadata(path).is_ok_and(|metadata| !metadata.permissions().readonly())
+    }
+}
@@ -0,0 +1,117 @@
+//! Plugins are executables named `epi-<name>` on PATH. epiphyte runs them with a JSON
+//! request on stdin, whose `kind` says what is asked:
+//!
+//! - `link`: create `destination` for a config entry with `link_type = "<name>"`, e.g.
+//!   by decrypting `source` or fetching a secret. Entries of plugin types are handed to
+//!   the plugin on every link and relink.
+//! - `event`: a worktree lifecycle event, for plugins listed in `notify.plugins`
+//! - `command`: run as `epi <name> [args...]`, with the arguments also on the command line
+//!
+//! Link and event plugins named by a project config are commands of that config, run
+//! only once it is trusted.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use crate::notify::Event;
+use crate::trace;
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Request<'a> {
+    Link {
+        root: &'a Path,
+        worktree: &'a str,
+        worktree_path: &'a Path,
+        /// Path of the config entry, relative to the worktree
+        path: &'a str,
+        source: &'a Path,
+        destination: &'a Path,
+    },
+    Event(&'a Event<'a>),
+    Command {
+        root: &'a Path,
+        args: &'a [String],
+    },
+}
+
+/// The executable of the plugin `name`.
+pub fn executable(name: &str) -> String {
+    format!("epi-{}", name)
+}
+
+/// Run the plugin with `request` on stdin, failing with its stderr when it exits non-zero.
+pub fn call(name: &str, dir: &Path, request: &Request) -> Result<()> {
+    let mut command = command(name)?;
+    command
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let started = Instant::now();
+    let output = spawn(name, &mut command, request).and_then(|child| {
+        child
+            .wait_with_output()
+            .with_context(|| format!("Failed to wait for {}", executable(name)))
+    });
+    let exit_code = output.as_ref().ok().and_then(|output| output.status.code());
+    trace::finished(&command, started, exit_code);
+    let output = output?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            executable(name),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Run the plugin as the command `epi <name> [args...]`, in the foreground, and return
+/// its exit code.
+pub fn run_command(project_root: &Path, name: &str, args: &[String]) -> Result<i32> {
+    let mut command = command(name)?;
+    command.args(args);
+    let request = Request::Command {
+        root: project_root,
+        args,
+    };
+    let started = Instant::now();
+    let status = spawn(name, &mut command, &request).and_then(|mut child| {
+        child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", executable(name)))
+    });
+    trace::finished(&command, started, status.as_ref().ok().and_then(|status| status.code()));
+    let status = status?;
+    Ok(status.code().unwrap_or(1))
+}
+
+fn command(name: &str) -> Result<Command> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        anyhow::bail!("Invalid plugin name '{}'", name);
+    }
+    Ok(Command::new(executable(name)))
+}
+
+fn spawn(name: &str, command: &mut Command, request: &Request) -> Result<std::process::Child> {
+    let body = serde_json::to_vec(request).context("Failed to serialize plugin request")?;
+    let mut child = match command.stdin(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            anyhow::bail!("No plugin {} found on PATH", executable(name))
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to run {}", executable(name)))
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The plugin may exit without reading its request
+        let _ = stdin.write_all(&body);
+    }
+    Ok(child)
+}
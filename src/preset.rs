@@ -0,0 +1,144 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, FileEntry, Hook, LinkType};
+use crate::worktree::is_path_tracked;
+
+/// Ready-made configuration for common development environments.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Preset {
+    /// Enter worktrees through `nix develop` and link an `.envrc` with `use flake`
+    Nix,
+    /// Link mise/asdf tool-version files and run `mise install` in new worktrees
+    Mise,
+}
+
+const MISE_FILES: [&str; 3] = [".tool-versions", ".mise.toml", "mise.toml"];
+
+/// What applying a preset changed.
+#[derive(Default)]
+pub struct PresetChanges {
+    pub notes: Vec<String>,
+    /// File entries added to the config, to be linked into existing worktrees
+    pub entries: Vec<FileEntry>,
+}
+
+/// Presets that fit the repository, for suggesting after `init`.
+pub fn detect(project_root: &Path) -> Vec<Preset> {
+    let mut presets = Vec::new();
+    if project_root.join("flake.nix").exists() {
+        presets.push(Preset::Nix);
+    }
+    if MISE_FILES.iter().any(|path| project_root.join(path).exists()) {
+        presets.push(Preset::Mise);
+    }
+    presets
+}
+
+/// Apply `preset` to `config`. Without `hooks`, presets leave the hooks section alone.
+pub fn apply(
+    project_root: &Path,
+    config: &mut Config,
+    preset: Preset,
+    hooks: bool,
+) -> Result<PresetChanges> {
+    let mut changes = PresetChanges::default();
+    match preset {
+        Preset::Nix => {
+            if !project_root.join("flake.nix").exists() {
+                anyhow::bail!("No flake.nix in {}", project_root.display());
+            }
+            set_enter_command(config, "nix develop", &mut changes);
+            add_generated_file(project_root, config, ".envrc", "use flake\n", &mut changes)?;
+        }
+        Preset::Mise => {
+            let present: Vec<&str> = MISE_FILES
+                .into_iter()
+                .filter(|path| project_root.join(path).exists())
+                .collect();
+            if present.is_empty() {
+                anyhow::bail!(
+                    "None of {} exist in {}",
+                    MISE_FILES.join(", "),
+                    project_root.display()
+                );
+            }
+            for path in present {
+                add_file_entry(project_root, config, path, &mut changes)?;
+            }
+            if hooks {
+                set_post_create_hook(config, "mise install", &mut changes);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+fn set_enter_command(config: &mut Config, command: &str, changes: &mut PresetChanges) {
+    match &config.enter_command {
+        Some(existing) if existing != command => changes
+            .notes
+            .push(format!("Kept existing enter_command '{}'", existing)),
+        Some(_) => {}
+        None => {
+            config.enter_command = Some(command.to_string());
+            changes.notes.push(format!("Set enter_command to '{}'", command));
+        }
+    }
+}
+
+fn set_post_create_hook(config: &mut Config, command: &str, changes: &mut PresetChanges) {
+    match &config.hooks.post_create {
+        Some(existing) if existing.command() != command => changes
+            .notes
+            .push(format!("Kept existing post_create hook '{}'", existing.command())),
+        Some(_) => {}
+        None => {
+            config.hooks.post_create = Some(Hook::Command(command.to_string()));
+            changes.notes.push(format!("Set post_create hook to '{}'", command));
+        }
+    }
+}
+
+/// Create `path` in the project root with `content` unless it exists, then add it like
+/// `add_file_entry`.
+fn add_generated_file(
+    project_root: &Path,
+    config: &mut Config,
+    path: &str,
+    content: &str,
+    changes: &mut PresetChanges,
+) -> Result<()> {
+    let full_path = project_root.join(path);
+    if !full_path.exists() {
+        fs::write(&full_path, content)?;
+        changes.notes.push(format!("Created {}", path));
+    }
+    add_file_entry(project_root, config, path, changes)
+}
+
+/// Link `path` into worktrees unless git already tracks it.
+fn add_file_entry(
+    project_root: &Path,
+    config: &mut Config,
+    path: &str,
+    changes: &mut PresetChanges,
+) -> Result<()> {
+    if is_path_tracked(project_root, path)? {
+        changes
+            .notes
+            .push(format!("{} is tracked by git, so every worktree has it", path));
+    } else if !config.files.iter().any(|entry| entry.path == path) {
+        let entry = FileEntry {
+            path: path.to_string(),
+            link_type: LinkType::Symlink,
+            secret: None,
+        };
+        config.files.push(entry.clone());
+        changes.entries.push(entry);
+        changes.notes.push(format!("Added '{}' to configuration", path));
+    }
+    Ok(())
+}
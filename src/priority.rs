@@ -0,0 +1,67 @@
+use crate::config::BackgroundPriority;
+
+/// Lower the CPU and I/O priority of this process, for file operations running in the
+/// background of the user's work. Best effort: failures are only warned about.
+pub fn lower(priority: BackgroundPriority) {
+    let (nice, idle_io) = match priority {
+        BackgroundPriority::Normal => return,
+        BackgroundPriority::Low => (10, false),
+        BackgroundPriority::Idle => (19, true),
+    };
+    #[cfg(unix)]
+    {
+        // SAFETY: setpriority only reads its arguments
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            let err = std::io::Error::last_os_error();
+            eprintln!("Warning: failed to lower CPU priority: {}", err);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = nice;
+    if let Err(err) = lower_io(idle_io) {
+        eprintln!("Warning: failed to lower I/O priority: {}", err);
+    }
+}
+
+/// The idle I/O class only gets the disk when nobody else uses it; otherwise use the
+/// lowest level of the best-effort class. See ioprio_set(2).
+#[cfg(target_os = "linux")]
+fn lower_io(idle: bool) -> std::io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    let value = if idle {
+        IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT
+    } else {
+        IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | 7
+    };
+    // SAFETY: ioprio_set only reads its arguments
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Throttled disk I/O, as macOS uses for background work. See setiopolicy_np(3).
+#[cfg(target_os = "macos")]
+fn lower_io(_idle: bool) -> std::io::Result<()> {
+    const IOPOL_TYPE_DISK: libc::c_int = 0;
+    const IOPOL_SCOPE_PROCESS: libc::c_int = 0;
+    const IOPOL_THROTTLE: libc::c_int = 3;
+    extern "C" {
+        fn setiopolicy_np(iotype: libc::c_int, scope: libc::c_int, policy: libc::c_int)
+            -> libc::c_int;
+    }
+    // SAFETY: setiopolicy_np only reads its arguments
+    if unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_PROCESS, IOPOL_THROTTLE) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn lower_io(_idle: bool) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const TICK: Duration = Duration::from_millis(100);
+
+/// Status of a long operation on stderr, one phase at a time. On a terminal, a single
+/// line shows a spinner, the current phase and how long it has been running; otherwise
+/// each phase is printed on a line of its own.
+pub struct Progress {
+    enabled: bool,
+    live: Option<Live>,
+}
+
+struct Live {
+    state: Arc<State>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+struct State {
+    /// The current phase and when it started; None draws nothing
+    phase: Mutex<Option<(String, Instant)>>,
+    done: AtomicBool,
+}
+
+impl Progress {
+    /// Report phases on stderr. With `enabled` false, phases are silently ignored.
+    pub fn new(enabled: bool) -> Self {
+        let live = (enabled
+            && std::io::stderr().is_terminal()
+            && std::env::var("TERM").map_or(true, |term| term != "dumb"))
+        .then(|| {
+            let state = Arc::new(State {
+                phase: Mutex::new(None),
+                done: AtomicBool::new(false),
+            });
+            let ticker = {
+                let state = Arc::clone(&state);
+                thread::spawn(move || {
+                    let mut frame = 0;
+                    while !state.done.load(Ordering::Relaxed) {
+                        state.draw(SPINNER[frame % SPINNER.len()]);
+                        frame += 1;
+                        thread::sleep(TICK);
+                    }
+                })
+            };
+            Live {
+                state,
+                ticker: Some(ticker),
+            }
+        });
+        Progress { enabled, live }
+    }
+
+    /// Start the next phase.
+    pub fn phase(&self, name: &str) {
+        match &self.live {
+            Some(live) => live.state.set(Some(name)),
+            None if self.enabled => eprintln!("{}...", name),
+            None => {}
+        }
+    }
+
+    /// Run `step` as its own phase with the status line cleared, for steps that write to
+    /// the terminal themselves, like hooks.
+    pub fn interactive<T>(&self, name: &str, step: impl FnOnce() -> T) -> T {
+        match &self.live {
+            Some(live) => {
+                live.state.set(None);
+                eprintln!("{}...", name);
+            }
+            None => self.phase(name),
+        }
+        step()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(live) = &mut self.live {
+            live.state.done.store(true, Ordering::Relaxed);
+            if let Some(ticker) = live.ticker.take() {
+                let _ = ticker.join();
+            }
+            live.state.set(None);
+        }
+    }
+}
+
+impl State {
+    fn set(&self, name: Option<&str>) {
+        let mut phase = self.phase.lock().unwrap_or_else(|err| err.into_inner());
+        *phase = name.map(|name| (name.to_string(), Instant::now()));
+        // Clear the line right away so that whatever is printed next starts clean
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[K");
+        let _ = stderr.flush();
+    }
+
+    fn draw(&self, frame: char) {
+        let phase = self.phase.lock().unwrap_or_else(|err| err.into_inner());
+        let Some((name, started)) = phase.as_ref() else {
+            return;
+        };
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[K{} {} ({:.1}s)",
+            frame,
+            name,
+            started.elapsed().as_secs_f64()
+        );
+        let _ = stderr.flush();
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use inquire::error::InquireError;
+use inquire::Confirm;
+use std::io::IsTerminal;
+
+use crate::config::{ChangeKind, ConfirmPolicy};
+
+// Answers to prompts given in the environment, so scripts and tests can drive the CLI
+// without a terminal. Lists are comma-separated.
+pub const ASSUME_YES: &str = "EPI_ASSUME_YES";
+pub const SELECT_WORKTREE: &str = "EPI_SELECT_WORKTREE";
+pub const SELECT_BRANCH: &str = "EPI_SELECT_BRANCH";
+pub const SELECT_FILES: &str = "EPI_SELECT_FILES";
+pub const SELECT_COPIED: &str = "EPI_SELECT_COPIED";
+pub const SELECT_LINK_TARGETS: &str = "EPI_SELECT_LINK_TARGETS";
+pub const SYNC_ACTION: &str = "EPI_SYNC_ACTION";
+pub const MISSING_ACTION: &str = "EPI_MISSING_ACTION";
+pub const DELETE_BRANCH: &str = "EPI_DELETE_BRANCH";
+
+/// The answer EPI_ASSUME_YES gives to confirmations, or None when it is unset.
+pub fn env_assume_yes() -> Option<bool> {
+    env_yes_no(ASSUME_YES)
+}
+
+fn env_yes_no(var: &str) -> Option<bool> {
+    let answer = std::env::var(var).ok()?;
+    Some(matches!(answer.to_lowercase().as_str(), "1" | "y" | "yes" | "true"))
+}
+
+/// Offer an optional extra step, answered by `var` when it is set. Declined without a
+/// terminal, unlike `confirm`: nothing is lost by skipping the offer.
+pub fn offer(var: &str, message: &str) -> Result<bool> {
+    if let Some(answer) = env_yes_no(var) {
+        return Ok(answer);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    match Confirm::new(message).with_default(false).prompt() {
+        Ok(answer) => Ok(answer),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(false),
+        Err(err) => Err(err).context("Failed to prompt"),
+    }
+}
+
+/// Whether `var` answers its prompt.
+pub fn has_env_answer(var: &str) -> bool {
+    std::env::var_os(var).is_some()
+}
+
+/// The option named by `var`, or None when it is unset. Naming anything else is an error.
+pub fn env_choice(var: &str, options: &[String]) -> Result<Option<String>> {
+    let Ok(value) = std::env::var(var) else {
+        return Ok(None);
+    };
+    check_option(var, &value, options)?;
+    Ok(Some(value))
+}
+
+/// The options listed in `var`, or None when it is unset. An empty value selects nothing.
+pub fn env_choices(var: &str, options: &[String]) -> Result<Option<Vec<String>>> {
+    let Ok(value) = std::env::var(var) else {
+        return Ok(None);
+    };
+    let mut chosen = Vec::new();
+    for item in value.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        check_option(var, item, options)?;
+        chosen.push(item.to_string());
+    }
+    Ok(Some(chosen))
+}
+
+fn check_option(var: &str, value: &str, options: &[String]) -> Result<()> {
+    if !options.iter().any(|option| option == value) {
+        anyhow::bail!("{}={} is not one of: {}", var, value, options.join(", "));
+    }
+    Ok(())
+}
+
+/// Ask the user to confirm a change when the policy requires it for `kind`.
+/// Returns false when the user declines or cancels the prompt. EPI_ASSUME_YES answers
+/// every confirmation: `1`, `y` or `yes` accept, anything else declines.
+pub fn confirm(policy: ConfirmPolicy, kind: ChangeKind, message: &str) -> Result<bool> {
+    if !policy.requires_confirmation(kind) {
+        return Ok(true);
+    }
+    if let Some(answer) = env_assume_yes() {
+        return Ok(answer);
+    }
+    if !std::io::stdin().is_terminal() {
+        let hint = match kind {
+            ChangeKind::Discard => "pass --force or set EPI_ASSUME_YES=1 to discard them",
+            _ => "adjust `confirm` in the global config",
+        };
+        anyhow::bail!(
+            "{} (confirmation required, but stdin is not a terminal; {})",
+            message,
+            hint
+        );
+    }
+
+    match Confirm::new(message).with_default(false).prompt() {
+        Ok(answer) => Ok(answer),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(false),
+        Err(err) => Err(err).context("Failed to prompt for confirmation"),
+    }
+}
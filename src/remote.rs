@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::RemoteConfig;
+use crate::network;
+use crate::trace;
+use crate::worktree::Worktree;
+
+/// Worktrees of the remote checkout, read from `list --porcelain` run over SSH.
+pub fn list_worktrees(remote: &RemoteConfig) -> Result<Vec<Worktree>> {
+    network::ensure_online(&format!("Listing worktrees on {}", remote.host))?;
+    let script = format!(
+        "cd {} && {} list --porcelain",
+        shell_quote(&remote.path),
+        remote.command
+    );
+    let output = trace::output(Command::new("ssh").args([remote.host.as_str(), &script]))
+        .with_context(|| format!("Failed to run ssh {}", remote.host))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Listing worktrees on {} failed: {}",
+            remote.host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(branch), Some(path)) => Ok(Worktree {
+                    name: name.to_string(),
+                    branch: branch.to_string(),
+                    path: PathBuf::from(path),
+                }),
+                _ => anyhow::bail!("Unexpected porcelain line from {}: {}", remote.host, line),
+            }
+        })
+        .collect()
+}
+
+/// Open an interactive login shell on the remote host inside `path`.
+pub fn enter(remote: &RemoteConfig, path: &Path) -> Result<()> {
+    network::ensure_online(&format!("Entering a worktree on {}", remote.host))?;
+    let script = format!(
+        "cd {} && exec \"${{SHELL:-sh}}\" -l",
+        shell_quote(&path.to_string_lossy())
+    );
+    let status = trace::status(Command::new("ssh").args(["-t", remote.host.as_str(), &script]))
+        .with_context(|| format!("Failed to run ssh {}", remote.host))?;
+
+    // 255 is ssh's own failure; anything else is the remote shell's exit status
+    if status.code() == Some(255) {
+        anyhow::bail!("ssh to {} failed", remote.host);
+    }
+
+    Ok(())
+}
+
+/// Quote `value` for a POSIX shell.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
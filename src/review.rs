@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::git;
+use crate::metadata::Metadata;
+use crate::network;
+use crate::trace;
+use crate::worktree::{
+    add_worktree, auto_worktree_name, list_managed_worktrees, managed_path, remove_worktree,
+    AddOptions, AddReport,
+};
+
+/// Tag marking worktrees created by `review`.
+pub const REVIEW_TAG: &str = "review";
+
+/// What to review: a pull request of `origin`, or a branch.
+enum Target {
+    PullRequest(u64),
+    Branch(String),
+}
+
+/// Accepts `123`, `#123`, a pull request URL ending in `/pull/123`, or a branch name.
+fn parse_target(target: &str) -> Target {
+    let number = target
+        .trim_end_matches('/')
+        .rsplit_once("/pull/")
+        .map_or(target.trim_start_matches('#'), |(_, number)| number);
+    match number.parse() {
+        Ok(number) => Target::PullRequest(number),
+        Err(_) => Target::Branch(target.to_string()),
+    }
+}
+
+/// Create a worktree for reviewing `target` and tag it for `review --sweep`. Pull
+/// requests are fetched from `origin` into a local `pr-<number>` branch. With `refresh`,
+/// `gh` is asked again instead of reusing its cached answers.
+pub fn create(
+    project_root: &Path,
+    target: &str,
+    config: &Config,
+    refresh: bool,
+) -> Result<AddReport> {
+    let mut options = AddOptions::default();
+    let (name, branch, pull_request) = match parse_target(target) {
+        Target::PullRequest(number) => {
+            let branch = format!("pr-{}", number);
+            if managed_path(project_root, &branch).exists() {
+                anyhow::bail!("Pull request #{} already has worktree '{}'", number, branch);
+            }
+            network::ensure_online(&format!("Fetching pull request #{}", number))?;
+            let refspec = format!("+pull/{}/head:{}", number, branch);
+            git::run(project_root, &["fetch", "origin", &refspec])
+                .with_context(|| format!("Failed to fetch pull request #{}", number))?;
+            let cache = Cache::new(project_root, refresh);
+            options.description = pull_request_title(project_root, &cache, number)
+                .map(|title| format!("Review of #{}: {}", number, title));
+            (branch.clone(), branch, Some(number))
+        }
+        Target::Branch(branch) => (auto_worktree_name(project_root, Some(&branch)), branch, None),
+    };
+
+    options.tags.push(REVIEW_TAG.to_string());
+    let report = add_worktree(project_root, &name, Some(&branch), config, &options);
+
+    // Even when a later step failed, `review --sweep` must know the pull request of a
+    // worktree that was created
+    if pull_request.is_some() {
+        let mut metadata = Metadata::load(project_root)?;
+        if metadata.get(&name).is_some() {
+            metadata.entry(&name).pull_request = pull_request;
+            metadata.save(project_root)?;
+        }
+    }
+
+    report
+}
+
+/// Review worktrees whose pull request was merged, asking `gh` when it is installed. For
+/// branches, and without `gh` or offline, a branch counts as merged once the main branch
+/// contains it. With `refresh`, `gh` is asked again instead of reusing its cached answers.
+pub fn merged(project_root: &Path, config: &Config, refresh: bool) -> Result<Vec<String>> {
+    let metadata = Metadata::load(project_root)?;
+    let cache = Cache::new(project_root, refresh);
+    let mut merged = Vec::new();
+    for worktree in list_managed_worktrees(project_root, config)? {
+        let Some(meta) = metadata.get(&worktree.name) else {
+            continue;
+        };
+        if !meta.tags.iter().any(|tag| tag == REVIEW_TAG) {
+            continue;
+        }
+        let pr_merged = meta
+            .pull_request
+            .and_then(|number| pull_request_merged(project_root, &cache, number));
+        let is_merged = match pr_merged {
+            Some(is_merged) => is_merged,
+            None => {
+                !worktree.branch.is_empty()
+                    && git::output(
+                        project_root,
+                        &["merge-base", "--is-ancestor", &worktree.branch, &config.main_branch],
+                    )?
+                    .status
+                    .success()
+            }
+        };
+        if is_merged {
+            merged.push(worktree.name);
+        }
+    }
+    Ok(merged)
+}
+
+/// Remove a review worktree, and the `pr-<number>` branch `create` fetched for it.
+pub fn remove(project_root: &Path, name: &str, config: &Config) -> Result<PathBuf> {
+    let pull_request = Metadata::load(project_root)?
+        .get(name)
+        .and_then(|meta| meta.pull_request);
+    let path = remove_worktree(project_root, name, false, config)?;
+    if let Some(number) = pull_request {
+        git::run(project_root, &["branch", "-D", &format!("pr-{}", number)])?;
+    }
+    Ok(path)
+}
+
+/// Whether `gh` reports the pull request as merged, or None when `gh` can't tell.
+fn pull_request_merged(project_root: &Path, cache: &Cache, number: u64) -> Option<bool> {
+    if network::is_offline() {
+        return None;
+    }
+    pull_request_field(project_root, cache, number, "state").map(|state| state == "MERGED")
+}
+
+/// The title `gh` reports for the pull request, or None when `gh` can't tell.
+fn pull_request_title(project_root: &Path, cache: &Cache, number: u64) -> Option<String> {
+    pull_request_field(project_root, cache, number, "title")
+}
+
+/// The state and check summary of the latest pull request for `branch`, like
+/// `#12 open, checks passing`, possibly cached; None when `gh` knows no pull request.
+pub fn branch_status(project_root: &Path, cache: &Cache, branch: &str) -> Option<String> {
+    if branch.is_empty() {
+        return None;
+    }
+    // Check runs report a conclusion once completed, status contexts only a state
+    let query = "[.number, .state, ([.statusCheckRollup[]? | if (.conclusion // \"\") != \"\" \
+        then .conclusion else (.state // .status // \"PENDING\") end] | join(\",\"))] | @tsv";
+    let answer = cache.get_or(&format!("gh-branch-{}", branch), || {
+        if network::is_offline() {
+            return None;
+        }
+        let output = trace::output(
+            Command::new("gh")
+                .args(["pr", "view", branch, "--json", "number,state,statusCheckRollup"])
+                .args(["--jq", query])
+                .current_dir(project_root)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null()),
+        )
+        .ok()?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !value.is_empty()).then_some(value)
+    })?;
+    let mut fields = answer.split('\t');
+    let number = fields.next()?;
+    let state = fields.next()?.to_lowercase();
+    let checks: Vec<&str> = fields.next().unwrap_or_default().split(',').collect();
+    let failing = ["FAILURE", "ERROR", "CANCELLED", "TIMED_OUT", "ACTION_REQUIRED"];
+    let passing = ["SUCCESS", "NEUTRAL", "SKIPPED"];
+    let checks = if checks == [""] {
+        "no checks"
+    } else if checks.iter().any(|check| failing.contains(check)) {
+        "checks failing"
+    } else if checks.iter().all(|check| passing.contains(check)) {
+        "checks passing"
+    } else {
+        "checks pending"
+    };
+    Some(format!("#{} {}, {}", number, state, checks))
+}
+
+/// A field of the pull request as `gh` reports it, possibly cached; None when `gh` can't
+/// tell or the field is empty.
+fn pull_request_field(
+    project_root: &Path,
+    cache: &Cache,
+    number: u64,
+    field: &str,
+) -> Option<String> {
+    cache.get_or(&format!("gh-pr-{}-{}", number, field), || {
+        let output = trace::output(
+            Command::new("gh")
+                .args(["pr", "view", &number.to_string(), "--json", field])
+                .args(["--jq", &format!(".{}", field)])
+                .current_dir(project_root)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null()),
+        )
+        .ok()?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !value.is_empty()).then_some(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target_pull_requests() {
+        for target in ["123", "#123", "https://github.com/owner/repo/pull/123/"] {
+            assert!(matches!(parse_target(target), Target::PullRequest(123)), "{}", target);
+        }
+    }
+
+    #[test]
+    fn parse_target_branches() {
+        for target in ["feature/x", "#fix", "https://github.com/owner/repo/pull/new"] {
+            assert!(matches!(parse_target(target), Target::Branch(b) if b == target), "{}", target);
+        }
+    }
+}
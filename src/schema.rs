@@ -0,0 +1,548 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+
+/// Shape of a config value.
+enum Kind {
+    String,
+    Bool,
+    Integer,
+    /// An octal mode written as a string, e.g. "0700"
+    Mode,
+    /// A command string, or a table with `command` and options: `serialize = true` to
+    /// never run two instances of the hook at once, `only_profiles` and `only_tags` to run
+    /// it only for some worktrees
+    Hook,
+    /// One of the listed strings, each with a description
+    Enum(&'static [(&'static str, &'static str)]),
+    StringList,
+    /// A table of arbitrary keys with string values
+    StringMap,
+    Table(&'static [Field]),
+    ArrayOfTables(&'static [Field]),
+}
+
+/// A config key. Keep in sync with the serde structs in `config.rs`.
+struct Field {
+    key: &'static str,
+    kind: Kind,
+    /// TOML literal used when the key is absent
+    default: Option<&'static str>,
+    required: bool,
+    description: &'static str,
+}
+
+const fn field(key: &'static str, kind: Kind, description: &'static str) -> Field {
+    Field {
+        key,
+        kind,
+        default: None,
+        required: false,
+        description,
+    }
+}
+
+const fn with_default(
+    key: &'static str,
+    kind: Kind,
+    default: &'static str,
+    description: &'static str,
+) -> Field {
+    Field {
+        key,
+        kind,
+        default: Some(default),
+        required: false,
+        description,
+    }
+}
+
+const fn required(key: &'static str, kind: Kind, description: &'static str) -> Field {
+    Field {
+        key,
+        kind,
+        default: None,
+        required: true,
+        description,
+    }
+}
+
+const PROJECT: &[Field] = &[
+    with_default(
+        "main_branch",
+        Kind::String,
+        "\"main\"",
+        "Branch worktrees are added from and `sync` rebases onto",
+    ),
+    field(
+        "files",
+        Kind::ArrayOfTables(&[
+            required("path", Kind::String, "Path relative to the project root"),
+            with_default(
+                "link_type",
+                Kind::Enum(&[
+                    ("symlink", "Symlink to the file in the project root"),
+                    ("copy", "Independent copy in each worktree"),
+                    (
+                        "template",
+                        "Rendered per worktree from .epi/templates/<path>.tmpl, with \
+                        {{EPI_*}} and [env] variables and {{port BASE}}",
+                    ),
+                    ("secret", "Fetched from the `secret` URI when linked, readable only by you"),
+                    ("<plugin>", "Any other name: created by the epi-<plugin> plugin on PATH"),
+                ]),
+                "\"symlink\"",
+                "How the file is placed in worktrees",
+            ),
+            field(
+                "secret",
+                Kind::String,
+                "Secret manager URI of secret entries: op://vault/item/field (1Password) or \
+                vault://<path>#<field> (HashiCorp Vault)",
+            ),
+        ]),
+        "Untracked files from the project root made available in every worktree",
+    ),
+    field(
+        "link_rules",
+        Kind::ArrayOfTables(&[
+            required(
+                "pattern",
+                Kind::String,
+                "Glob matched against the path, or its file name when it has no `/`; `*` \
+                and `?` stop at `/`, `**` crosses it",
+            ),
+            required(
+                "link_type",
+                Kind::String,
+                "symlink, copy or a plugin name; `type` is accepted too",
+            ),
+        ]),
+        "Link types `files add` picks by path when neither --copy nor --symlink is given; \
+        the first matching rule wins",
+    ),
+    field(
+        "env",
+        Kind::StringMap,
+        "Environment variables for `enter`, `exec` and `each`, after the EPI_* variables",
+    ),
+    with_default(
+        "motd",
+        Kind::Bool,
+        "false",
+        "Print a summary of the worktree's state when entering it",
+    ),
+    field(
+        "enter_command",
+        Kind::String,
+        "Command `enter` runs through `sh -c` instead of $SHELL, e.g. `nix develop`",
+    ),
+    field(
+        "worktrees",
+        Kind::ArrayOfTables(&[
+            required("name", Kind::String, "Worktree name"),
+            field("branch", Kind::String, "Branch to check out; defaults to the name"),
+            field("profile", Kind::String, "Profile the worktree is created with"),
+            field("tags", Kind::StringList, "Tags the worktree is created with"),
+        ]),
+        "Worktrees `apply` creates when missing",
+    ),
+    field(
+        "hooks",
+        Kind::Table(&[
+            field(
+                "pre_create",
+                Kind::Hook,
+                "Runs in the project root before a worktree is created; a non-zero exit \
+                aborts creation",
+            ),
+            field(
+                "post_create",
+                Kind::Hook,
+                "Runs in the new worktree after files are linked",
+            ),
+            field(
+                "max_concurrent",
+                Kind::Integer,
+                "Most hooks of this project running at once, across epiphyte processes",
+            ),
+            field(
+                "timeout",
+                Kind::Integer,
+                "Seconds after which a running hook is killed and counted as failed",
+            ),
+        ]),
+        "Shell commands run at points of the worktree lifecycle",
+    ),
+    field(
+        "copy",
+        Kind::Table(&[
+            with_default(
+                "dedup",
+                Kind::Bool,
+                "false",
+                "Keep one read-only copy of each file under .epi/store and hardlink it into \
+                 worktrees",
+            ),
+            with_default(
+                "strategy",
+                Kind::Enum(&[
+                    ("kernel", "Let the kernel copy (copy_file_range or sendfile on Linux)"),
+                    ("buffered", "Read and write through a buffer of `buffer_size` bytes"),
+                    ("sparse", "Like buffered, but keep holes of sparse files"),
+                ]),
+                "\"kernel\"",
+                "How file contents are copied",
+            ),
+            with_default(
+                "buffer_size",
+                Kind::Integer,
+                "1048576",
+                "Buffer size in bytes for the buffered and sparse strategies",
+            ),
+            field(
+                "confirm_above",
+                Kind::Integer,
+                "Ask before adding a worktree whose copy entries add up to more bytes than this",
+            ),
+            with_default(
+                "background_priority",
+                Kind::Enum(&[
+                    ("normal", "Leave the priority alone"),
+                    ("low", "Nice 10, and the lowest best-effort I/O priority"),
+                    ("idle", "Nice 19, and disk time only when nobody else uses it"),
+                ]),
+                "\"low\"",
+                "CPU and I/O priority of `relink --background`, as run by the git hooks of \
+                `install-hooks`; I/O is throttled on macOS",
+            ),
+        ]),
+        "How copy entries are materialized in worktrees",
+    ),
+    field(
+        "permissions",
+        Kind::Table(&[
+            field(
+                "dir_mode",
+                Kind::Mode,
+                "Octal mode for directories epiphyte creates, e.g. \"0700\"",
+            ),
+            field(
+                "file_mode",
+                Kind::Mode,
+                "Octal mode for files copied into worktrees, e.g. \"0600\"",
+            ),
+        ]),
+        "Modes for what epiphyte creates, for shared machines where the defaults are too open",
+    ),
+    field(
+        "remote",
+        Kind::Table(&[
+            required("host", Kind::String, "SSH destination, e.g. `devbox` or `me@devbox`"),
+            required("path", Kind::String, "Path of the repository root on the remote host"),
+            with_default(
+                "command",
+                Kind::String,
+                "\"epiphyte\"",
+                "Command that runs epiphyte on the remote host, interpreted by the remote shell",
+            ),
+        ]),
+        "A checkout of this repository on another machine, reached over SSH (experimental)",
+    ),
+    field(
+        "devcontainer",
+        Kind::Table(&[
+            with_default(
+                "template",
+                Kind::String,
+                "\".devcontainer/devcontainer.json\"",
+                "devcontainer.json in the project root used as the template",
+            ),
+            with_default(
+                "engine",
+                Kind::String,
+                "\"docker\"",
+                "Container engine CLI, e.g. `docker` or `podman`",
+            ),
+        ]),
+        "Generate a dev container config in every new worktree",
+    ),
+    field(
+        "notify",
+        Kind::Table(&[
+            field("webhook", Kind::String, "URL the JSON event is POSTed to"),
+            field(
+                "command",
+                Kind::String,
+                "Command run through `sh -c` with the JSON event on stdin and EPI_EVENT set",
+            ),
+            field(
+                "plugins",
+                Kind::StringList,
+                "Plugins (`epi-<name>` on PATH) sent every event as JSON on stdin",
+            ),
+        ]),
+        "Where worktree lifecycle events are delivered",
+    ),
+    field(
+        "each",
+        Kind::StringMap,
+        "Named shell commands runnable in every worktree with `epi each <name>`",
+    ),
+    with_default(
+        "strict",
+        Kind::Bool,
+        "false",
+        "Exit non-zero when a command reported warnings, like `--strict`",
+    ),
+    with_default(
+        "scratch",
+        Kind::Bool,
+        "false",
+        "Create an ignored .scratch directory, skipped by backup tools, in every new worktree",
+    ),
+    with_default(
+        "rerere",
+        Kind::Bool,
+        "false",
+        "Enable git rerere for the repository so `sync` replays recorded conflict resolutions",
+    ),
+    field(
+        "unmanaged",
+        Kind::StringList,
+        "Directories in .epi/trees that epiphyte leaves alone in listings and bulk operations",
+    ),
+    with_default(
+        "layout",
+        Kind::Enum(&[
+            ("nested", "In .epi/trees/<name>, inside the main checkout"),
+            ("sibling", "Next to the main checkout, in ../<repo>--<name>"),
+        ]),
+        "\"nested\"",
+        "Where new worktrees are created; existing worktrees stay where they are",
+    ),
+];
+
+const GLOBAL: &[Field] = &[
+    field(
+        "alias",
+        Kind::StringMap,
+        "Command aliases expanded before argument parsing, e.g. `cd = \"enter\"`",
+    ),
+    with_default(
+        "confirm",
+        Kind::Enum(&[
+            ("always", "Confirm destructive operations and overwrites of existing files"),
+            (
+                "destructive",
+                "Confirm only operations that delete work, and copies above copy.confirm_above",
+            ),
+            ("never", "Prompt only before `remove` discards local changes without --force"),
+        ]),
+        "\"destructive\"",
+        "Which operations ask for confirmation before proceeding",
+    ),
+    field(
+        "git_timeout",
+        Kind::Integer,
+        "Seconds after which a spawned git command is killed; unset waits indefinitely",
+    ),
+    field(
+        "git_binary",
+        Kind::String,
+        "Path of the git every command runs, instead of the first on PATH",
+    ),
+    field(
+        "git_args",
+        Kind::StringList,
+        "Arguments passed to git before every subcommand, e.g. [\"-c\", \"protocol.version=2\"]",
+    ),
+    with_default(
+        "require_init",
+        Kind::Bool,
+        "false",
+        "Refuse to run commands other than `init` in repositories without a project config",
+    ),
+    with_default(
+        "on_missing_worktree",
+        Kind::Enum(&[
+            (
+                "ask",
+                "Ask whether to recreate it, prune it or, when entering, pick another worktree",
+            ),
+            ("recreate", "Check out its branch again at the same place"),
+            ("prune", "Forget the worktree"),
+            ("error", "Leave it and report the error"),
+        ]),
+        "\"ask\"",
+        "What `enter` and `doctor --fix` do about a worktree whose directory was deleted",
+    ),
+];
+
+/// The schema of the project config, or of the per-user config with `global`, as an
+/// annotated TOML document.
+pub fn render_toml(global: bool) -> String {
+    let (title, fields) = schema(global);
+    let mut output = format!("# {}\n", title);
+    let (values, tables): (Vec<&Field>, Vec<&Field>) = fields
+        .iter()
+        .partition(|field| {
+            !matches!(
+                field.kind,
+                Kind::StringMap | Kind::Table(_) | Kind::ArrayOfTables(_)
+            )
+        });
+    for field in values {
+        output.push('\n');
+        write_toml_field(&mut output, field);
+    }
+    for field in tables {
+        output.push('\n');
+        write_toml_table(&mut output, field);
+    }
+    output
+}
+
+fn write_toml_table(output: &mut String, table: &Field) {
+    output.push_str(&format!("# {}\n", table.description));
+    match &table.kind {
+        Kind::StringMap => {
+            output.push_str(&format!("# Type: {}\n", type_name(&table.kind)));
+            output.push_str(&format!("[{}]\n# NAME = \"...\"\n", table.key));
+        }
+        Kind::Table(fields) => {
+            output.push_str(&format!("[{}]\n", table.key));
+            fields.iter().for_each(|field| write_toml_field(output, field));
+        }
+        Kind::ArrayOfTables(fields) => {
+            output.push_str(&format!("[[{}]]\n", table.key));
+            fields.iter().for_each(|field| write_toml_field(output, field));
+        }
+        _ => unreachable!("only tables have a header"),
+    }
+}
+
+fn write_toml_field(output: &mut String, field: &Field) {
+    output.push_str(&format!("# {}\n", field.description));
+    let mut type_name = type_name(&field.kind).to_string();
+    if field.required {
+        type_name.push_str(", required");
+    }
+    if let Some(default) = field.default {
+        type_name.push_str(&format!(", default {}", default));
+    }
+    output.push_str(&format!("# Type: {}\n", type_name));
+    if let Kind::Enum(values) = &field.kind {
+        for (value, description) in values.iter() {
+            output.push_str(&format!("#   \"{}\": {}\n", value, description));
+        }
+    }
+    let value = field.default.unwrap_or_else(|| placeholder(&field.kind));
+    output.push_str(&format!("# {} = {}\n", field.key, value));
+}
+
+fn type_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::String => "string",
+        Kind::Bool => "boolean",
+        Kind::Integer => "integer",
+        Kind::Mode => "octal mode string",
+        Kind::StringList => "array of strings",
+        Kind::Hook => {
+            "string or { command = string, serialize = boolean, only_profiles = array of \
+            strings, only_tags = array of strings }"
+        }
+        Kind::Enum(_) => "one of",
+        Kind::StringMap => "table of strings",
+        Kind::Table(_) => "table",
+        Kind::ArrayOfTables(_) => "array of tables",
+    }
+}
+
+fn placeholder(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::Bool => "false",
+        Kind::Integer => "0",
+        Kind::Mode => "\"0700\"",
+        Kind::StringList => "[\"...\"]",
+        _ => "\"...\"",
+    }
+}
+
+/// The same schema as a JSON Schema document.
+pub fn render_json(global: bool) -> Result<Value> {
+    let (title, fields) = schema(global);
+    let mut document = object_schema(fields)?;
+    document["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    document["title"] = json!(title);
+    Ok(document)
+}
+
+fn object_schema(fields: &[Field]) -> Result<Value> {
+    let mut properties = Map::new();
+    for field in fields {
+        properties.insert(field.key.to_string(), field_schema(field)?);
+    }
+    let required: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.required)
+        .map(|field| field.key)
+        .collect();
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    Ok(schema)
+}
+
+fn field_schema(field: &Field) -> Result<Value> {
+    let mut schema = match &field.kind {
+        Kind::String => json!({ "type": "string" }),
+        Kind::Bool => json!({ "type": "boolean" }),
+        Kind::Integer => json!({ "type": "integer", "minimum": 0 }),
+        Kind::Mode => json!({ "type": "string", "pattern": "^(0o)?[0-7]{1,4}$" }),
+        Kind::Hook => json!({
+            "oneOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string" },
+                        "serialize": { "type": "boolean", "default": false },
+                        "only_profiles": { "type": "array", "items": { "type": "string" } },
+                        "only_tags": { "type": "array", "items": { "type": "string" } },
+                    },
+                    "required": ["command"],
+                },
+            ],
+        }),
+        Kind::Enum(values) => json!({
+            "type": "string",
+            "enum": values.iter().map(|(value, _)| *value).collect::<Vec<_>>(),
+        }),
+        Kind::StringList => json!({ "type": "array", "items": { "type": "string" } }),
+        Kind::StringMap => json!({
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+        }),
+        Kind::Table(fields) => object_schema(fields)?,
+        Kind::ArrayOfTables(fields) => json!({
+            "type": "array",
+            "items": object_schema(fields)?,
+        }),
+    };
+    schema["description"] = json!(field.description);
+    if let Some(default) = field.default {
+        let parsed: toml::Table = toml::from_str(&format!("value = {}", default))
+            .with_context(|| format!("Invalid default for '{}'", field.key))?;
+        schema["default"] = serde_json::to_value(&parsed["value"])?;
+    }
+    Ok(schema)
+}
+
+fn schema(global: bool) -> (&'static str, &'static [Field]) {
+    if global {
+        ("epiphyte global config (~/.config/epiphyte/config.toml)", GLOBAL)
+    } else {
+        ("epiphyte project config (.epi/config.toml)", PROJECT)
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::perms;
+
+pub const SCRATCH_DIR: &str = ".scratch";
+
+/// Ignores the scratch directory, this file included, without touching the worktree's
+/// own .gitignore
+const GITIGNORE: &str = "*\n";
+
+/// Marks the directory for backup tools that skip caches, e.g. `tar --exclude-caches`,
+/// borg and restic; see https://bford.info/cachedir/
+const CACHEDIR_TAG: &str = "Signature: 8a477f597d28d172789f06886806bc55\n\
+# This directory is an epiphyte scratch directory; its contents are not backed up.\n";
+
+/// Create the worktree's scratch directory if it is missing. Git ignores everything in
+/// it, so worktrees with only scratch files still count as clean when removed.
+pub fn ensure(worktree_path: &Path, dir_mode: Option<u32>) -> Result<PathBuf> {
+    let dir = worktree_path.join(SCRATCH_DIR);
+    perms::create_dir_all(&dir, dir_mode)
+        .with_context(|| format!("Failed to create scratch dir: {}", dir.display()))?;
+    for (name, content) in [(".gitignore", GITIGNORE), ("CACHEDIR.TAG", CACHEDIR_TAG)] {
+        let path = dir.join(name);
+        if !path.exists() {
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    Ok(dir)
+}
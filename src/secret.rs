@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::network;
+use crate::trace;
+
+/// Read the secret at `uri` with the secret manager's CLI, which handles sign-in:
+/// `op://vault/item/field` through 1Password's `op read`, and `vault://<path>#<field>`
+/// through `vault kv get -field=<field> <path>`.
+pub fn fetch(uri: &str) -> Result<Vec<u8>> {
+    let mut command = if uri.starts_with("op://") {
+        let mut command = Command::new("op");
+        command.args(["read", "--no-newline", uri]);
+        command
+    } else if let Some(rest) = uri.strip_prefix("vault://") {
+        let (path, field) = rest
+            .split_once('#')
+            .with_context(|| format!("Vault secret {} names no #field", uri))?;
+        let mut command = Command::new("vault");
+        command.args(["kv", "get", &format!("-field={}", field), path]);
+        command
+    } else {
+        anyhow::bail!("Unsupported secret URI {}: expected op:// or vault://", uri);
+    };
+    network::ensure_online("Fetching secrets")?;
+
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = trace::output(command.stdin(Stdio::null()))
+        .with_context(|| format!("Failed to run {} to fetch {}", program, uri))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed to fetch {}: {}",
+            program,
+            uri,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Write a fetched secret, readable only by the user unless `mode` says otherwise.
+pub fn write(path: &Path, content: &[u8], mode: Option<u32>) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode.unwrap_or(0o600));
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::config::CopyConfig;
+use crate::copy;
+
+/// Materialize `src` at `dst` as a hardlink to a content-addressed blob in `store_dir`,
+/// so identical files across worktrees share one copy on disk. Falls back to a plain
+/// copy of the blob when hardlinks are not possible (e.g. across filesystems).
+///
+/// Blobs are shared, so they are made read-only: tools that replace files by renaming
+/// break the link instead of changing every worktree. A blob that was edited in place
+/// anyway no longer matches its hash and is not reused.
+pub fn link_from_store(
+    store_dir: &Path,
+    src: &Path,
+    dst: &Path,
+    copy_config: &CopyConfig,
+) -> Result<()> {
+    let blob = store_blob(store_dir, src, copy_config)?;
+    if fs::hard_link(&blob, dst).is_err() {
+        copy::copy_file(&blob, dst, copy_config).with_context(|| {
+            format!("Failed to copy {} -> {}", blob.display(), dst.display())
+        })?;
+    }
+    Ok(())
+}
+
+/// Delete blobs no worktree links to anymore. Returns the number of blobs removed.
+#[cfg(unix)]
+pub fn gc(store_dir: &Path) -> Result<usize> {
+    use std::os::unix::fs::MetadataExt;
+
+    if !store_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for shard in fs::read_dir(store_dir)? {
+        let shard = shard?.path();
+        if !shard.is_dir() {
+            continue;
+        }
+        for blob in fs::read_dir(&shard)? {
+            let blob = blob?.path();
+            if fs::metadata(&blob)?.nlink() == 1 {
+                fs::remove_file(&blob)
+                    .with_context(|| format!("Failed to remove blob: {}", blob.display()))?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(not(unix))]
+pub fn gc(_store_dir: &Path) -> Result<usize> {
+    Ok(0)
+}
+
+fn store_blob(store_dir: &Path, src: &Path, copy_config: &CopyConfig) -> Result<PathBuf> {
+    let hash = hash_file(src)?;
+    let shard = store_dir.join(&hash[..2]);
+    let blob = shard.join(&hash);
+    if blob.exists() {
+        if hash_file(&blob)? == hash {
+            return Ok(blob);
+        }
+        // Edited through one of its links: the files linked to it keep their content,
+        // new links get a fresh blob
+        #[cfg(windows)]
+        {
+            // Windows refuses to delete read-only files
+            let mut permissions = fs::metadata(&blob)?.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(&blob, permissions)?;
+        }
+        fs::remove_file(&blob)
+            .with_context(|| format!("Failed to remove blob: {}", blob.display()))?;
+    }
+
+    fs::create_dir_all(&shard)
+        .with_context(|| format!("Failed to create store dir: {}", shard.display()))?;
+    // Copy under a temporary name so a partial write never looks like a valid blob
+    let partial = shard.join(format!("{}.partial", hash));
+    // Left over from an interrupted run
+    let _ = fs::remove_file(&partial);
+    copy::copy_file(src, &partial, copy_config)
+        .with_context(|| format!("Failed to store {}", src.display()))?;
+    let mut permissions = fs::metadata(&partial)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&partial, permissions)?;
+    fs::rename(&partial, &blob)
+        .with_context(|| format!("Failed to store {}", src.display()))?;
+    Ok(blob)
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
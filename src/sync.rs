@@ -0,0 +1,294 @@
+use anyhow::{Context, Result};
+use inquire::error::InquireError;
+use inquire::Select;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::config::{get_state_dir, Config};
+use crate::git;
+use crate::metadata::Metadata;
+use crate::notify;
+use crate::prompt;
+use crate::worktree::{enter_worktree, list_managed_worktrees, Worktree};
+
+const SYNC_STATE_FILE: &str = "sync.toml";
+
+/// Worktrees left mid-rebase by a previous `sync`, picked up again by `sync --continue`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub unresolved: Vec<String>,
+}
+
+impl SyncState {
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let path = get_state_dir(project_root).join(SYNC_STATE_FILE);
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read sync state: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse sync state: {}", path.display()))
+    }
+
+    pub fn save(&self, project_root: &Path) -> Result<()> {
+        let state_dir = get_state_dir(project_root);
+        let path = state_dir.join(SYNC_STATE_FILE);
+        if self.unresolved.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove sync state: {}", path.display()))?;
+            }
+            return Ok(());
+        }
+        fs::create_dir_all(&state_dir)
+            .with_context(|| format!("Failed to create state dir: {}", state_dir.display()))?;
+        let content = toml::to_string_pretty(self).context("Failed to serialize sync state")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write sync state: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub synced: Vec<String>,
+    pub skipped: Vec<(String, String)>,
+    pub unresolved: Vec<String>,
+    pub aborted: Vec<String>,
+    pub failed: Vec<(String, String)>,
+    /// Files git rerere resolved from a recorded resolution, per worktree
+    pub reused: Vec<(String, Vec<String>)>,
+}
+
+enum ConflictAction {
+    Skip,
+    Shell,
+    Abort,
+}
+
+/// Rebase every managed worktree onto the main branch. With `resume`, only the
+/// worktrees recorded as unresolved by the previous run are processed, continuing
+/// their in-progress rebase.
+pub fn sync_worktrees(project_root: &Path, config: &Config, resume: bool) -> Result<SyncReport> {
+    if config.rerere {
+        enable_rerere(project_root)?;
+    }
+    let mut state = SyncState::load(project_root)?;
+    let mut worktrees = list_managed_worktrees(project_root, config)?;
+    if resume {
+        if state.unresolved.is_empty() {
+            anyhow::bail!("No unresolved worktrees from a previous sync");
+        }
+        worktrees.retain(|wt| state.unresolved.contains(&wt.name));
+    }
+
+    let mut report = SyncReport::default();
+    for worktree in worktrees {
+        // Left by an earlier run, still waiting for `--continue`
+        if !resume && rebase_in_progress(&worktree.path)? {
+            report.unresolved.push(worktree.name);
+            continue;
+        }
+        // HEAD is detached while a rebase is in progress, so only check fresh runs
+        if !resume && worktree.branch.is_empty() {
+            report
+                .skipped
+                .push((worktree.name, "detached HEAD".to_string()));
+            continue;
+        }
+        if worktree.branch == config.main_branch {
+            report
+                .skipped
+                .push((worktree.name, "main branch".to_string()));
+            continue;
+        }
+
+        let mut reused = Vec::new();
+        let mut result = if resume && rebase_in_progress(&worktree.path)? {
+            rebase(&worktree.path, REBASE_CONTINUE, &mut reused)
+        } else if resume {
+            // Resolved by hand since the last run
+            Ok(())
+        } else {
+            rebase(&worktree.path, &["rebase", &config.main_branch], &mut reused)
+        };
+        // Recorded resolutions may settle every conflict of a commit; carry on as long as
+        // they keep doing so
+        let mut replayed = !reused.is_empty();
+        while result.is_err()
+            && replayed
+            && rebase_in_progress(&worktree.path)?
+            && !has_conflicts(&worktree.path)?
+        {
+            let before = reused.len();
+            result = rebase(&worktree.path, REBASE_CONTINUE, &mut reused);
+            replayed = reused.len() > before;
+        }
+        if !reused.is_empty() {
+            report.reused.push((worktree.name.clone(), reused));
+        }
+
+        match result {
+            Ok(()) => report.synced.push(worktree.name),
+            Err(err) => {
+                if !rebase_in_progress(&worktree.path)? {
+                    report.failed.push((worktree.name, err.to_string()));
+                    continue;
+                }
+                resolve_conflict(&worktree, &mut report)?;
+            }
+        }
+    }
+
+    state.unresolved = report.unresolved.clone();
+    state.save(project_root)?;
+
+    if let Some(notify_config) = &config.notify {
+        let details: Vec<String> = report
+            .failed
+            .iter()
+            .map(|(name, error)| format!("{}: {}", name, error))
+            .chain(report.unresolved.iter().map(|name| format!("{}: conflicts", name)))
+            .collect();
+        if !details.is_empty() {
+            notify::send(
+                notify_config,
+                &notify::Event {
+                    event: "sync_failed",
+                    root: project_root,
+                    worktree: None,
+                    path: None,
+                    details,
+                },
+            );
+        }
+    }
+
+    if !report.synced.is_empty() {
+        let mut metadata = Metadata::load(project_root)?;
+        for name in &report.synced {
+            metadata.record_operation(name, "sync");
+        }
+        metadata.save(project_root)?;
+    }
+
+    Ok(report)
+}
+
+fn resolve_conflict(worktree: &Worktree, report: &mut SyncReport) -> Result<()> {
+    loop {
+        match prompt_conflict_action(&worktree.name)? {
+            ConflictAction::Skip => {
+                report.unresolved.push(worktree.name.clone());
+                return Ok(());
+            }
+            ConflictAction::Abort => {
+                git::run(&worktree.path, &["rebase", "--abort"])?;
+                report.aborted.push(worktree.name.clone());
+                return Ok(());
+            }
+            ConflictAction::Shell => {
+                println!(
+                    "Resolve the conflicts and run `git rebase --continue`, then exit the shell"
+                );
+                enter_worktree(&worktree.path, &[], None)?;
+                if !rebase_in_progress(&worktree.path)? {
+                    report.synced.push(worktree.name.clone());
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn prompt_conflict_action(name: &str) -> Result<ConflictAction> {
+    let actions = ["skip", "shell", "abort"].map(String::from);
+    match prompt::env_choice(prompt::SYNC_ACTION, &actions)?.as_deref() {
+        Some("shell") => return Ok(ConflictAction::Shell),
+        Some("abort") => return Ok(ConflictAction::Abort),
+        Some(_) => return Ok(ConflictAction::Skip),
+        None => {}
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(ConflictAction::Skip);
+    }
+
+    let options = vec![
+        "Skip (leave the rebase in progress)",
+        "Open a shell to resolve",
+        "Abort the rebase",
+    ];
+    let selection = Select::new(
+        &format!("Rebase of '{}' hit conflicts", name),
+        options.clone(),
+    )
+    .prompt();
+    match selection {
+        Ok(choice) if choice == options[1] => Ok(ConflictAction::Shell),
+        Ok(choice) if choice == options[2] => Ok(ConflictAction::Abort),
+        Ok(_) => Ok(ConflictAction::Skip),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            Ok(ConflictAction::Skip)
+        }
+        Err(err) => Err(err).context("Failed to prompt for conflict resolution"),
+    }
+}
+
+const REBASE_CONTINUE: &[&str] = &["-c", "core.editor=true", "rebase", "--continue"];
+
+/// Turn on rerere in the repository config, which every worktree shares along with the
+/// rr-cache in the common git dir. Resolutions replayed by rerere are staged.
+fn enable_rerere(project_root: &Path) -> Result<()> {
+    for (key, value) in [("rerere.enabled", "true"), ("rerere.autoUpdate", "true")] {
+        let output = git::output(project_root, &["config", "--get", key])?;
+        if String::from_utf8_lossy(&output.stdout).trim() != value {
+            git::run(project_root, &["config", key, value])?;
+        }
+    }
+    Ok(())
+}
+
+/// Run a rebase step, adding the files rerere resolved from recorded resolutions to
+/// `reused`. Fails like `git::run` when the step stops.
+fn rebase(worktree_path: &Path, args: &[&str], reused: &mut Vec<String>) -> Result<()> {
+    let output = git::output(worktree_path, args)?;
+    for stream in [&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            let path = ["Resolved '", "Staged '"]
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))
+                .and_then(|rest| rest.strip_suffix("' using previous resolution."));
+            if let Some(path) = path.filter(|path| !reused.iter().any(|p| p == path)) {
+                reused.push(path.to_string());
+            }
+        }
+    }
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn has_conflicts(worktree_path: &Path) -> Result<bool> {
+    let output = git::output(worktree_path, &["diff", "--name-only", "--diff-filter=U"])?;
+    Ok(!output.stdout.is_empty())
+}
+
+fn rebase_in_progress(worktree_path: &Path) -> Result<bool> {
+    for dir in ["rebase-merge", "rebase-apply"] {
+        let output = git::output(worktree_path, &["rev-parse", "--git-path", dir])?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && worktree_path.join(path).exists() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
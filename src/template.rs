@@ -0,0 +1,216 @@
+use anyhow::{Context, Result};
+use inquire::error::InquireError;
+use inquire::Confirm;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, FileEntry, LinkType};
+use crate::git;
+use crate::metadata::Metadata;
+use crate::perms;
+use crate::prompt;
+
+/// Render `template`, replacing `{{NAME}}` with the variable NAME and `{{port BASE}}`
+/// with BASE plus the worktree's port slot, so that every worktree gets its own ports.
+pub fn render(template: &str, vars: &BTreeMap<String, String>, port_slot: u16) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").context("Unclosed {{ in template")?;
+        let expression = after[..end].trim();
+        if let Some(base) = expression.strip_prefix("port ") {
+            let port = base
+                .trim()
+                .parse::<u16>()
+                .ok()
+                .and_then(|base| base.checked_add(port_slot))
+                .with_context(|| format!("Invalid port in {{{{{}}}}}", expression))?;
+            output.push_str(&port.to_string());
+        } else {
+            let value = vars
+                .get(expression)
+                .with_context(|| format!("Unknown variable {{{{{}}}}} in template", expression))?;
+            output.push_str(value);
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Render the template at `src` for a worktree, with the EPI_* variables and the config
+/// `[env]` entries that commands run in the worktree get.
+pub fn render_for_worktree(
+    project_root: &Path,
+    env: &BTreeMap<String, String>,
+    name: &str,
+    worktree_path: &Path,
+    src: &Path,
+) -> Result<String> {
+    let template = fs::read_to_string(src)
+        .with_context(|| format!("Failed to read template: {}", src.display()))?;
+    let output = git::output(worktree_path, &["branch", "--show-current"])?;
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let mut vars = BTreeMap::from([
+        ("EPI_ROOT".to_string(), project_root.display().to_string()),
+        ("EPI_WORKTREE".to_string(), name.to_string()),
+        ("EPI_WORKTREE_PATH".to_string(), worktree_path.display().to_string()),
+        ("EPI_BRANCH".to_string(), branch),
+    ]);
+    vars.extend(env.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+    let mut metadata = Metadata::load(project_root)?;
+    let port_slot = match metadata.get(name).and_then(|meta| meta.port_slot) {
+        Some(slot) => slot,
+        None => {
+            let slot = metadata.allocate_port_slot(name);
+            metadata.save(project_root)?;
+            slot
+        }
+    };
+    render(&template, &vars, port_slot)
+}
+
+/// A file of the project root turned into a template by `files template init`.
+pub struct TemplateInit {
+    pub entry: FileEntry,
+    pub template: PathBuf,
+    /// Values replaced with variables, with their replacement
+    pub replaced: Vec<(String, String)>,
+}
+
+/// Move the file at `path` into `.epi/templates`, replacing values that differ per
+/// worktree with variables as the user confirms, and configure it as a template entry.
+pub fn init(project_root: &Path, config: &mut Config, path: &str) -> Result<TemplateInit> {
+    if config
+        .files
+        .iter()
+        .any(|entry| entry.path == path && matches!(entry.link_type, LinkType::Template))
+    {
+        anyhow::bail!("'{}' is already a template", path);
+    }
+    let file = project_root.join(path);
+    let mut content = fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    let mut replaced = Vec::new();
+    for (value, replacement) in candidates(project_root, &content) {
+        if !confirm_replacement(&value, &replacement)? {
+            continue;
+        }
+        content = match replacement.strip_prefix("{{port ") {
+            Some(_) => replace_port(&content, &value, &replacement),
+            None => content.replace(&value, &replacement),
+        };
+        replaced.push((value, replacement));
+    }
+
+    let entry = FileEntry {
+        path: path.to_string(),
+        link_type: LinkType::Template,
+        secret: None,
+    };
+    let template = entry.source(project_root);
+    if let Some(parent) = template.parent() {
+        perms::create_dir_all(parent, config.permissions.dir_mode)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(&template, content)
+        .with_context(|| format!("Failed to write {}", template.display()))?;
+    fs::remove_file(&file).with_context(|| format!("Failed to remove {}", file.display()))?;
+
+    let (latest, ()) = Config::update(project_root, |latest| {
+        latest.files.retain(|existing| existing.path != path);
+        latest.files.push(entry.clone());
+        Ok(())
+    })?;
+    *config = latest;
+    Ok(TemplateInit {
+        entry,
+        template,
+        replaced,
+    })
+}
+
+/// Values of the file likely to differ between worktrees: the project root path, its
+/// directory name (e.g. in database names) and port numbers.
+fn candidates(project_root: &Path, content: &str) -> Vec<(String, String)> {
+    let mut candidates = Vec::new();
+    let root = project_root.display().to_string();
+    if content.contains(&root) {
+        candidates.push((root, "{{EPI_WORKTREE_PATH}}".to_string()));
+    }
+    if let Some(dir_name) = project_root.file_name().map(|name| name.to_string_lossy()) {
+        let without_root = content.replace(&project_root.display().to_string(), "");
+        if dir_name.len() >= 3 && without_root.contains(dir_name.as_ref()) {
+            candidates.push((dir_name.to_string(), "{{EPI_WORKTREE}}".to_string()));
+        }
+    }
+    for port in ports(content) {
+        if !candidates.iter().any(|(value, _)| *value == port) {
+            let replacement = format!("{{{{port {}}}}}", port);
+            candidates.push((port, replacement));
+        }
+    }
+    candidates
+}
+
+/// Numbers between 1024 and 65535 right after a `:` or `=`, as in `PORT=3000` or
+/// `localhost:5432`.
+fn ports(content: &str) -> Vec<String> {
+    let mut ports = Vec::new();
+    for (index, _) in content.match_indices([':', '=']) {
+        let digits: String = content[index + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        let followed_by_digit = content[index + 1 + digits.len()..]
+            .starts_with(|c: char| c.is_alphanumeric());
+        let is_port = digits.parse::<u16>().is_ok_and(|port| port >= 1024);
+        if is_port && !followed_by_digit && !ports.contains(&digits) {
+            ports.push(digits);
+        }
+    }
+    ports
+}
+
+/// Replace the port after every `:` or `=`, leaving other occurrences of the number alone.
+fn replace_port(content: &str, port: &str, replacement: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(index) = rest.find([':', '=']) {
+        output.push_str(&rest[..=index]);
+        rest = &rest[index + 1..];
+        let is_port = rest.strip_prefix(port).is_some_and(|after| {
+            !after.starts_with(|c: char| c.is_alphanumeric())
+        });
+        if is_port {
+            output.push_str(replacement);
+            rest = &rest[port.len()..];
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn confirm_replacement(value: &str, replacement: &str) -> Result<bool> {
+    if let Some(answer) = prompt::env_assume_yes() {
+        return Ok(answer);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    match Confirm::new(&format!("Replace '{}' with {}?", value, replacement))
+        .with_default(true)
+        .prompt()
+    {
+        Ok(answer) => Ok(answer),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(false),
+        Err(err) => Err(err).context("Failed to prompt for template variables"),
+    }
+}
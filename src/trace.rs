@@ -0,0 +1,124 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::history::{self, CommandRecord};
+
+/// Source of the command log records written for `--trace-commands`.
+pub const SOURCE: &str = "trace";
+
+/// Every external process epiphyte spawns, recorded when `--trace-commands` is on.
+struct Tracer {
+    stderr: bool,
+    project_root: Option<PathBuf>,
+    /// Processes that finished before the project root was known, e.g. while git found it
+    pending: Vec<CommandRecord>,
+}
+
+static TRACER: Mutex<Option<Tracer>> = Mutex::new(None);
+
+fn tracer() -> std::sync::MutexGuard<'static, Option<Tracer>> {
+    TRACER.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Trace every process spawned from now on into the command log, and to stderr too with
+/// `stderr`.
+pub fn enable(stderr: bool) {
+    *tracer() = Some(Tracer {
+        stderr,
+        project_root: None,
+        pending: Vec::new(),
+    });
+}
+
+/// Write traces to the command log of `project_root`, starting with those held back
+/// until it was known.
+pub fn set_project_root(project_root: &Path) {
+    if let Some(tracer) = tracer().as_mut() {
+        for mut record in tracer.pending.drain(..) {
+            record.worktree = location(Path::new(&record.worktree), Some(project_root));
+            history::record(project_root, &record);
+        }
+        tracer.project_root = Some(project_root.to_path_buf());
+    }
+}
+
+/// Record that `command`, started at `started`, finished with `exit_code`; None when it
+/// could not start or was killed.
+pub fn finished(command: &Command, started: Instant, exit_code: Option<i32>) {
+    let mut tracer = tracer();
+    let Some(tracer) = tracer.as_mut() else {
+        return;
+    };
+    let command_line = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cwd = match command.get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::current_dir().unwrap_or_default(),
+    };
+    let duration = started.elapsed();
+    if tracer.stderr {
+        eprintln!(
+            "[trace] {} (in {}): {} after {}ms",
+            command_line,
+            cwd.display(),
+            exit_code.map_or("no exit status".to_string(), |code| format!("exit {}", code)),
+            duration.as_millis()
+        );
+    }
+
+    let root = tracer.project_root.as_deref();
+    let record = CommandRecord::new(
+        &location(&cwd, root),
+        SOURCE,
+        &command_line,
+        duration,
+        exit_code,
+    );
+    match root {
+        Some(root) => history::record(root, &record),
+        None => tracer.pending.push(record),
+    }
+}
+
+/// The directory a process ran in, which stands in for the worktree of its record:
+/// relative to the project root when inside it.
+fn location(cwd: &Path, project_root: Option<&Path>) -> String {
+    match project_root.map(|root| cwd.strip_prefix(root)) {
+        Some(Ok(relative)) if relative.as_os_str().is_empty() => ".".to_string(),
+        Some(Ok(relative)) => relative.display().to_string(),
+        _ => cwd.display().to_string(),
+    }
+}
+
+/// `Command::status`, traced.
+pub fn status(command: &mut Command) -> io::Result<ExitStatus> {
+    let started = Instant::now();
+    let status = command.status();
+    finished(command, started, status.as_ref().ok().and_then(ExitStatus::code));
+    status
+}
+
+/// `Command::output`, traced.
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    let started = Instant::now();
+    let output = command.output();
+    finished(
+        command,
+        started,
+        output.as_ref().ok().and_then(|output| output.status.code()),
+    );
+    output
+}
+
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use inquire::error::InquireError;
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, GlobalConfig};
+use crate::messages;
+use crate::prompt;
+
+const TRUST_FILE: &str = "trusted-commands.toml";
+
+/// Commands from project configs the user agreed to run, like direnv's allow list. Kept
+/// next to the global config, per project root and config key, as a fingerprint of the
+/// command so that editing it requires trusting it again.
+#[derive(Default, Serialize, Deserialize)]
+struct TrustStore {
+    #[serde(default)]
+    projects: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl TrustStore {
+    fn path() -> Option<PathBuf> {
+        GlobalConfig::path().and_then(|path| Some(path.parent()?.join(TRUST_FILE)))
+    }
+
+    fn load() -> Result<Self> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(TrustStore::default());
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path().context("Cannot locate the global config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize trust store")?;
+        fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn is_trusted(&self, project_root: &Path, key: &str, command: &str) -> bool {
+        self.projects
+            .get(project_root.to_string_lossy().as_ref())
+            .and_then(|commands| commands.get(key))
+            == Some(&fingerprint(command))
+    }
+}
+
+fn fingerprint(command: &str) -> String {
+    Sha256::digest(command.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Whether the command at `key` of the project config is trusted as it is now.
+pub fn is_trusted(project_root: &Path, key: &str, command: &str) -> bool {
+    TrustStore::load().is_ok_and(|store| store.is_trusted(project_root, key, command))
+}
+
+/// Commands of the config not trusted yet, or changed since they were trusted.
+pub fn untrusted(project_root: &Path, config: &Config) -> Result<Vec<(String, String)>> {
+    let store = TrustStore::load()?;
+    Ok(config
+        .commands()
+        .into_iter()
+        .filter(|(key, command)| !store.is_trusted(project_root, key, command))
+        .collect())
+}
+
+/// Trust every command of the config, forgetting commands it no longer has.
+pub fn trust(project_root: &Path, config: &Config) -> Result<()> {
+    let mut store = TrustStore::load()?;
+    let commands = config
+        .commands()
+        .into_iter()
+        .map(|(key, command)| (key, fingerprint(&command)))
+        .collect();
+    store
+        .projects
+        .insert(project_root.to_string_lossy().to_string(), commands);
+    store.save()
+}
+
+/// Before epiphyte runs anything from the config, show the commands not trusted yet
+/// and ask the user to trust them. EPI_ASSUME_YES answers the prompt.
+pub fn ensure_trusted(project_root: &Path, config: &Config) -> Result<()> {
+    let untrusted = untrusted(project_root, config)?;
+    if untrusted.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "The config of {} has commands that are new or changed since they were trusted:",
+        project_root.display()
+    );
+    for (key, command) in &untrusted {
+        eprintln!("  {}: {}", key, command);
+    }
+
+    let message = "Trust the commands of this config?";
+    let answer = match prompt::env_assume_yes() {
+        Some(answer) => answer,
+        None if std::io::stdin().is_terminal() => {
+            match Confirm::new(message).with_default(false).prompt() {
+                Ok(answer) => answer,
+                Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => false,
+                Err(err) => return Err(err).context("Failed to prompt for trust"),
+            }
+        }
+        None => false,
+    };
+    if !answer {
+        let root = project_root.display().to_string();
+        return Err(messages::CONFIG_UNTRUSTED.error(&[("root", &root)]));
+    }
+    trust(project_root, config)
+}
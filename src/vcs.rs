@@ -0,0 +1,439 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::get_trees_dir;
+use crate::git;
+use crate::trace;
+
+pub const JJ_DIR: &str = ".jj";
+
+/// A worktree (or workspace) as reported by the underlying version control system.
+pub struct VcsWorktree {
+    pub path: PathBuf,
+    pub branch: String,
+    /// The reason given when the worktree was locked, empty when none was
+    pub locked: Option<String>,
+}
+
+/// Operations epiphyte needs from the version control system backing a repository.
+pub trait Vcs {
+    fn current_branch(&self) -> Result<String>;
+
+    fn branch_exists(&self, branch_name: &str) -> Result<bool>;
+
+    fn list_worktrees(&self) -> Result<Vec<VcsWorktree>>;
+
+    /// Create a worktree at `path` checking out `branch_name`, creating the branch when
+    /// `create_branch` is set, at `start_point` if given. Without `checkout` the worktree
+    /// starts with no files.
+    fn add_worktree(
+        &self,
+        path: &Path,
+        branch_name: &str,
+        create_branch: bool,
+        start_point: Option<&str>,
+        checkout: bool,
+    ) -> Result<()>;
+
+    fn move_worktree(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Remove the worktree at `path`; without `force`, refuse when it has local changes.
+    fn remove_worktree(&self, path: &Path, force: bool) -> Result<()>;
+
+    /// Forget the worktrees whose directory was deleted.
+    fn prune_worktrees(&self) -> Result<()>;
+
+    /// Delete a branch; without `force`, refuse when it is not merged.
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<()>;
+
+    fn rename_branch(&self, from: &str, to: &str) -> Result<()>;
+
+    /// Keep the worktree at `path` from being pruned, moved or removed, e.g. while the
+    /// removable or network drive it lives on is unmounted.
+    fn lock_worktree(&self, path: &Path, reason: Option<&str>) -> Result<()>;
+
+    fn unlock_worktree(&self, path: &Path) -> Result<()>;
+}
+
+/// Open the version control backend for the repository at `project_root`.
+/// Colocated jj repositories (a `.jj` directory next to `.git`) use jj workspaces.
+pub fn open(project_root: &Path) -> Box<dyn Vcs> {
+    if project_root.join(JJ_DIR).is_dir() {
+        Box::new(Jj::new(project_root))
+    } else {
+        Box::new(Git::new(project_root))
+    }
+}
+
+pub struct Git {
+    root: PathBuf,
+}
+
+impl Git {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            root: project_root.to_path_buf(),
+        }
+    }
+}
+
+impl Vcs for Git {
+    fn current_branch(&self) -> Result<String> {
+        let output = git::output(&self.root, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git rev-parse failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn branch_exists(&self, branch_name: &str) -> Result<bool> {
+        let branch_ref = format!("refs/heads/{}", branch_name);
+        let output = git::output(&self.root, &["show-ref", "--verify", "--quiet", &branch_ref])?;
+
+        Ok(output.status.success())
+    }
+
+    fn list_worktrees(&self) -> Result<Vec<VcsWorktree>> {
+        let output = git::output(&self.root, &["worktree", "list", "--porcelain"])?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git worktree list failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut worktrees = Vec::new();
+        let mut current_path: Option<PathBuf> = None;
+        let mut current_branch: Option<String> = None;
+        let mut current_lock: Option<String> = None;
+
+        for line in stdout.lines() {
+            if line.starts_with("worktree ") {
+                if let Some(path) = current_path.take() {
+                    worktrees.push(VcsWorktree {
+                        path,
+                        branch: current_branch.take().unwrap_or_default(),
+                        locked: current_lock.take(),
+                    });
+                }
+                current_path = Some(PathBuf::from(line.strip_prefix("worktree ").unwrap()));
+                current_branch = None;
+                current_lock = None;
+            } else if line.starts_with("branch ") {
+                current_branch = Some(
+                    line.strip_prefix("branch refs/heads/")
+                        .unwrap_or(line.strip_prefix("branch ").unwrap())
+                        .to_string(),
+                );
+            } else if line == "locked" || line.starts_with("locked ") {
+                current_lock = Some(line.strip_prefix("locked").unwrap().trim().to_string());
+            }
+        }
+
+        if let Some(path) = current_path {
+            worktrees.push(VcsWorktree {
+                path,
+                branch: current_branch.unwrap_or_default(),
+                locked: current_lock,
+            });
+        }
+
+        Ok(worktrees)
+    }
+
+    fn add_worktree(
+        &self,
+        path: &Path,
+        branch_name: &str,
+        create_branch: bool,
+        start_point: Option<&str>,
+        checkout: bool,
+    ) -> Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+        let mut args = vec!["worktree", "add"];
+        if !checkout {
+            args.push("--no-checkout");
+        }
+        if create_branch {
+            args.extend(["-b", branch_name, &path_str]);
+            args.extend(start_point);
+        } else {
+            args.extend([path_str.as_str(), branch_name]);
+        }
+
+        let output = git::output(&self.root, &args)?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git worktree add failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+
+    fn move_worktree(&self, from: &Path, to: &Path) -> Result<()> {
+        let from_str = from.to_string_lossy().to_string();
+        let to_str = to.to_string_lossy().to_string();
+
+        let output = git::output(&self.root, &["worktree", "move", &from_str, &to_str])?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git worktree move failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+
+    fn remove_worktree(&self, path: &Path, force: bool) -> Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+        let mut args = vec!["worktree", "remove"];
+        if force {
+            args.push("--force");
+        }
+        args.push(&path_str);
+
+        let output = git::output(&self.root, &args)?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git worktree remove failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+
+    fn prune_worktrees(&self) -> Result<()> {
+        git::run(&self.root, &["worktree", "prune"])
+    }
+
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<()> {
+        let flag = if force { "-D" } else { "-d" };
+        git::run(&self.root, &["branch", flag, branch_name])
+    }
+
+    fn rename_branch(&self, from: &str, to: &str) -> Result<()> {
+        git::run(&self.root, &["branch", "-m", from, to])
+    }
+
+    fn lock_worktree(&self, path: &Path, reason: Option<&str>) -> Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+        let mut args = vec!["worktree", "lock"];
+        if let Some(reason) = reason {
+            args.extend(["--reason", reason]);
+        }
+        args.push(&path_str);
+        git::run(&self.root, &args)
+    }
+
+    fn unlock_worktree(&self, path: &Path) -> Result<()> {
+        git::run(&self.root, &["worktree", "unlock", &path.to_string_lossy()])
+    }
+}
+
+pub struct Jj {
+    root: PathBuf,
+}
+
+impl Jj {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            root: project_root.to_path_buf(),
+        }
+    }
+
+    fn run(&self, args: &[&str]) -> Result<String> {
+        self.run_in(&self.root, args)
+    }
+
+    fn run_in(&self, dir: &Path, args: &[&str]) -> Result<String> {
+        let output = trace::output(Command::new("jj").args(args).current_dir(dir))
+            .with_context(|| format!("Failed to run jj {}", args.first().unwrap_or(&"")))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "jj {} failed: {}",
+                args.iter().take(2).copied().collect::<Vec<_>>().join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// The first local bookmark on the parent of the workspace's working-copy commit.
+    fn workspace_bookmark(&self, workspace: &str) -> Result<String> {
+        let revision = format!("{}@-", workspace);
+        let stdout = self.run(&[
+            "log",
+            "--no-graph",
+            "-r",
+            &revision,
+            "-T",
+            r#"local_bookmarks.map(|b| b.name()).join("\n")"#,
+        ])?;
+        Ok(stdout.lines().next().unwrap_or_default().trim().to_string())
+    }
+
+    /// Files changed in the working-copy commit of the workspace at `path`. Running jj
+    /// there first snapshots edits made since the last jj command in it.
+    pub fn working_copy_changes(&self, path: &Path) -> Result<Vec<String>> {
+        let stdout = self.run_in(path, &["diff", "--name-only", "-r", "@"])?;
+        Ok(stdout.lines().map(String::from).collect())
+    }
+}
+
+impl Vcs for Jj {
+    fn current_branch(&self) -> Result<String> {
+        self.workspace_bookmark("default")
+    }
+
+    fn branch_exists(&self, branch_name: &str) -> Result<bool> {
+        // Colocated repositories export bookmarks as git branches.
+        Git::new(&self.root).branch_exists(branch_name)
+    }
+
+    fn list_worktrees(&self) -> Result<Vec<VcsWorktree>> {
+        let stdout = self.run(&["workspace", "list"])?;
+        let trees_dir = get_trees_dir(&self.root);
+        let mut worktrees = Vec::new();
+
+        for line in stdout.lines() {
+            let Some((name, _)) = line.split_once(':') else {
+                continue;
+            };
+            // Workspaces are named after their directory, in the trees dir or next to
+            // the main checkout
+            let sibling = self.root.parent().unwrap_or(&self.root).join(name);
+            let path = if name == "default" {
+                self.root.clone()
+            } else if !trees_dir.join(name).exists() && sibling.exists() {
+                sibling
+            } else {
+                trees_dir.join(name)
+            };
+            worktrees.push(VcsWorktree {
+                path,
+                branch: self.workspace_bookmark(name)?,
+                locked: None,
+            });
+        }
+
+        Ok(worktrees)
+    }
+
+    fn add_worktree(
+        &self,
+        path: &Path,
+        branch_name: &str,
+        create_branch: bool,
+        start_point: Option<&str>,
+        checkout: bool,
+    ) -> Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut args = vec!["workspace", "add", "--name", &name];
+        if !checkout {
+            // An empty sparse pattern set leaves the working copy unpopulated
+            args.extend(["--sparse-patterns", "empty"]);
+        }
+        if !create_branch {
+            args.extend(["-r", branch_name]);
+        } else if let Some(start_point) = start_point {
+            args.extend(["-r", start_point]);
+        }
+        args.push(&path_str);
+        self.run(&args)?;
+
+        if create_branch {
+            // The new working-copy commit sits on top of the start point, which is where
+            // `workspace_bookmark` looks for the workspace's bookmark
+            let revision = format!("{}@-", name);
+            self.run(&["bookmark", "create", branch_name, "-r", &revision])?;
+        }
+
+        Ok(())
+    }
+
+    fn move_worktree(&self, from: &Path, _to: &Path) -> Result<()> {
+        anyhow::bail!("jj workspaces cannot be moved: {}", from.display())
+    }
+
+    fn remove_worktree(&self, path: &Path, force: bool) -> Result<()> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Snapshot the workspace, so that even forced removal keeps its edits in the
+        // working-copy commit
+        let changes = self.working_copy_changes(path)?;
+        if !force && !changes.is_empty() {
+            anyhow::bail!(
+                "Workspace '{}' has changes in its working-copy commit: {}",
+                name,
+                changes.join(", ")
+            );
+        }
+        self.run(&["workspace", "forget", &name])?;
+        // After the snapshot, the workspace directory holds nothing the repository lacks
+        std::fs::remove_dir_all(path)
+            .with_context(|| format!("Failed to remove workspace dir: {}", path.display()))?;
+        Ok(())
+    }
+
+    fn prune_worktrees(&self) -> Result<()> {
+        for workspace in self.list_worktrees()? {
+            if workspace.path.exists() {
+                continue;
+            }
+            if let Some(name) = workspace.path.file_name() {
+                self.run(&["workspace", "forget", &name.to_string_lossy()])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<()> {
+        if !force {
+            // Like `git branch -d`, refuse unless the main checkout already contains it
+            let revset = format!("\"{}\" ~ ::default@", branch_name);
+            let unmerged = self.run(&["log", "--no-graph", "-r", &revset, "-T", "commit_id"])?;
+            if !unmerged.trim().is_empty() {
+                anyhow::bail!("Bookmark '{}' is not fully merged", branch_name);
+            }
+        }
+        self.run(&["bookmark", "delete", branch_name])?;
+        Ok(())
+    }
+
+    fn rename_branch(&self, from: &str, to: &str) -> Result<()> {
+        self.run(&["bookmark", "rename", from, to])?;
+        Ok(())
+    }
+
+    fn lock_worktree(&self, path: &Path, _reason: Option<&str>) -> Result<()> {
+        anyhow::bail!("jj workspaces cannot be locked: {}", path.display())
+    }
+
+    fn unlock_worktree(&self, path: &Path) -> Result<()> {
+        anyhow::bail!("jj workspaces cannot be locked: {}", path.display())
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::{Context, Result};
 use inquire::error::InquireError;
 use inquire::Select;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::io::Write;
@@ -8,72 +10,402 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use tabwriter::TabWriter;
 
-use crate::config::{get_trees_dir, Config, FileEntry, LinkType};
+use crate::config::{
+    get_sibling_dir, get_store_dir, get_trees_dir, Config, FileEntry, Hook, CONFIG_DIR,
+};
+use crate::devcontainer;
+use crate::env::worktree_env;
+use crate::failure::FailureKind;
+use crate::git;
+use crate::hooks::{run_hook, HookPayload};
+use crate::link::{is_linked, link_entry, LinkContext};
+use crate::messages;
+use crate::metadata::Metadata;
+use crate::network;
+use crate::patch::PatchReport;
+use crate::notify;
+use crate::perms;
+use crate::progress::Progress;
+use crate::scratch;
+use crate::prompt;
+use crate::store;
+use crate::trace;
+use crate::trust;
+use crate::vcs;
 
-pub fn get_current_branch(project_root: &Path) -> Result<String> {
-    let output = Command::new("git")
-        .args(["rev-parse", "--abbrev-ref", "HEAD"])
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git rev-parse")?;
+pub fn is_path_tracked(project_root: &Path, path: &str) -> Result<bool> {
+    let output = git::output(project_root, &["ls-files", "--error-unmatch", "--", path])?;
+
+    Ok(output.status.success())
+}
+
+pub fn list_ignored_files(project_root: &Path) -> Result<Vec<String>> {
+    let output = git::output(project_root, &["ls-files", "-i", "-o", "--exclude-standard"])?;
 
     if !output.status.success() {
         anyhow::bail!(
-            "git rev-parse failed: {}",
+            "git ls-files failed: {}",
             String::from_utf8_lossy(&output.stderr)
         );
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut files: Vec<String> = stdout
+        .lines()
+        .filter(|line| !line.is_empty() && !line.contains('/'))
+        .map(|line| line.to_string())
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
 }
 
-pub fn branch_exists(project_root: &Path, branch_name: &str) -> Result<bool> {
-    let output = Command::new("git")
-        .args(["show-ref", "--verify", "--quiet", &format!("refs/heads/{}", branch_name)])
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git show-ref")?;
+/// Untracked and ignored paths anywhere in the repository. Wholly ignored directories,
+/// like `node_modules`, are listed once instead of file by file. Epiphyte's own directory
+/// is left out.
+pub fn list_untracked_paths(project_root: &Path) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for args in [
+        ["ls-files", "-o", "--exclude-standard"].as_slice(),
+        ["ls-files", "-o", "-i", "--directory", "--exclude-standard"].as_slice(),
+    ] {
+        let output = git::output(project_root, args)?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git ls-files failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        paths.extend(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim_end_matches('/').to_string())
+                .filter(|path| !path.is_empty())
+                .filter(|path| !Path::new(path).starts_with(CONFIG_DIR)),
+        );
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
 
-    Ok(output.status.success())
+/// Fetch all remotes once for the repository; worktrees share the fetched refs.
+pub fn fetch_all(project_root: &Path) -> Result<()> {
+    git::run(project_root, &["fetch", "--all", "--prune"])
 }
 
-pub fn is_path_tracked(project_root: &Path, path: &str) -> Result<bool> {
-    let output = Command::new("git")
-        .args(["ls-files", "--error-unmatch", "--", path])
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git ls-files")?;
+pub fn run_maintenance(project_root: &Path) -> Result<()> {
+    git::run(project_root, &["maintenance", "run"])
+}
 
-    Ok(output.status.success())
+/// Number of changed and untracked files in a worktree.
+pub fn dirty_file_count(worktree_path: &Path) -> Result<usize> {
+    let output = git::output(worktree_path, &["status", "--porcelain"])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().count())
 }
 
-pub fn list_ignored_files(project_root: &Path) -> Result<Vec<String>> {
-    let output = Command::new("git")
-        .args(["ls-files", "-i", "-o", "--exclude-standard"])
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git ls-files")?;
+/// Paths with changes that removing the worktree would lose, as `git status` reports
+/// them, or jj for the working-copy commit of a jj workspace. Configured files still
+/// linked from the root are left out: they can be linked again.
+pub fn unsaved_changes(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let (name, worktree_path) = (worktree.name.as_str(), &worktree.path);
+    let context = LinkContext::new(project_root, config).for_worktree(name, worktree_path);
+    let linked = |path: &str| {
+        config.files.iter().any(|entry| {
+            let Ok(relative) = Path::new(path).strip_prefix(&entry.path) else {
+                return false;
+            };
+            let (mut src, mut dst) = (entry.source(project_root), worktree_path.join(&entry.path));
+            // Untracked directories are listed file by file, and compared so
+            if !relative.as_os_str().is_empty() {
+                src.push(relative);
+                dst.push(relative);
+            }
+            is_linked(&src, &dst, entry, &context)
+        })
+    };
+    // jj keeps the changes of a workspace in its working-copy commit, which removing the
+    // workspace abandons
+    if project_root.join(vcs::JJ_DIR).is_dir() {
+        let changes = vcs::Jj::new(project_root).working_copy_changes(worktree_path)?;
+        return Ok(changes.into_iter().filter(|path| !linked(path)).collect());
+    }
 
+    let output = git::output(
+        worktree_path,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )?;
     if !output.status.success() {
         anyhow::bail!(
-            "git ls-files failed: {}",
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let changes = status_paths(&String::from_utf8_lossy(&output.stdout));
+    Ok(changes.into_iter().filter(|path| !linked(path)).collect())
+}
+
+/// The paths of `git status --porcelain -z` output, without the original paths of
+/// renames and copies.
+fn status_paths(stdout: &str) -> Vec<String> {
+    let mut fields = stdout.split('\0').filter(|field| !field.is_empty());
+    let mut paths = Vec::new();
+    while let Some(field) = fields.next() {
+        let (status, path) = field.split_at(field.len().min(3));
+        // Renames and copies are followed by their original path
+        if status.starts_with(['R', 'C']) {
+            fields.next();
+        }
+        paths.push(path.to_string());
+    }
+    paths
+}
+
+/// Configured files whose source exists in the root but that are absent, or dangling
+/// symlinks, in the worktree.
+pub fn missing_links(project_root: &Path, worktree_path: &Path, config: &Config) -> Vec<String> {
+    config
+        .files
+        .iter()
+        .filter(|entry| entry.source(project_root).exists())
+        .filter(|entry| !worktree_path.join(&entry.path).exists())
+        .map(|entry| entry.path.clone())
+        .collect()
+}
+
+/// How the configured files of a worktree compare to what linking would make.
+#[derive(Default, Serialize)]
+pub struct LinkHealth {
+    /// Entries linked as configured
+    pub linked: usize,
+    /// Entries absent from the worktree, or dangling symlinks
+    pub missing: Vec<String>,
+    /// Entries present but not as configured, e.g. a regular file where a symlink belongs
+    pub differs: Vec<String>,
+}
+
+impl LinkHealth {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.differs.is_empty()
+    }
+}
+
+/// Check the configured files of a worktree. Entries whose source is missing from the
+/// root, secrets and plugin entries are left out: there is nothing to compare them to.
+pub fn link_health(project_root: &Path, worktree: &Worktree, config: &Config) -> LinkHealth {
+    let context =
+        LinkContext::new(project_root, config).for_worktree(&worktree.name, &worktree.path);
+    let mut health = LinkHealth::default();
+    for entry in &config.files {
+        let src = entry.source(project_root);
+        if !entry.link_type.needs_source() || !src.exists() {
+            continue;
+        }
+        let dst = worktree.path.join(&entry.path);
+        if !dst.exists() {
+            health.missing.push(entry.path.clone());
+        } else if is_linked(&src, &dst, entry, &context) {
+            health.linked += 1;
+        } else {
+            health.differs.push(entry.path.clone());
+        }
+    }
+    health
+}
+
+#[derive(Serialize)]
+pub struct UpstreamStatus {
+    pub upstream: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// The `branch.<name>.description` git keeps for a branch, e.g. from `git branch
+/// --edit-description`.
+pub fn branch_description(project_root: &Path, branch: &str) -> Result<Option<String>> {
+    if branch.is_empty() {
+        return Ok(None);
+    }
+    let key = format!("branch.{}.description", branch);
+    let output = git::output(project_root, &["config", "--get", &key])?;
+    let description = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((!description.is_empty()).then_some(description))
+}
+
+pub fn set_branch_description(project_root: &Path, branch: &str, description: &str) -> Result<()> {
+    let key = format!("branch.{}.description", branch);
+    git::run(project_root, &["config", &key, description])
+        .with_context(|| format!("Failed to describe branch '{}'", branch))
+}
+
+/// Tracking information for a branch, or None when it has no upstream configured.
+pub fn upstream_status(project_root: &Path, branch: &str) -> Result<Option<UpstreamStatus>> {
+    if branch.is_empty() {
+        return Ok(None);
+    }
+
+    let upstream_ref = format!("{}@{{upstream}}", branch);
+    let output = git::output(project_root, &["rev-parse", "--abbrev-ref", &upstream_ref])?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let upstream = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let range = format!("{}...{}", branch, upstream_ref);
+    let output = git::output(project_root, &["rev-list", "--left-right", "--count", &range])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git rev-list failed: {}",
             String::from_utf8_lossy(&output.stderr)
         );
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut files: Vec<String> = stdout
+    let mut counts = stdout.split_whitespace().map(|n| n.parse().unwrap_or(0));
+    Ok(Some(UpstreamStatus {
+        upstream,
+        ahead: counts.next().unwrap_or(0),
+        behind: counts.next().unwrap_or(0),
+    }))
+}
+
+/// A worktree with its group and the state of its checkout, as listed by `list --json`.
+#[derive(Serialize)]
+pub struct WorktreeStatus {
+    #[serde(flatten)]
+    pub worktree: Worktree,
+    pub group: Option<String>,
+    /// Changed and untracked files
+    pub dirty: usize,
+    pub upstream: Option<UpstreamStatus>,
+    /// Whether the current directory is in this worktree
+    pub current: bool,
+    /// The reason given with `lock`, empty when none was; None when not locked
+    pub locked: Option<String>,
+}
+
+pub fn worktree_status(
+    project_root: &Path,
+    worktree: Worktree,
+    metadata: &Metadata,
+    locks: &BTreeMap<PathBuf, String>,
+) -> Result<WorktreeStatus> {
+    Ok(WorktreeStatus {
+        group: metadata.get(&worktree.name).and_then(|meta| meta.group.clone()),
+        locked: locks.get(&worktree.path).cloned(),
+        dirty: dirty_file_count(&worktree.path)?,
+        upstream: upstream_status(project_root, &worktree.branch)?,
+        current: detect_current_worktree(project_root)?.as_deref() == Some(&worktree.name),
+        worktree,
+    })
+}
+
+/// Locked worktrees by path, with the reason given when they were locked.
+pub fn worktree_locks(project_root: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    Ok(vcs::open(project_root)
+        .list_worktrees()?
+        .into_iter()
+        .filter_map(|wt| Some((wt.path, wt.locked?)))
+        .collect())
+}
+
+/// Lock or unlock a managed worktree, as `git worktree lock` and `unlock` do.
+pub fn set_worktree_lock(
+    project_root: &Path,
+    name: &str,
+    lock: bool,
+    reason: Option<&str>,
+) -> Result<PathBuf> {
+    if is_root_name(project_root, name) {
+        return Err(messages::WORKTREE_RESERVED.error(&[("name", name)]));
+    }
+    let worktree_path = managed_path(project_root, name);
+    // A locked worktree may be on a drive that is not mounted
+    let known = worktree_path.exists() || Metadata::load(project_root)?.get(name).is_some();
+    if !known {
+        return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", name)]));
+    }
+    let vcs = vcs::open(project_root);
+    if lock {
+        vcs.lock_worktree(&worktree_path, reason)?;
+    } else {
+        vcs.unlock_worktree(&worktree_path)?;
+    }
+    Ok(worktree_path)
+}
+
+/// The object filter of a partial clone (e.g. `blob:none`), or None for a full clone.
+pub fn partial_clone_filter(project_root: &Path) -> Result<Option<String>> {
+    let output = git::output(
+        project_root,
+        &["config", "--get-regexp", r"^remote\..*\.partialclonefilter$"],
+    )?;
+
+    // Exit status 1 means no remote has a filter configured
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
         .lines()
-        .filter(|line| !line.is_empty() && !line.contains('/'))
-        .map(|line| line.to_string())
-        .collect();
-    files.sort();
-    files.dedup();
-    Ok(files)
+        .find_map(|line| line.split_once(' '))
+        .map(|(_, filter)| filter.to_string()))
+}
+
+/// Objects in the tree of `revision` that are not present locally and would be fetched
+/// from the promisor remote on checkout. Missing trees are not descended into, so with
+/// tree filters this is a lower bound.
+pub fn missing_checkout_objects(project_root: &Path, revision: &str) -> Result<usize> {
+    let output = git::output(
+        project_root,
+        &["rev-list", "--objects", "--no-walk", "--missing=print", revision],
+    )?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().filter(|line| line.starts_with('?')).count())
+}
+
+fn warn_partial_clone_checkout(project_root: &Path, revision: &str) -> Result<()> {
+    let Some(filter) = partial_clone_filter(project_root)? else {
+        return Ok(());
+    };
+    let missing = missing_checkout_objects(project_root, revision)?;
+    if missing > 0 && network::is_offline_requested() {
+        anyhow::bail!(
+            "This is a partial clone (filter {}) and checking out '{}' needs at least {} \
+            missing object(s) from the remote, but epiphyte is running offline. Use \
+            --no-checkout to skip it.",
+            filter,
+            revision,
+            missing
+        );
+    }
+    if missing > 0 {
+        eprintln!(
+            "Warning: this is a partial clone (filter {}); checking out '{}' will fetch \
+            at least {} missing object(s) from the remote. Use --no-checkout to skip it.",
+            filter, revision, missing
+        );
+    }
+    Ok(())
 }
 
 pub fn ensure_on_main_branch(project_root: &Path, main_branch: &str) -> Result<()> {
-    let current = get_current_branch(project_root)?;
+    let current = vcs::open(project_root).current_branch()?;
     if current != main_branch {
         anyhow::bail!(
             "Not on main branch. Current branch is '{}', expected '{}'. \
@@ -85,16 +417,45 @@ pub fn ensure_on_main_branch(project_root: &Path, main_branch: &str) -> Result<(
     Ok(())
 }
 
+/// Where the managed worktree `name` lives: the path recorded in its metadata when it is
+/// outside the trees dir, `.epi/trees/<name>` otherwise.
+pub fn managed_path(project_root: &Path, name: &str) -> PathBuf {
+    managed_path_in(project_root, &Metadata::load(project_root).unwrap_or_default(), name)
+}
+
+/// `managed_path` with the metadata already loaded, for loops over worktrees.
+pub fn managed_path_in(project_root: &Path, metadata: &Metadata, name: &str) -> PathBuf {
+    metadata
+        .get(name)
+        .and_then(|meta| meta.path.clone())
+        .unwrap_or_else(|| get_trees_dir(project_root).join(name))
+}
+
+/// Name of the managed worktree checked out at `path`: its directory name in the trees
+/// dir, or the metadata entry recording that path.
+fn managed_name(trees_dir: &Path, metadata: &Metadata, path: &Path) -> Option<String> {
+    if path.starts_with(trees_dir) {
+        return path.file_name().map(|n| n.to_string_lossy().to_string());
+    }
+    metadata
+        .worktrees
+        .iter()
+        .find(|(_, meta)| meta.path.as_deref() == Some(path))
+        .map(|(name, _)| name.clone())
+}
+
+/// Whether a new worktree can't be named `name`, under either layout.
+pub fn name_taken(project_root: &Path, name: &str) -> bool {
+    managed_path(project_root, name).exists() || get_sibling_dir(project_root, name).exists()
+}
+
 pub fn get_worktree_path(project_root: &Path, name: &str) -> Result<PathBuf> {
-    let trees_dir = get_trees_dir(project_root);
-    let worktree_path = trees_dir.join(name);
+    let worktree_path = managed_path(project_root, name);
 
     if !worktree_path.exists() {
-        anyhow::bail!(
-            "Worktree '{}' does not exist.\n{}",
-            name,
-            format_worktree_list(project_root)?
-        );
+        let worktrees = format_worktree_list(project_root)?;
+        return Err(messages::WORKTREE_NOT_LISTED
+            .error(&[("name", name), ("worktrees", &worktrees)]));
     }
 
     Ok(worktree_path)
@@ -107,7 +468,13 @@ pub fn detect_current_worktree(project_root: &Path) -> Result<Option<String>> {
     let trees_dir = get_trees_dir(project_root);
 
     if !current_dir.starts_with(&trees_dir) {
-        return Ok(None);
+        let metadata = Metadata::load(project_root)?;
+        let outside = metadata.worktrees.iter().find(|(_, meta)| {
+            meta.path
+                .as_deref()
+                .is_some_and(|path| current_dir.starts_with(path) && path.exists())
+        });
+        return Ok(outside.map(|(name, _)| name.clone()));
     }
 
     // Find the worktree root (direct child of trees_dir)
@@ -127,28 +494,87 @@ pub fn detect_current_worktree(project_root: &Path) -> Result<Option<String>> {
     Ok(None)
 }
 
+/// The managed worktree the current directory is in, or `@root` inside the main checkout.
+pub fn detect_current_checkout(project_root: &Path) -> Result<Option<String>> {
+    if let Some(name) = detect_current_worktree(project_root)? {
+        return Ok(Some(name));
+    }
+    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+    Ok(current_dir
+        .starts_with(project_root)
+        .then(|| ROOT_WORKTREE.to_string()))
+}
+
 /// Get the worktree name, either from the provided argument or by detecting the current worktree.
 pub fn resolve_worktree_name(project_root: &Path, name: Option<&str>) -> Result<String> {
     match name {
         Some(n) => Ok(n.to_string()),
         None => detect_current_worktree(project_root)?.ok_or_else(|| {
-            anyhow::anyhow!(
-                "Not inside a worktree. Please specify a worktree name.\n{}",
-                format_worktree_list(project_root).unwrap_or_else(|err| {
-                    format!("Failed to list worktrees: {}", err)
-                })
-            )
+            let worktrees = format_worktree_list(project_root)
+                .unwrap_or_else(|err| format!("Failed to list worktrees: {}", err));
+            messages::NOT_IN_WORKTREE.error(&[("worktrees", &worktrees)])
         }),
     }
 }
 
-pub fn enter_worktree(worktree_path: &Path) -> Result<()> {
+/// Pseudo-name of the main checkout, for commands that enter or run in a worktree.
+pub const ROOT_WORKTREE: &str = "@root";
+
+/// Whether `name` means the main checkout: `@root`, or `main` unless a managed worktree
+/// has that name.
+pub fn is_root_name(project_root: &Path, name: &str) -> bool {
+    name == ROOT_WORKTREE || (name == "main" && !managed_path(project_root, name).exists())
+}
+
+/// Find a managed worktree by name, or the main checkout for `@root`.
+pub fn find_worktree(project_root: &Path, name: &str) -> Result<Worktree> {
+    if is_root_name(project_root, name) {
+        let branch = vcs::open(project_root)
+            .list_worktrees()?
+            .into_iter()
+            .find(|wt| wt.path == project_root)
+            .map(|wt| wt.branch)
+            .unwrap_or_default();
+        return Ok(Worktree {
+            name: ROOT_WORKTREE.to_string(),
+            path: project_root.to_path_buf(),
+            branch,
+        });
+    }
+    let path = get_worktree_path(project_root, name)?;
+    let worktree = list_worktrees(project_root)?
+        .into_iter()
+        .find(|wt| wt.name == name)
+        .unwrap_or(Worktree {
+            name: name.to_string(),
+            path,
+            branch: String::new(),
+        });
+    Ok(worktree)
+}
+
+/// Start an interactive shell in a worktree: `enter_command` through `sh -c` when given,
+/// otherwise $SHELL.
+pub fn enter_worktree(
+    worktree_path: &Path,
+    env: &[(String, String)],
+    enter_command: Option<&str>,
+) -> Result<()> {
     let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let mut command = match enter_command {
+        Some(enter_command) => {
+            let mut command = Command::new("sh");
+            command.args(["-c", enter_command]);
+            command
+        }
+        None => Command::new(&shell),
+    };
 
-    let status = Command::new(&shell)
+    command
         .current_dir(worktree_path)
-        .status()
-        .with_context(|| format!("Failed to spawn shell: {}", shell))?;
+        .envs(env.iter().map(|(k, v)| (k, v)));
+    let status = trace::status(&mut command)
+        .with_context(|| format!("Failed to spawn shell: {}", enter_command.unwrap_or(&shell)))?;
 
     if !status.success() {
         if let Some(code) = status.code() {
@@ -159,27 +585,136 @@ pub fn enter_worktree(worktree_path: &Path) -> Result<()> {
     Ok(())
 }
 
-#[derive(Clone)]
+/// Run a command inside a worktree and return its exit code.
+pub fn exec_in_worktree(
+    worktree_path: &Path,
+    command: &[String],
+    env: &[(String, String)],
+) -> Result<i32> {
+    let (program, args) = command
+        .split_first()
+        .context("No command given")?;
+
+    let mut command_builder = Command::new(program);
+    command_builder
+        .args(args)
+        .current_dir(worktree_path)
+        .envs(env.iter().map(|(k, v)| (k, v)));
+    let status = trace::status(&mut command_builder)
+        .with_context(|| format!("Failed to run command: {}", program))?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+#[derive(Clone, Serialize)]
 pub struct Worktree {
     pub name: String,
     pub path: PathBuf,
     pub branch: String,
 }
 
-struct GitWorktree {
-    path: PathBuf,
-    branch: String,
+/// A path inside a worktree that an operation touched.
+#[derive(Serialize)]
+pub struct WorktreePath {
+    pub worktree: String,
+    pub path: PathBuf,
 }
 
+/// A path inside a worktree that an operation failed on.
+#[derive(Serialize)]
+pub struct WorktreePathFailure {
+    pub worktree: String,
+    pub path: PathBuf,
+    pub error: String,
+    pub kind: FailureKind,
+}
+
+#[derive(Serialize)]
 pub struct SymlinkRemovalReport {
-    pub removed: Vec<(String, PathBuf)>,
-    pub failed: Vec<(String, PathBuf, String)>,
+    pub removed: Vec<WorktreePath>,
+    pub failed: Vec<WorktreePathFailure>,
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct LinkReport {
-    pub linked: Vec<(String, PathBuf)>,
-    pub failed: Vec<(String, PathBuf, String)>,
+    /// Destinations that did not exist before
+    pub created: Vec<WorktreePath>,
+    /// Destinations that existed and were overwritten
+    pub replaced: Vec<WorktreePath>,
+    /// Symlinks that pointed somewhere other than their source, e.g. stale absolute paths
+    pub repaired: Vec<WorktreePath>,
+    /// Destinations already linked or copied, left alone
+    pub unchanged: Vec<WorktreePath>,
+    /// Configured sources that do not exist in the project root
+    pub missing: Vec<PathBuf>,
+    pub failed: Vec<WorktreePathFailure>,
+}
+
+impl LinkReport {
+    fn link(
+        &mut self,
+        worktree: &str,
+        src: &Path,
+        dst: PathBuf,
+        entry: &FileEntry,
+        context: &LinkContext,
+    ) {
+        let linked = WorktreePath {
+            worktree: worktree.to_string(),
+            path: dst,
+        };
+        if is_linked(src, &linked.path, entry, context) {
+            self.unchanged.push(linked);
+            return;
+        }
+        let existing = linked.path.symlink_metadata().ok();
+        let was_symlink = existing.as_ref().is_some_and(|meta| meta.file_type().is_symlink());
+        match link_entry(src, &linked.path, entry, context) {
+            Ok(()) if was_symlink => self.repaired.push(linked),
+            Ok(()) if existing.is_some() => self.replaced.push(linked),
+            Ok(()) => self.created.push(linked),
+            Err(err) => self.failed.push(WorktreePathFailure {
+                worktree: linked.worktree,
+                path: linked.path,
+                error: format!("{:#}", err),
+                kind: FailureKind::of(&err),
+            }),
+        }
+    }
+
+    /// Add the outcomes of another run, e.g. for another worktree.
+    pub fn extend(&mut self, other: LinkReport) {
+        self.created.extend(other.created);
+        self.replaced.extend(other.replaced);
+        self.repaired.extend(other.repaired);
+        self.unchanged.extend(other.unchanged);
+        for src in other.missing {
+            if !self.missing.contains(&src) {
+                self.missing.push(src);
+            }
+        }
+        self.failed.extend(other.failed);
+    }
+
+    /// Number of missing sources and failed entries.
+    pub fn problems(&self) -> usize {
+        self.missing.len() + self.failed.len()
+    }
+
+    /// Whether any destination was created, replaced or repaired.
+    pub fn changed(&self) -> bool {
+        !self.created.is_empty() || !self.replaced.is_empty() || !self.repaired.is_empty()
+    }
+}
+
+/// A worktree created by `add_worktree`, with the outcome of linking configured files.
+#[derive(Serialize)]
+pub struct AddReport {
+    pub worktree: Worktree,
+    pub links: LinkReport,
+    /// Set by `add --apply`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<PatchReport>,
 }
 
 impl std::fmt::Display for Worktree {
@@ -198,22 +733,26 @@ impl std::fmt::Display for Worktree {
     }
 }
 
+/// Worktrees in the trees dir or recorded in the metadata, without those the config
+/// lists as `unmanaged`.
+pub fn list_managed_worktrees(project_root: &Path, config: &Config) -> Result<Vec<Worktree>> {
+    let mut worktrees = list_worktrees(project_root)?;
+    worktrees.retain(|wt| !config.unmanaged.contains(&wt.name));
+    Ok(worktrees)
+}
+
 pub fn list_worktrees(project_root: &Path) -> Result<Vec<Worktree>> {
     let trees_dir = get_trees_dir(project_root);
-    if !trees_dir.exists() {
+    let metadata = Metadata::load(project_root)?;
+    if !trees_dir.exists() && metadata.worktrees.values().all(|meta| meta.path.is_none()) {
         return Ok(Vec::new());
     }
 
-    let worktrees = list_git_worktrees(project_root)?;
+    let worktrees = vcs::open(project_root).list_worktrees()?;
     let mut managed = Vec::new();
 
     for wt in worktrees {
-        if wt.path.starts_with(&trees_dir) {
-            let name = wt
-                .path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
+        if let Some(name) = managed_name(&trees_dir, &metadata, &wt.path) {
             managed.push(Worktree {
                 name,
                 path: wt.path,
@@ -225,35 +764,74 @@ pub fn list_worktrees(project_root: &Path) -> Result<Vec<Worktree>> {
     Ok(managed)
 }
 
+/// Find the checkout that has `branch` checked out, returning a label (the managed
+/// worktree name, "(root)" or "(unmanaged)") and its path.
+pub fn find_branch_checkout(project_root: &Path, branch: &str) -> Result<Option<(String, PathBuf)>> {
+    let trees_dir = get_trees_dir(project_root);
+    let metadata = Metadata::load(project_root)?;
+    let checkout = vcs::open(project_root)
+        .list_worktrees()?
+        .into_iter()
+        .find(|wt| wt.branch == branch);
+
+    Ok(checkout.map(|wt| {
+        let label = if wt.path == project_root {
+            "(root)".to_string()
+        } else {
+            managed_name(&trees_dir, &metadata, &wt.path)
+                .unwrap_or_else(|| "(unmanaged)".to_string())
+        };
+        (label, wt.path)
+    }))
+}
+
+#[derive(Serialize)]
 pub struct ImportMove {
+    pub name: String,
     pub from: PathBuf,
     pub to: PathBuf,
     pub relink_error: Option<String>,
 }
 
+#[derive(Serialize)]
 pub struct ImportSkip {
     pub path: PathBuf,
     pub reason: String,
 }
 
+#[derive(Serialize)]
 pub struct ImportFailure {
     pub path: PathBuf,
     pub error: String,
+    pub kind: FailureKind,
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct ImportReport {
     pub moved: Vec<ImportMove>,
     pub skipped: Vec<ImportSkip>,
     pub failed: Vec<ImportFailure>,
 }
 
-pub fn import_all_worktrees(project_root: &Path, config: &Config) -> Result<ImportReport> {
+/// Create the trees dir if needed, applying the configured directory mode to it and
+/// to the config dir that contains it.
+fn ensure_trees_dir(project_root: &Path, config: &Config) -> Result<PathBuf> {
+    let mode = config.permissions.dir_mode;
+    let config_dir = project_root.join(CONFIG_DIR);
+    perms::ensure_dir(&config_dir, mode)
+        .with_context(|| format!("Failed to create config dir: {}", config_dir.display()))?;
     let trees_dir = get_trees_dir(project_root);
-    fs::create_dir_all(&trees_dir)
+    perms::ensure_dir(&trees_dir, mode)
         .with_context(|| format!("Failed to create trees dir: {}", trees_dir.display()))?;
+    Ok(trees_dir)
+}
+
+pub fn import_all_worktrees(project_root: &Path, config: &Config) -> Result<ImportReport> {
+    let trees_dir = ensure_trees_dir(project_root, config)?;
+    let metadata = Metadata::load(project_root)?;
 
-    let worktrees = list_git_worktrees(project_root)?;
+    let vcs = vcs::open(project_root);
+    let worktrees = vcs.list_worktrees()?;
     let mut report = ImportReport::default();
 
     for wt in worktrees {
@@ -264,7 +842,7 @@ pub fn import_all_worktrees(project_root: &Path, config: &Config) -> Result<Impo
             });
             continue;
         }
-        if wt.path.starts_with(&trees_dir) {
+        if managed_name(&trees_dir, &metadata, &wt.path).is_some() {
             report.skipped.push(ImportSkip {
                 path: wt.path,
                 reason: "already managed".to_string(),
@@ -272,59 +850,105 @@ pub fn import_all_worktrees(project_root: &Path, config: &Config) -> Result<Impo
             continue;
         }
 
-        let src_path = wt.path;
-        let base_name = src_path
-            .file_name()
-            .map(|n| n.to_string_lossy().to_string())
-            .unwrap_or_else(|| "worktree".to_string());
-        let dest = unique_import_path(&trees_dir, &base_name);
-        let src_str = src_path.to_string_lossy().to_string();
-        let dest_str = dest.to_string_lossy().to_string();
+        let name = unique_name(project_root, &directory_name(&wt.path));
+        match import_worktree(project_root, config, wt.path.clone(), wt.branch, name) {
+            Ok(moved) => report.moved.push(moved),
+            Err(err) => report.failed.push(ImportFailure {
+                path: wt.path,
+                error: err.to_string(),
+                kind: FailureKind::of(&err),
+            }),
+        }
+    }
 
-        let output = Command::new("git")
-            .args(["worktree", "move", &src_str, &dest_str])
-            .current_dir(project_root)
-            .output()
-            .context("Failed to run git worktree move")?;
+    Ok(report)
+}
 
-        if !output.status.success() {
-            report.failed.push(ImportFailure {
-                path: src_path,
-                error: format!(
-                    "git worktree move failed: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ),
-            });
-            continue;
+/// Import the one worktree at `path`, named after its directory unless `name` is given.
+pub fn adopt_worktree(
+    project_root: &Path,
+    path: &Path,
+    name: Option<&str>,
+    config: &Config,
+) -> Result<ImportMove> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let trees_dir = ensure_trees_dir(project_root, config)?;
+    let worktree = vcs::open(project_root)
+        .list_worktrees()?
+        .into_iter()
+        .find(|wt| wt.path.canonicalize().is_ok_and(|wt_path| wt_path == path))
+        .with_context(|| {
+            format!("{} is not a worktree of {}", path.display(), project_root.display())
+        })?;
+    if worktree.path == project_root {
+        anyhow::bail!("{} is the main checkout", path.display());
+    }
+    let metadata = Metadata::load(project_root)?;
+    if let Some(managed) = managed_name(&trees_dir, &metadata, &worktree.path) {
+        anyhow::bail!("{} is already managed as '{}'", path.display(), managed);
+    }
+
+    let name = match name {
+        Some(name) if is_root_name(project_root, name) => {
+            return Err(messages::WORKTREE_RESERVED.error(&[("name", name)]));
+        }
+        Some(name) if name_taken(project_root, name) => {
+            return Err(messages::WORKTREE_EXISTS.error(&[("name", name)]));
         }
+        Some(name) => name.to_string(),
+        None => unique_name(project_root, &directory_name(&worktree.path)),
+    };
+    import_worktree(project_root, config, worktree.path, worktree.branch, name)
+}
 
-        let name = dest
-            .file_name()
-            .map(|n| n.to_string_lossy().to_string())
-            .unwrap_or_default();
-        let relink_error = if name.is_empty() {
-            Some("relink failed: unable to determine worktree name".to_string())
-        } else {
-            relink_worktree(project_root, &name, config)
-                .err()
-                .map(|err| format!("relink failed: {}", err))
-        };
+fn directory_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
 
-        report.moved.push(ImportMove {
-            from: src_path,
-            to: dest,
-            relink_error,
-        });
-    }
+/// Move the worktree at `src_path` to the directory of `name`, record it, and link the
+/// configured files into it. Linking problems are reported, not failures.
+fn import_worktree(
+    project_root: &Path,
+    config: &Config,
+    src_path: PathBuf,
+    branch: String,
+    name: String,
+) -> Result<ImportMove> {
+    let dest = config.worktree_dir(project_root, &name);
+    vcs::open(project_root).move_worktree(&src_path, &dest)?;
+
+    let worktree = Worktree {
+        name,
+        path: dest,
+        branch,
+    };
+    let relink_error = match record_location(project_root, &worktree.name, &worktree.path)
+        .and_then(|()| relink_worktree(project_root, &worktree, config, false))
+    {
+        Ok(links) => links.failed.first().map(|failure| {
+            format!("relink failed: {}: {}", failure.path.display(), failure.error)
+        }),
+        Err(err) => Some(format!("relink failed: {}", err)),
+    };
 
-    Ok(report)
+    Ok(ImportMove {
+        name: worktree.name,
+        from: src_path,
+        to: worktree.path,
+        relink_error,
+    })
 }
 
 pub fn remove_symlinks_from_worktrees(
     project_root: &Path,
     rel_path: &str,
+    config: &Config,
 ) -> Result<SymlinkRemovalReport> {
-    let worktrees = list_worktrees(project_root)?;
+    let worktrees = list_managed_worktrees(project_root, config)?;
     let mut removed = Vec::new();
     let mut failed = Vec::new();
 
@@ -336,25 +960,49 @@ pub fn remove_symlinks_from_worktrees(
             Ok(metadata) => {
                 if metadata.file_type().is_symlink() {
                     if let Err(err) = fs::remove_file(&dst) {
-                        failed.push((name, dst, err.to_string()));
+                        failed.push(WorktreePathFailure {
+                            worktree: name,
+                            path: dst,
+                            error: err.to_string(),
+                            kind: FailureKind::of_io(&err),
+                        });
                     } else {
-                        removed.push((name, dst));
+                        removed.push(WorktreePath {
+                            worktree: name,
+                            path: dst,
+                        });
                     }
                 }
             }
             Err(err) if err.kind() == io::ErrorKind::NotFound => {}
-            Err(err) => failed.push((name, dst, err.to_string())),
+            Err(err) => failed.push(WorktreePathFailure {
+                worktree: name,
+                path: dst,
+                error: err.to_string(),
+                kind: FailureKind::of_io(&err),
+            }),
         }
     }
 
     Ok(SymlinkRemovalReport { removed, failed })
 }
 
+/// Link entries into existing worktrees: all of them, or only those named in `targets`.
 pub fn link_entries_to_worktrees(
     project_root: &Path,
     entries: &[FileEntry],
+    targets: Option<&[String]>,
+    config: &Config,
 ) -> Result<LinkReport> {
-    let worktrees = list_worktrees(project_root)?;
+    let mut worktrees = list_managed_worktrees(project_root, config)?;
+    if let Some(targets) = targets {
+        worktrees.retain(|wt| targets.contains(&wt.name));
+    }
+    let context = LinkContext::new(project_root, config);
+    let contexts: Vec<LinkContext> = worktrees
+        .iter()
+        .map(|worktree| context.for_worktree(&worktree.name, &worktree.path))
+        .collect();
     let mut report = LinkReport::default();
 
     if worktrees.is_empty() || entries.is_empty() {
@@ -362,27 +1010,16 @@ pub fn link_entries_to_worktrees(
     }
 
     for entry in entries {
-        let src = project_root.join(&entry.path);
-        if !src.exists() {
-            eprintln!(
-                "Warning: source file does not exist: {}",
-                src.display()
-            );
+        let src = entry.source(project_root);
+        // Secrets and plugins may create files from nothing
+        if !src.exists() && entry.link_type.needs_source() {
+            report.missing.push(src);
             continue;
         }
 
-        for worktree in &worktrees {
+        for (worktree, context) in worktrees.iter().zip(&contexts) {
             let dst = worktree.path.join(&entry.path);
-            match link_entry(&src, &dst, &entry.link_type) {
-                Ok(()) => report
-                    .linked
-                    .push((worktree.name.clone(), dst)),
-                Err(err) => report.failed.push((
-                    worktree.name.clone(),
-                    dst,
-                    err.to_string(),
-                )),
-            }
+            report.link(&worktree.name, &src, dst, entry, context);
         }
     }
 
@@ -399,6 +1036,9 @@ pub fn select_worktree_name(project_root: &Path) -> Result<Option<String>> {
     }
 
     let names: Vec<String> = worktrees.into_iter().map(|wt| wt.name).collect();
+    if let Some(name) = prompt::env_choice(prompt::SELECT_WORKTREE, &names)? {
+        return Ok(Some(name));
+    }
     let selection = Select::new("Select worktree", names).prompt();
     match selection {
         Ok(name) => Ok(Some(name)),
@@ -407,6 +1047,67 @@ pub fn select_worktree_name(project_root: &Path) -> Result<Option<String>> {
     }
 }
 
+/// Prompt for a local or remote-tracking branch, most recently committed first, leaving
+/// out branches already checked out. Remote branches are returned without the remote, so
+/// git creates a local branch tracking it.
+pub fn select_branch(project_root: &Path) -> Result<Option<String>> {
+    let checked_out: Vec<String> = vcs::open(project_root)
+        .list_worktrees()?
+        .into_iter()
+        .map(|wt| wt.branch)
+        .collect();
+    let output = git::output(
+        project_root,
+        &[
+            "for-each-ref",
+            "--sort=-committerdate",
+            "--format=%(refname)\t%(refname:short)\t%(committerdate:relative)",
+            "refs/heads",
+            "refs/remotes",
+        ],
+    )?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git for-each-ref failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut branches = Vec::new();
+    let mut labels = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(refname), Some(short), Some(date)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let branch = match refname.strip_prefix("refs/remotes/") {
+            Some(remote_ref) => match remote_ref.split_once('/') {
+                Some((_, "HEAD")) | None => continue,
+                Some((_, branch)) => branch.to_string(),
+            },
+            None => short.to_string(),
+        };
+        if branches.contains(&branch) || checked_out.contains(&branch) {
+            continue;
+        }
+        branches.push(branch);
+        labels.push(format!("{}  ({})", short, date));
+    }
+    if branches.is_empty() {
+        anyhow::bail!("No branches found.");
+    }
+    if let Some(branch) = prompt::env_choice(prompt::SELECT_BRANCH, &branches)? {
+        return Ok(Some(branch));
+    }
+
+    match Select::new("Select branch", labels).raw_prompt() {
+        Ok(choice) => Ok(Some(branches.swap_remove(choice.index))),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(None),
+        Err(err) => Err(err).context("Failed to prompt for branch selection"),
+    }
+}
+
 fn format_worktree_list(project_root: &Path) -> Result<String> {
     let worktrees = list_worktrees(project_root)?;
     if worktrees.is_empty() {
@@ -431,217 +1132,589 @@ fn format_worktree_list(project_root: &Path) -> Result<String> {
     Ok(String::from_utf8_lossy(&output).trim_end().to_string())
 }
 
+/// Pick a free worktree name: a slug of `branch` without its remote, when given,
+/// otherwise the next unused `review-<n>`.
+pub fn auto_worktree_name(project_root: &Path, branch: Option<&str>) -> String {
+    if let Some(branch) = branch {
+        let remotes = remotes(project_root).unwrap_or_default();
+        let local = match branch.split_once('/') {
+            Some((remote, local)) if remotes.iter().any(|r| r == remote) => local,
+            _ => branch,
+        };
+        let slug: String = local
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let slug = slug.trim_matches('-');
+        return unique_name(project_root, slug);
+    }
+
+    let mut index = 1;
+    loop {
+        let name = format!("review-{}", index);
+        if !name_taken(project_root, &name) {
+            return name;
+        }
+        index += 1;
+    }
+}
+
+/// The names of the repository's remotes.
+fn remotes(project_root: &Path) -> Result<Vec<String>> {
+    let output = git::output(project_root, &["remote"])?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// When `branch` names a branch that only exists on a remote, `<remote>/<branch>` or a
+/// bare name found on exactly one remote, fetch it and return its remote-tracking ref and
+/// its local name. Offline, the remote-tracking ref is used as last fetched.
+fn fetch_remote_branch(
+    project_root: &Path,
+    branch: &str,
+    progress: &Progress,
+) -> Result<Option<(String, String)>> {
+    // jj tracks remote bookmarks itself
+    if project_root.join(vcs::JJ_DIR).is_dir() || vcs::open(project_root).branch_exists(branch)? {
+        return Ok(None);
+    }
+    let remotes = remotes(project_root)?;
+    let ref_exists = |refname: &str| {
+        git::output(project_root, &["show-ref", "--verify", "--quiet", refname])
+            .is_ok_and(|output| output.status.success())
+    };
+
+    let (remote, local) = match branch.split_once('/') {
+        Some((remote, local)) if remotes.iter().any(|r| r == remote) => {
+            (remote.to_string(), local.to_string())
+        }
+        _ => {
+            let mut found = remotes
+                .into_iter()
+                .filter(|remote| ref_exists(&format!("refs/remotes/{}/{}", remote, branch)));
+            match (found.next(), found.next()) {
+                (Some(remote), None) => (remote, branch.to_string()),
+                _ => return Ok(None),
+            }
+        }
+    };
+    let remote_ref = format!("{}/{}", remote, local);
+    let tracking_ref = format!("refs/remotes/{}", remote_ref);
+    if !network::is_offline() || !ref_exists(&tracking_ref) {
+        network::ensure_online(&format!("Fetching {}", remote_ref))?;
+        progress.phase(&format!("Fetching {}", remote_ref));
+        let refspec = format!("+refs/heads/{}:{}", local, tracking_ref);
+        git::run(project_root, &["fetch", &remote, &refspec])
+            .with_context(|| format!("Failed to fetch branch {}", remote_ref))?;
+    }
+    Ok(Some((remote_ref, local)))
+}
+
+/// Optional behavior of `add_worktree`.
+#[derive(Default)]
+pub struct AddOptions {
+    /// Register the worktree without populating its files
+    pub no_checkout: bool,
+    /// Recorded for the worktree and matched against the `only_profiles` of hooks
+    pub profile: Option<String>,
+    /// Recorded for the worktree and matched against the `only_tags` of hooks
+    pub tags: Vec<String>,
+    /// Set as the git description of the worktree's branch
+    pub description: Option<String>,
+    /// Commit a new branch starts from, instead of HEAD of the main checkout
+    pub start_point: Option<String>,
+    /// Show the phase the creation is in on stderr
+    pub progress: bool,
+}
+
 pub fn add_worktree(
     project_root: &Path,
     name: &str,
     branch: Option<&str>,
     config: &Config,
-) -> Result<PathBuf> {
-    let trees_dir = get_trees_dir(project_root);
-    fs::create_dir_all(&trees_dir)
-        .with_context(|| format!("Failed to create trees dir: {}", trees_dir.display()))?;
+    options: &AddOptions,
+) -> Result<AddReport> {
+    if name == ROOT_WORKTREE {
+        return Err(messages::WORKTREE_RESERVED.error(&[("name", ROOT_WORKTREE)]));
+    }
+    ensure_trees_dir(project_root, config)?;
 
-    let worktree_path = trees_dir.join(name);
-    if worktree_path.exists() {
-        anyhow::bail!("Worktree '{}' already exists", name);
+    let worktree_path = config.worktree_dir(project_root, name);
+    if worktree_path.exists() || managed_path(project_root, name).exists() {
+        return Err(messages::WORKTREE_EXISTS.error(&[("name", name)]));
     }
 
-    let worktree_path_str = worktree_path.to_string_lossy().to_string();
+    let vcs = vcs::open(project_root);
+    trust::ensure_trusted(project_root, config)?;
+    let progress = Progress::new(options.progress);
 
     // Determine the branch to use and whether to create a new one
+    let mut start_point = options.start_point.clone();
+    let mut upstream = None;
     let (branch_name, create_new_branch) = if let Some(b) = branch {
-        // Explicit branch specified - use it as-is (checkout existing)
-        (b.to_string(), false)
-    } else if branch_exists(project_root, name)? {
+        match fetch_remote_branch(project_root, b, &progress)? {
+            // Only on a remote - create a local branch tracking it
+            Some((remote_ref, local)) if !vcs.branch_exists(&local)? => {
+                start_point = Some(remote_ref.clone());
+                upstream = Some(remote_ref);
+                (local, true)
+            }
+            Some((_, local)) => (local, false),
+            // Explicit branch specified - use it as-is (checkout existing)
+            None => (b.to_string(), false),
+        }
+    } else if vcs.branch_exists(name)? {
         // Branch with the same name as worktree already exists - checkout it
         (name.to_string(), false)
     } else {
         // No existing branch - create a new one
         (name.to_string(), true)
     };
+    if let (false, Some(start_point)) = (create_new_branch, &options.start_point) {
+        anyhow::bail!(
+            "Branch '{}' already exists; it cannot be started from {}",
+            branch_name,
+            start_point
+        );
+    }
 
-    let args: Vec<&str> = if create_new_branch {
-        vec!["worktree", "add", "-b", &branch_name, &worktree_path_str]
-    } else {
-        vec!["worktree", "add", &worktree_path_str, &branch_name]
-    };
+    let applies = |hook: &&Hook| hook.applies_to(options.profile.as_deref(), &options.tags);
+    if let Some(hook) = config.hooks.pre_create.as_ref().filter(applies) {
+        let pending = Worktree {
+            name: name.to_string(),
+            path: worktree_path.clone(),
+            branch: branch_name.clone(),
+        };
+        let env = worktree_env(project_root, &pending, config, None)?;
+        let payload = HookPayload {
+            event: "pre_create",
+            root: project_root,
+            worktree: &pending,
+            files: config.files.iter().map(|f| f.path.as_str()).collect(),
+        };
+        progress
+            .interactive("Running pre_create hook", || {
+                run_hook(hook, &config.hooks, project_root, &env, &payload)
+            })
+            .context("Worktree creation aborted by pre_create hook")?;
+    }
 
-    let output = Command::new("git")
-        .args(&args)
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git worktree add")?;
+    if !options.no_checkout && !project_root.join(vcs::JJ_DIR).is_dir() {
+        let revision = match &start_point {
+            _ if !create_new_branch => branch_name.as_str(),
+            Some(start_point) => start_point.as_str(),
+            None => "HEAD",
+        };
+        warn_partial_clone_checkout(project_root, revision)?;
+    }
 
-    if !output.status.success() {
-        anyhow::bail!(
-            "git worktree add failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+    progress.phase(&format!("Creating worktree at {}", worktree_path.display()));
+    vcs.add_worktree(
+        &worktree_path,
+        &branch_name,
+        create_new_branch,
+        start_point.as_deref(),
+        !options.no_checkout,
+    )?;
+    if let Some(upstream) = &upstream {
+        git::run(project_root, &["branch", "--set-upstream-to", upstream, &branch_name])?;
+    }
+    record_location(project_root, name, &worktree_path)?;
+    // Recorded before the steps that may fail, so a worktree that exists is never
+    // missing its profile and tags
+    let mut metadata = Metadata::load(project_root)?;
+    let meta = metadata.entry(name);
+    meta.branch = Some(branch_name.clone());
+    // Branches tracking a remote one start with commits of their own
+    if create_new_branch && upstream.is_none() {
+        meta.start_commit = branch_commit(project_root, &branch_name)?;
+    }
+    meta.profile = options.profile.clone();
+    meta.tags.extend(options.tags.iter().cloned());
+    metadata.record_operation(name, "add");
+    metadata.save(project_root)?;
+    if let Some(description) = &options.description {
+        set_branch_description(project_root, &branch_name, description)?;
     }
 
     // Link/copy configured files
-    link_files(project_root, &worktree_path, config)?;
+    progress.phase("Linking files");
+    let links = link_files(project_root, name, &worktree_path, config, false);
+    let worktree = Worktree {
+        name: name.to_string(),
+        path: worktree_path,
+        branch: branch_name,
+    };
 
-    Ok(worktree_path)
+    if let Some(devcontainer) = &config.devcontainer {
+        devcontainer::write_config(project_root, &worktree, devcontainer)?;
+    }
+
+    if config.scratch {
+        scratch::ensure(&worktree.path, config.permissions.dir_mode)?;
+    }
+
+    if let Some(hook) = config.hooks.post_create.as_ref().filter(applies) {
+        let env = worktree_env(project_root, &worktree, config, None)?;
+        let payload = HookPayload {
+            event: "post_create",
+            root: project_root,
+            worktree: &worktree,
+            files: config.files.iter().map(|f| f.path.as_str()).collect(),
+        };
+        progress
+            .interactive("Running post_create hook", || {
+                run_hook(hook, &config.hooks, &worktree.path, &env, &payload)
+            })
+            .with_context(|| {
+                format!("Worktree '{}' was created but its post_create hook failed", name)
+            })?;
+    }
+
+    if let Some(notify_config) = &config.notify {
+        notify::send(
+            notify_config,
+            &notify::Event {
+                event: "worktree_created",
+                root: project_root,
+                worktree: Some(name),
+                path: Some(&worktree.path),
+                details: vec![format!("branch {}", worktree.branch)],
+            },
+        );
+    }
+
+    Ok(AddReport {
+        worktree,
+        links,
+        patch: None,
+    })
 }
 
-fn link_files(project_root: &Path, worktree_path: &Path, config: &Config) -> Result<()> {
+fn link_files(
+    project_root: &Path,
+    name: &str,
+    worktree_path: &Path,
+    config: &Config,
+    refresh_secrets: bool,
+) -> LinkReport {
+    let context = LinkContext::new(project_root, config)
+        .for_worktree(name, worktree_path)
+        .refreshing_secrets(refresh_secrets);
+    let mut report = LinkReport::default();
     for entry in &config.files {
-        let src = project_root.join(&entry.path);
-        let dst = worktree_path.join(&entry.path);
-
-        if !src.exists() {
-            eprintln!("Warning: source file does not exist: {}", src.display());
+        let src = entry.source(project_root);
+        // Secrets and plugins may create files from nothing
+        if !src.exists() && entry.link_type.needs_source() {
+            report.missing.push(src);
             continue;
         }
 
-        link_entry(&src, &dst, &entry.link_type)?;
+        let dst = worktree_path.join(&entry.path);
+        report.link(name, &src, dst, entry, &context);
     }
+    report
+}
 
-    Ok(())
+/// Link a single configured entry into a worktree, replacing what is there.
+pub fn link_one(
+    project_root: &Path,
+    name: &str,
+    path: &str,
+    config: &Config,
+) -> Result<LinkReport> {
+    let worktree_path = get_worktree_path(project_root, name)?;
+    let Some(entry) = config.files.iter().find(|entry| entry.path == path) else {
+        anyhow::bail!("'{}' is not a configured file", path);
+    };
+    let context = LinkContext::new(project_root, config).for_worktree(name, &worktree_path);
+    let mut report = LinkReport::default();
+    let src = entry.source(project_root);
+    if src.exists() || !entry.link_type.needs_source() {
+        report.link(name, &src, worktree_path.join(&entry.path), entry, &context);
+    } else {
+        report.missing.push(src);
+    }
+    Ok(report)
 }
 
-fn link_entry(src: &Path, dst: &Path, link_type: &LinkType) -> Result<()> {
-    // Create parent directories for destination
-    if let Some(parent) = dst.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create parent dir: {}", parent.display()))?;
+/// Remove a managed worktree and forget its metadata. Without `force`, worktrees
+/// with uncommitted changes are left alone.
+pub fn remove_worktree(
+    project_root: &Path,
+    name: &str,
+    force: bool,
+    config: &Config,
+) -> Result<PathBuf> {
+    let worktree_path = managed_path(project_root, name);
+    if !worktree_path.exists() {
+        return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", name)]));
     }
 
-    // Remove existing destination if it exists
-    if dst.exists() || dst.symlink_metadata().is_ok() {
-        if dst.is_dir() && !dst.symlink_metadata()?.file_type().is_symlink() {
-            fs::remove_dir_all(dst)?;
-        } else {
-            fs::remove_file(dst)?;
-        }
+    vcs::open(project_root).remove_worktree(&worktree_path, force)?;
+    if worktree_path.exists() {
+        fs::remove_dir_all(&worktree_path).with_context(|| {
+            format!("Failed to remove worktree dir: {}", worktree_path.display())
+        })?;
     }
 
-    match link_type {
-        LinkType::Symlink => {
-            #[cfg(unix)]
-            {
-                std::os::unix::fs::symlink(src, dst).with_context(|| {
-                    format!("Failed to symlink {} -> {}", src.display(), dst.display())
-                })?;
-            }
-            #[cfg(windows)]
-            {
-                if src.is_dir() {
-                    std::os::windows::fs::symlink_dir(src, dst)?;
-                } else {
-                    std::os::windows::fs::symlink_file(src, dst)?;
-                }
-            }
-        }
-        LinkType::Copy => {
-            if src.is_dir() {
-                copy_dir_recursive(src, dst)?;
-            } else {
-                fs::copy(src, dst).with_context(|| {
-                    format!("Failed to copy {} -> {}", src.display(), dst.display())
-                })?;
-            }
-        }
+    let mut metadata = Metadata::load(project_root)?;
+    if metadata.get(name).is_some() {
+        metadata.remove(name);
+        metadata.save(project_root)?;
     }
 
-    Ok(())
+    // Blobs only linked from the removed worktree are now unreferenced
+    store::gc(&get_store_dir(project_root))?;
+
+    if let Some(notify_config) = &config.notify {
+        notify::send(
+            notify_config,
+            &notify::Event {
+                event: "worktree_removed",
+                root: project_root,
+                worktree: Some(name),
+                path: Some(&worktree_path),
+                details: Vec::new(),
+            },
+        );
+    }
+
+    Ok(worktree_path)
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
-    fs::create_dir_all(dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            fs::copy(&src_path, &dst_path)?;
+/// Managed worktrees whose branch the main branch already contains. Branches still at
+/// the commit `add` created them at, which the main branch trivially contains, don't count.
+pub fn merged_worktrees(project_root: &Path, config: &Config) -> Result<Vec<Worktree>> {
+    let metadata = Metadata::load(project_root)?;
+    let mut merged = Vec::new();
+    for worktree in list_managed_worktrees(project_root, config)? {
+        let branch = &worktree.branch;
+        if branch.is_empty() || *branch == config.main_branch {
+            continue;
+        }
+        let contained = git::output(
+            project_root,
+            &["merge-base", "--is-ancestor", branch, &config.main_branch],
+        )?
+        .status
+        .success();
+        if !contained {
+            continue;
+        }
+        // Branches not created by `add`, like remote tracking and pull request branches,
+        // have commits of their own from the start
+        let start = metadata
+            .get(&worktree.name)
+            .filter(|meta| meta.branch.as_ref() == Some(branch))
+            .and_then(|meta| meta.start_commit.clone());
+        if start.is_none() || branch_commit(project_root, branch)? != start {
+            merged.push(worktree);
         }
     }
-    Ok(())
+    Ok(merged)
 }
 
-pub fn relink_worktree(project_root: &Path, name: &str, config: &Config) -> Result<()> {
-    let trees_dir = get_trees_dir(project_root);
-    let worktree_path = trees_dir.join(name);
+/// The commit `branch` points to, or None when there is no such local branch.
+fn branch_commit(project_root: &Path, branch: &str) -> Result<Option<String>> {
+    let reference = format!("refs/heads/{}", branch);
+    let output = git::output(project_root, &["rev-parse", "--verify", "--quiet", &reference])?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
 
-    if !worktree_path.exists() {
-        anyhow::bail!("Worktree '{}' does not exist", name);
+/// A worktree moved to a new name by `rename_worktree`.
+#[derive(Serialize)]
+pub struct RenameReport {
+    pub path: PathBuf,
+    /// The old and new name of the branch, when it was renamed along
+    pub branch: Option<(String, String)>,
+    pub links: LinkReport,
+}
+
+/// Move a worktree to the directory of `new_name`, keeping its layout, and link the
+/// configured files again at the new path. With `rename_branch`, its branch is renamed
+/// to `new_name` too.
+pub fn rename_worktree(
+    project_root: &Path,
+    old_name: &str,
+    new_name: &str,
+    rename_branch: bool,
+    config: &Config,
+) -> Result<RenameReport> {
+    for name in [old_name, new_name] {
+        if is_root_name(project_root, name) {
+            return Err(messages::WORKTREE_RESERVED.error(&[("name", name)]));
+        }
+    }
+    let old_path = managed_path(project_root, old_name);
+    if !old_path.exists() {
+        return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", old_name)]));
+    }
+    if name_taken(project_root, new_name) {
+        return Err(messages::WORKTREE_EXISTS.error(&[("name", new_name)]));
     }
 
-    link_files(project_root, &worktree_path, config)?;
+    let vcs = vcs::open(project_root);
+    let branch = if rename_branch {
+        let branch = find_worktree(project_root, old_name)?.branch;
+        if branch.is_empty() {
+            anyhow::bail!("Worktree '{}' has no branch to rename", old_name);
+        }
+        if branch == config.main_branch {
+            anyhow::bail!("Not renaming the main branch '{}'", branch);
+        }
+        if vcs.branch_exists(new_name)? {
+            anyhow::bail!("Branch '{}' already exists", new_name);
+        }
+        Some(branch)
+    } else {
+        None
+    };
 
-    Ok(())
-}
+    let mut metadata = Metadata::load(project_root)?;
+    let mut meta = metadata.worktrees.remove(old_name).unwrap_or_default();
+    // Worktrees outside the trees dir stay next to the main checkout
+    let new_path = match &meta.path {
+        Some(_) => get_sibling_dir(project_root, new_name),
+        None => get_trees_dir(project_root).join(new_name),
+    };
+    vcs.move_worktree(&old_path, &new_path)?;
+    if meta.path.is_some() {
+        meta.path = Some(new_path.clone());
+    }
+    // Saved before the branch is renamed, so that a failure there leaves the moved
+    // worktree managed under its new name
+    metadata.worktrees.insert(new_name.to_string(), meta);
+    metadata.record_operation(new_name, "rename");
+    metadata.save(project_root)?;
+
+    let branch = match branch {
+        Some(branch) => {
+            vcs.rename_branch(&branch, new_name).with_context(|| {
+                format!(
+                    "Moved worktree '{}' to '{}', but failed to rename its branch",
+                    old_name, new_name
+                )
+            })?;
+            metadata.entry(new_name).branch = Some(new_name.to_string());
+            metadata.save(project_root)?;
+            Some((branch, new_name.to_string()))
+        }
+        None => None,
+    };
 
-fn list_git_worktrees(project_root: &Path) -> Result<Vec<GitWorktree>> {
-    let output = Command::new("git")
-        .args(["worktree", "list", "--porcelain"])
-        .current_dir(project_root)
-        .output()
-        .context("Failed to run git worktree list")?;
+    // Templates and copies may depend on the worktree's name and path
+    let links = link_files(project_root, new_name, &new_path, config, false);
+    store::gc(&get_store_dir(project_root))?;
 
-    if !output.status.success() {
-        anyhow::bail!(
-            "git worktree list failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
+    Ok(RenameReport {
+        path: new_path,
+        branch,
+        links,
+    })
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut worktrees = Vec::new();
-    let mut current_path: Option<PathBuf> = None;
-    let mut current_branch: Option<String> = None;
-
-    for line in stdout.lines() {
-        if line.starts_with("worktree ") {
-            if let Some(path) = current_path.take() {
-                worktrees.push(GitWorktree {
-                    path,
-                    branch: current_branch.take().unwrap_or_default(),
-                });
-            }
-            current_path = Some(PathBuf::from(line.strip_prefix("worktree ").unwrap()));
-            current_branch = None;
-        } else if line.starts_with("branch ") {
-            current_branch = Some(
-                line.strip_prefix("branch refs/heads/")
-                    .unwrap_or(line.strip_prefix("branch ").unwrap())
-                    .to_string(),
-            );
+/// Destinations in a worktree that relinking would replace.
+pub fn existing_link_destinations(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+) -> Result<Vec<PathBuf>> {
+    let (name, worktree_path) = (worktree.name.as_str(), &worktree.path);
+    let context = LinkContext::new(project_root, config).for_worktree(name, worktree_path);
+    let mut destinations = Vec::new();
+    for entry in &config.files {
+        let src = entry.source(project_root);
+        let dst = worktree_path.join(&entry.path);
+        // Plugins decide themselves what to do with existing files
+        if src.exists()
+            && entry.link_type.plugin().is_none()
+            && dst.symlink_metadata().is_ok()
+            && !is_linked(&src, &dst, entry, &context)
+        {
+            destinations.push(dst);
         }
     }
+    Ok(destinations)
+}
 
-    if let Some(path) = current_path {
-        worktrees.push(GitWorktree {
-            path,
-            branch: current_branch.unwrap_or_default(),
-        });
+/// Link the configured files into the worktree again; with `refresh_secrets`, secrets
+/// are fetched again too.
+pub fn relink_worktree(
+    project_root: &Path,
+    worktree: &Worktree,
+    config: &Config,
+    refresh_secrets: bool,
+) -> Result<LinkReport> {
+    let (name, worktree_path) = (worktree.name.as_str(), &worktree.path);
+    if !worktree_path.exists() {
+        return Err(messages::WORKTREE_NOT_FOUND.error(&[("name", name)]));
     }
 
-    Ok(worktrees)
+    let report = link_files(project_root, name, worktree_path, config, refresh_secrets);
+    store::gc(&get_store_dir(project_root))?;
+
+    let mut metadata = Metadata::load(project_root)?;
+    metadata.record_operation(name, "relink");
+    metadata.save(project_root)?;
+
+    Ok(report)
 }
 
-fn unique_import_path(trees_dir: &Path, base_name: &str) -> PathBuf {
+/// Record where the worktree `name` lives when it is outside the trees dir, which is
+/// how epiphyte finds it again.
+fn record_location(project_root: &Path, name: &str, path: &Path) -> Result<()> {
+    if path.starts_with(get_trees_dir(project_root)) {
+        return Ok(());
+    }
+    let mut metadata = Metadata::load(project_root)?;
+    metadata.entry(name).path = Some(path.to_path_buf());
+    metadata.save(project_root)
+}
+
+fn unique_name(project_root: &Path, base_name: &str) -> String {
     let base = if base_name.is_empty() {
         "worktree"
     } else {
         base_name
     };
-
-    let mut candidate = trees_dir.join(base);
-    if !candidate.exists() {
-        return candidate;
+    if !name_taken(project_root, base) {
+        return base.to_string();
     }
 
     let mut index = 2;
     loop {
-        candidate = trees_dir.join(format!("{}-{}", base, index));
-        if !candidate.exists() {
+        let candidate = format!("{}-{}", base, index);
+        if !name_taken(project_root, &candidate) {
             return candidate;
         }
         index += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_paths_skips_original_paths_of_renames() {
+        let stdout = " M a.txt\0R  new name.txt\0old name.txt\0?? dir/b\0";
+        assert_eq!(status_paths(stdout), ["a.txt", "new name.txt", "dir/b"]);
+    }
+
+    #[test]
+    fn status_paths_of_clean_worktree() {
+        assert!(status_paths("").is_empty());
+    }
+}